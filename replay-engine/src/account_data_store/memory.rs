@@ -37,4 +37,13 @@ impl AccountDataStoreInnerTrait for MemoryAccountDataStore {
         }
         Ok(())
     }
+
+    fn traverse_sorted<F: FnMut(&String, &AccountData) -> Result<()>>(&self, mut callback: F) -> Result<()> {
+        let mut entries: Vec<(&String, &AccountData)> = self.data.iter().collect();
+        entries.sort_unstable_by_key(|(pubkey, _)| *pubkey);
+        for (pubkey, data) in entries {
+            callback(pubkey, data)?;
+        }
+        Ok(())
+    }
 }
@@ -9,6 +9,7 @@ trait AccountDataStoreInnerTrait {
   fn upsert(&mut self, pubkey: &String, data: &AccountData) -> Result<()>;
   fn delete(&mut self, pubkey: &String) -> Result<()>;
   fn traverse<F: FnMut(&String, &AccountData) -> Result<()>>(&self, callback: F) -> Result<()>;
+  fn traverse_sorted<F: FnMut(&String, &AccountData) -> Result<()>>(&self, callback: F) -> Result<()>;
 }
 
 enum AccountDataStoreInner {
@@ -60,4 +61,12 @@ impl AccountDataStore {
           AccountDataStoreInner::RocksDB(store) => store.traverse(callback),
       }
   }
+
+  // same as traverse, but in pubkey order (deterministic output)
+  pub fn traverse_sorted<F: FnMut(&String, &AccountData) -> Result<()>>(&self, callback: F) -> Result<()> {
+      match &self.inner {
+          AccountDataStoreInner::Memory(store) => store.traverse_sorted(callback),
+          AccountDataStoreInner::RocksDB(store) => store.traverse_sorted(callback),
+      }
+  }
 }
@@ -50,4 +50,9 @@ impl AccountDataStoreInnerTrait for RocksDBAccountDataStore {
         }
         Ok(())
     }
+
+    // keys are stored in bytewise order (same as the order of String)
+    fn traverse_sorted<F: FnMut(&String, &AccountData) -> Result<()>>(&self, callback: F) -> Result<()> {
+        self.traverse(callback)
+    }
 }
@@ -80,7 +80,7 @@ pub fn from_json(ix: &String, json: &String) -> Result<DecodedInstruction, Error
   fn from_str<'de, T>(json: &'de String) -> Result<T, ErrorCode>
   where T: de::Deserialize<'de>,
   {
-    serde_json::from_str(json).map_err(|_| ErrorCode::InvalidWhirlpoolInstructionJsonString)
+    serde_json::from_str(json).map_err(|e| ErrorCode::InvalidWhirlpoolInstructionJsonString(e.to_string()))
   }
 
   if ix.as_str() == "programDeploy" {
@@ -1260,6 +1260,40 @@ where
     serializer.serialize_str(&base64)
 }
 
+// base64 string (or null) to Option<Vec<u8>>
+pub fn deserialize_option_base64<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let base64: Option<String> = de::Deserialize::deserialize(deserializer)?;
+    match base64 {
+        Some(base64) => match BASE64_STANDARD.decode(base64).ok() {
+            Some(data) => Ok(Some(data)),
+            None => Err(de::Error::custom("expected base64 string")),
+        },
+        None => Ok(None),
+    }
+}
+
+// Option<Vec<u8>> to base64 string (or null)
+pub fn serialize_option_base64<S>(data: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match data {
+        Some(data) => serializer.serialize_some(&BASE64_STANDARD.encode(data)),
+        None => serializer.serialize_none(),
+    }
+}
+
+// u128 to string
+pub fn serialize_u128<S>(n: &u128, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&n.to_string())
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -1,13 +1,23 @@
+pub use solana_sdk::transaction::TransactionError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ErrorCode {
-  #[error("invalid whirlpool instruction json string")]
-  InvalidWhirlpoolInstructionJsonString,
+  #[error("invalid whirlpool instruction json string: {0}")]
+  InvalidWhirlpoolInstructionJsonString(String),
 
   #[error("unknown whirlpool instruction detected: {0}")]
   UnknownWhirlpoolInstruction(String),
 
-  #[error("instruction replay failed")]
-  InstructionReplayFailed,
+  #[error("instruction replay failed: {0}")]
+  InstructionReplayFailed(TransactionError),
+
+  #[error("account not found: {0}")]
+  AccountNotFound(String),
+
+  #[error("account deserialize failed: {0}")]
+  AccountDeserializeFailed(String),
+
+  #[error("account data store error: {0}")]
+  AccountDataStoreError(String),
 }
@@ -5,6 +5,8 @@ pub mod replay_engine;
 pub mod replay_environment;
 pub mod replay_instruction;
 pub mod account_data_store;
+pub mod transfer_verification;
+pub mod program_events;
 
 mod replay_instructions;
 mod util;
@@ -0,0 +1,218 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+use crate::pubkeys;
+use crate::replay_environment::RecordedInstruction;
+
+/*
+
+Whirlpool program events
+
+Newer Whirlpool programs emit Anchor events.
+- emit!: "Program data: <base64>" log of the whirlpool program
+- emit_cpi!: self-CPI to the whirlpool program (EVENT_IX_TAG + event data)
+
+The event data is discriminator (sha256("event:<Name>")[..8]) + borsh serialized fields.
+Unknown events are ignored.
+
+*/
+
+// anchor_lang::event::EVENT_IX_TAG (0x1d9acb512ea545e4) in little endian
+const EVENT_IX_TAG_LE: [u8; 8] = [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+const PROGRAM_DATA_LOG_PREFIX: &str = "Program data: ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramEvent {
+  PoolInitialized(PoolInitialized),
+  LiquidityIncreased(LiquidityChanged),
+  LiquidityDecreased(LiquidityChanged),
+  Traded(Traded),
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PoolInitialized {
+  pub whirlpool: Pubkey,
+  pub whirlpools_config: Pubkey,
+  pub token_mint_a: Pubkey,
+  pub token_mint_b: Pubkey,
+  pub tick_spacing: u16,
+  pub token_program_a: Pubkey,
+  pub token_program_b: Pubkey,
+  pub decimals_a: u8,
+  pub decimals_b: u8,
+  pub initial_sqrt_price: u128,
+}
+
+// LiquidityIncreased and LiquidityDecreased share the layout
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LiquidityChanged {
+  pub whirlpool: Pubkey,
+  pub position: Pubkey,
+  pub tick_lower_index: i32,
+  pub tick_upper_index: i32,
+  pub liquidity: u128,
+  pub token_a_amount: u64,
+  pub token_b_amount: u64,
+  pub token_a_transfer_fee: u64,
+  pub token_b_transfer_fee: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Traded {
+  pub whirlpool: Pubkey,
+  pub a_to_b: bool,
+  pub pre_sqrt_price: u128,
+  pub post_sqrt_price: u128,
+  pub input_amount: u64,
+  pub output_amount: u64,
+  pub input_transfer_fee: u64,
+  pub output_transfer_fee: u64,
+  pub lp_fee: u64,
+  pub protocol_fee: u64,
+}
+
+impl ProgramEvent {
+  pub fn name(&self) -> &'static str {
+    match self {
+      ProgramEvent::PoolInitialized(_) => "PoolInitialized",
+      ProgramEvent::LiquidityIncreased(_) => "LiquidityIncreased",
+      ProgramEvent::LiquidityDecreased(_) => "LiquidityDecreased",
+      ProgramEvent::Traded(_) => "Traded",
+    }
+  }
+}
+
+// events emitted by the whirlpool program (logs first, then self-CPIs)
+pub fn decode_program_events(log_messages: &[String], inner_instructions: &[RecordedInstruction]) -> Vec<ProgramEvent> {
+  let whirlpool_program_id = pubkeys::ORCA_WHIRLPOOL_PROGRAM_ID.to_string();
+
+  let mut events = vec![];
+
+  // "Program data:" logs are attributed to the program at the top of the invocation stack
+  let mut invocation_stack: Vec<&str> = vec![];
+  for log_message in log_messages.iter() {
+    if let Some(data) = log_message.strip_prefix(PROGRAM_DATA_LOG_PREFIX) {
+      if invocation_stack.last() == Some(&whirlpool_program_id.as_str()) {
+        if let Some(event) = BASE64_STANDARD.decode(data).ok().and_then(|data| decode_program_event(&data)) {
+          events.push(event);
+        }
+      }
+    } else if let Some(invoke) = log_message.strip_prefix("Program ") {
+      // "Program <id> invoke [n]", "Program <id> success", "Program <id> failed: <reason>"
+      let mut tokens = invoke.split_whitespace();
+      match (tokens.next(), tokens.next()) {
+        (Some(program_id), Some("invoke")) => invocation_stack.push(program_id),
+        (Some(_), Some("success")) | (Some(_), Some("failed:")) => { invocation_stack.pop(); },
+        _ => {},
+      }
+    }
+  }
+
+  for inner in inner_instructions.iter() {
+    if inner.program_id != whirlpool_program_id || !inner.data.starts_with(&EVENT_IX_TAG_LE) {
+      continue;
+    }
+    if let Some(event) = decode_program_event(&inner.data[EVENT_IX_TAG_LE.len()..]) {
+      events.push(event);
+    }
+  }
+
+  events
+}
+
+// None if the discriminator is unknown (or the data is broken)
+pub fn decode_program_event(data: &[u8]) -> Option<ProgramEvent> {
+  if data.len() < 8 {
+    return None;
+  }
+  let (discriminator, mut payload) = data.split_at(8);
+
+  if discriminator == event_discriminator("PoolInitialized") {
+    PoolInitialized::deserialize(&mut payload).ok().map(ProgramEvent::PoolInitialized)
+  } else if discriminator == event_discriminator("LiquidityIncreased") {
+    LiquidityChanged::deserialize(&mut payload).ok().map(ProgramEvent::LiquidityIncreased)
+  } else if discriminator == event_discriminator("LiquidityDecreased") {
+    LiquidityChanged::deserialize(&mut payload).ok().map(ProgramEvent::LiquidityDecreased)
+  } else if discriminator == event_discriminator("Traded") {
+    Traded::deserialize(&mut payload).ok().map(ProgramEvent::Traded)
+  } else {
+    None
+  }
+}
+
+pub fn event_discriminator(event_name: &str) -> [u8; 8] {
+  let hash = anchor_lang::solana_program::hash::hash(format!("event:{}", event_name).as_bytes());
+  let mut discriminator = [0u8; 8];
+  discriminator.copy_from_slice(&hash.to_bytes()[..8]);
+  discriminator
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn traded() -> Traded {
+    Traded {
+      whirlpool: pubkeys::ORCA_WHIRLPOOL_PROGRAM_ID,
+      a_to_b: true,
+      pre_sqrt_price: 18446744073709551616,
+      post_sqrt_price: 18446744073709000000,
+      input_amount: 1_000_000,
+      output_amount: 999_000,
+      input_transfer_fee: 0,
+      output_transfer_fee: 0,
+      lp_fee: 2_700,
+      protocol_fee: 300,
+    }
+  }
+
+  fn event_data(event_name: &str, event: &impl AnchorSerialize) -> Vec<u8> {
+    let mut data = event_discriminator(event_name).to_vec();
+    data.extend(event.try_to_vec().unwrap());
+    data
+  }
+
+  #[test]
+  fn test_decode_program_events_from_logs() {
+    let whirlpool = pubkeys::ORCA_WHIRLPOOL_PROGRAM_ID.to_string();
+    let data = BASE64_STANDARD.encode(event_data("Traded", &traded()));
+    let log_messages = vec![
+      format!("Program {} invoke [1]", whirlpool),
+      "Program log: Instruction: Swap".to_string(),
+      format!("Program {} invoke [2]", pubkeys::SPL_TOKEN_PROGRAM_ID),
+      // emitted by other program
+      format!("Program data: {}", data),
+      format!("Program {} success", pubkeys::SPL_TOKEN_PROGRAM_ID),
+      format!("Program data: {}", data),
+      format!("Program {} consumed 40000 of 1400000 compute units", whirlpool),
+      format!("Program {} success", whirlpool),
+    ];
+
+    let events = decode_program_events(&log_messages, &[]);
+    assert_eq!(events, vec![ProgramEvent::Traded(traded())]);
+  }
+
+  #[test]
+  fn test_decode_program_events_from_self_cpi() {
+    let mut data = EVENT_IX_TAG_LE.to_vec();
+    data.extend(event_data("Traded", &traded()));
+    let inner_instructions = vec![
+      RecordedInstruction {
+        program_id: pubkeys::ORCA_WHIRLPOOL_PROGRAM_ID.to_string(),
+        accounts: vec![],
+        data,
+        stack_height: 2,
+      },
+    ];
+
+    let events = decode_program_events(&[], &inner_instructions);
+    assert_eq!(events, vec![ProgramEvent::Traded(traded())]);
+  }
+
+  #[test]
+  fn test_decode_program_event_unknown() {
+    assert_eq!(decode_program_event(&event_data("Unknown", &traded())), None);
+    assert_eq!(decode_program_event(&[0u8; 4]), None);
+  }
+}
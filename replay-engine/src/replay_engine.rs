@@ -1,5 +1,5 @@
 use crate::decoded_instructions::DecodedWhirlpoolInstruction;
-use crate::replay_environment::ReplayEnvironment;
+use crate::replay_environment::{ExecutionRecord, ReplayEnvironment};
 use crate::replay_instruction::{replay_whirlpool_instruction, ReplayInstructionResult};
 use crate::types::{ProgramData, Slot};
 use crate::programs;
@@ -7,6 +7,7 @@ use crate::errors::ErrorCode;
 use crate::util;
 use crate::pubkeys;
 use crate::account_data_store::AccountDataStore;
+use crate::transfer_verification;
 
 const MAX_EXECUTION_ON_REPLAY_ENVIRONMENT: u64 = 20_000;
 
@@ -18,6 +19,10 @@ pub struct ReplayEngine {
   // environment
   environment: ReplayEnvironment,
   replay_execution_counter: u64,
+  // strict mode
+  verify_token_transfers: bool,
+  // logs, compute units, inner instructions and return data
+  execution_recording: bool,
 }
 
 impl ReplayEngine {
@@ -34,9 +39,17 @@ impl ReplayEngine {
       accounts,
       environment,
       replay_execution_counter,
+      verify_token_transfers: false,
+      execution_recording: false,
     };
   }
 
+  fn rebuild_environment(&mut self) {
+    self.environment = ReplayEngine::build_environment(self.slot.block_time, &self.program_data);
+    self.environment.set_execution_recording(self.execution_recording);
+    self.replay_execution_counter = 0u64;
+  }
+
   fn build_environment(block_time: i64, program_data: &ProgramData) -> ReplayEnvironment {
     // The environment should be rebuilt periodically to avoid processing too many transactions in a single environment.
     // Since Solana is capable of handling 50,000 TPS, it should theoretically be able to safely handle 20,000 txs per bank, haha.
@@ -80,6 +93,11 @@ impl ReplayEngine {
     return &self.accounts;
   }
 
+  // direct modification (e.g. removing accounts not needed for filtered replay)
+  pub fn get_accounts_mut(&mut self) -> &mut AccountDataStore {
+    return &mut self.accounts;
+  }
+
   pub fn update_slot(&mut self, slot: u64, block_height: u64, block_time: i64) {
     self.slot = Slot { slot, block_height, block_time };
     self.environment.set_sysvar_clock_unix_timestamp(self.slot.block_time);
@@ -87,17 +105,39 @@ impl ReplayEngine {
 
   pub fn update_program_data(&mut self, program_data: Vec<u8>) {
     self.program_data = program_data;
-    self.environment = ReplayEngine::build_environment(self.slot.block_time, &self.program_data);
-    self.replay_execution_counter = 0u64;
+    self.rebuild_environment();
+  }
+
+  // compare the token amounts moved by the replayed instruction with the recorded ones (see ReplayInstructionResult)
+  pub fn set_token_transfer_verification(&mut self, enabled: bool) {
+    self.verify_token_transfers = enabled;
+  }
+
+  // record logs, compute units, inner instructions and return data of each replayed instruction (slower)
+  pub fn set_execution_recording(&mut self, enabled: bool) {
+    self.execution_recording = enabled;
+    self.environment.set_execution_recording(enabled);
+  }
+
+  // available even if the replay failed (useful to read the logs of the failed instruction)
+  pub fn get_last_execution_record(&self) -> Option<&ExecutionRecord> {
+    self.environment.get_last_execution_record()
   }
 
   pub fn replay_instruction(&mut self, ix: &DecodedWhirlpoolInstruction) -> Result<ReplayInstructionResult, ErrorCode> {
     // rebuild periodically to avoid processing too many transactions in a single environment
     if self.replay_execution_counter >= MAX_EXECUTION_ON_REPLAY_ENVIRONMENT {
-      self.environment = ReplayEngine::build_environment(self.slot.block_time, &self.program_data);
-      self.replay_execution_counter = 0u64;
+      self.rebuild_environment();
     }
 
+    let expected_transfers = if self.verify_token_transfers {
+      transfer_verification::expected_token_transfers(ix)
+    } else {
+      vec![]
+    };
+    let token_accounts = transfer_verification::token_accounts(&expected_transfers);
+    self.environment.watch_token_amounts(token_accounts.iter().map(util::pubkey).collect());
+
     self.replay_execution_counter += 1;
     let result = replay_whirlpool_instruction(
       &mut self.environment,
@@ -106,16 +146,31 @@ impl ReplayEngine {
     );
 
     match result {
-      Ok(result) => {
-        if !result.execution_result.was_executed_successfully() {
-          return Err(ErrorCode::InstructionReplayFailed);
+      Ok(mut result) => {
+        result.execution_record = self.environment.get_last_execution_record().cloned();
+
+        if let Err(err) = result.execution_result.flattened_result() {
+          return Err(ErrorCode::InstructionReplayFailed(err));
+        }
+
+        if !expected_transfers.is_empty() {
+          let pre_amounts = self.environment.get_token_amounts_before_execution()
+            .iter()
+            .map(|(pubkey, amount)| (pubkey.to_string(), *amount))
+            .collect();
+          let post_amounts = token_accounts
+            .iter()
+            .filter_map(|pubkey| self.environment.get_token_amount(util::pubkey(pubkey)).map(|amount| (pubkey.clone(), amount)))
+            .collect();
+          // reported with the result, the replay goes on (the replayed state is the source of truth)
+          result.token_transfer_mismatches = transfer_verification::find_token_amount_mismatches(&expected_transfers, &pre_amounts, &post_amounts);
         }
 
         // write back
         util::update_accounts(
           &mut self.accounts,
           &result.snapshot,
-        ).unwrap();
+        ).map_err(|e| ErrorCode::AccountDataStoreError(e.to_string()))?;
 
         return Ok(result);
       },
@@ -1,5 +1,5 @@
 use std::{
-    collections::{/*HashMap,*/ HashSet},
+    collections::{HashMap, HashSet},
     //convert::TryInto,
     path::Path,
     sync::{atomic::AtomicBool, Arc},
@@ -46,6 +46,9 @@ use solana_transaction_status::{
 };
 */
 
+use crate::program_events::{decode_program_events, ProgramEvent};
+use crate::pubkeys;
+
 pub use bincode;
 pub use serde;
 pub use solana_client;
@@ -58,6 +61,41 @@ pub struct ReplayEnvironment {
     faucet: Keypair,
     config: GenesisConfig,
     nonce: u64,
+    // token accounts whose amount is recorded just before each transaction execution
+    watched_token_accounts: Vec<Pubkey>,
+    token_amounts_before_execution: HashMap<Pubkey, u64>,
+    // logs, inner instructions and return data of the last transaction (opt-in, it slows down the execution)
+    execution_recording: bool,
+    last_execution_record: Option<ExecutionRecord>,
+}
+
+// recorded details of the replay transaction (the whirlpool instruction + memo instruction for nonce)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionRecord {
+    pub log_messages: Vec<String>,
+    // units consumed by the whirlpool instruction (the whole transaction including the memo instruction if the log is not available)
+    pub compute_units_consumed: u64,
+    // CPIs from the whirlpool instruction
+    pub inner_instructions: Vec<RecordedInstruction>,
+    pub return_data: Option<RecordedReturnData>,
+    // Anchor events emitted by the whirlpool program (via logs or self-CPI)
+    // events are decoded from the recording, so they are available only if the execution recording is enabled
+    pub events: Vec<ProgramEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedInstruction {
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    pub data: Vec<u8>,
+    // 1 is the top level instruction (so CPIs from the whirlpool instruction start from 2)
+    pub stack_height: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedReturnData {
+    pub program_id: String,
+    pub data: Vec<u8>,
 }
 
 impl ReplayEnvironment {
@@ -169,6 +207,7 @@ impl ReplayEnvironment {
                 inner_instructions = details.inner_instructions;
                 log_messages = details.log_messages;
                 return_data = details.return_data;
+                // the whole transaction (the whirlpool instruction only is in ExecutionRecord)
                 compute_units_consumed = Some(details.executed_units);
             }
             TransactionExecutionResult::NotExecuted(err) => {
@@ -246,7 +285,12 @@ impl ReplayEnvironment {
     where
         VersionedTransaction: From<T>,
     {
-        let txs = vec![tx.into()];
+        self.token_amounts_before_execution = self.watched_token_accounts
+            .iter()
+            .filter_map(|pubkey| self.get_token_amount(*pubkey).map(|amount| (*pubkey, amount)))
+            .collect();
+
+        let txs: Vec<VersionedTransaction> = vec![tx.into()];
         let batch = self.bank.prepare_entry_batch(txs.clone()).unwrap();
         let (
             TransactionResults {
@@ -258,14 +302,29 @@ impl ReplayEnvironment {
             &batch,
             16usize,
             false, // collect_balances
-            false, // enable_cpi_recording
-            false, // enable_log_recording
-            false, // enable_return_data_recording
+            self.execution_recording, // enable_cpi_recording
+            self.execution_recording, // enable_log_recording
+            self.execution_recording, // enable_return_data_recording
             &mut ExecuteTimings::default(),
             None,
         );
 
-        execution_results.remove(0)
+        let execution_result = execution_results.remove(0);
+        self.last_execution_record = if self.execution_recording {
+            Some(build_execution_record(&txs[0], &execution_result))
+        } else {
+            None
+        };
+        execution_result
+    }
+
+    pub fn set_execution_recording(&mut self, enabled: bool) {
+        self.execution_recording = enabled;
+        self.last_execution_record = None;
+    }
+
+    pub fn get_last_execution_record(&self) -> Option<&ExecutionRecord> {
+        self.last_execution_record.as_ref()
     }
 
     pub fn get_latest_blockhash(&self) -> Hash {
@@ -292,6 +351,25 @@ impl ReplayEnvironment {
         self.bank.get_account(&pubkey).map(|acc| acc.into())
     }
 
+    // amount of Token and TokenExtensions account (both programs share the base layout)
+    pub fn get_token_amount(&self, pubkey: Pubkey) -> Option<u64> {
+        let account = self.get_account(pubkey)?;
+        if account.owner != spl_token::ID && account.owner != spl_token_2022::ID {
+            return None;
+        }
+        let amount = account.data.get(64..72)?;
+        Some(u64::from_le_bytes(amount.try_into().unwrap()))
+    }
+
+    pub fn watch_token_amounts(&mut self, pubkeys: Vec<Pubkey>) {
+        self.watched_token_accounts = pubkeys;
+        self.token_amounts_before_execution.clear();
+    }
+
+    pub fn get_token_amounts_before_execution(&self) -> &HashMap<Pubkey, u64> {
+        &self.token_amounts_before_execution
+    }
+
     pub fn set_account(&mut self, pubkey: Pubkey, account: &Account) -> &mut Self {
         self.bank.store_account(&pubkey, account);
         self
@@ -666,9 +744,86 @@ impl ReplayEnvironmentBuilder {
             faucet: self.faucet.insecure_clone(),
             config: self.config.clone(),
             nonce: 0,
+            watched_token_accounts: vec![],
+            token_amounts_before_execution: HashMap::new(),
+            execution_recording: false,
+            last_execution_record: None,
         };
         env.advance_blockhash();
 
         env
     }
 }
+
+fn build_execution_record(tx: &VersionedTransaction, execution_result: &TransactionExecutionResult) -> ExecutionRecord {
+    let details = match execution_result {
+        TransactionExecutionResult::Executed { details, .. } => details,
+        // not executed (e.g. failed to load accounts)
+        TransactionExecutionResult::NotExecuted(_) => {
+            return ExecutionRecord {
+                log_messages: vec![],
+                compute_units_consumed: 0,
+                inner_instructions: vec![],
+                return_data: None,
+                events: vec![],
+            };
+        }
+    };
+
+    let account_keys = tx.message.static_account_keys();
+    let key_at = |index: u8| account_keys.get(index as usize).map_or_else(String::new, |pubkey| pubkey.to_string());
+
+    // the whirlpool instruction is the first instruction of the replay transaction
+    let inner_instructions = details.inner_instructions
+        .as_ref()
+        .and_then(|inner_instructions| inner_instructions.first())
+        .map(|instructions| instructions
+            .iter()
+            .map(|inner| RecordedInstruction {
+                program_id: key_at(inner.instruction.program_id_index),
+                accounts: inner.instruction.accounts.iter().map(|index| key_at(*index)).collect(),
+                data: inner.instruction.data.clone(),
+                stack_height: inner.stack_height,
+            })
+            .collect())
+        .unwrap_or_default();
+
+    let log_messages = details.log_messages.clone().unwrap_or_default();
+    let events = decode_program_events(&log_messages, &inner_instructions);
+    let compute_units_consumed = whirlpool_instruction_compute_units(&log_messages).unwrap_or(details.executed_units);
+
+    ExecutionRecord {
+        log_messages,
+        compute_units_consumed,
+        inner_instructions,
+        return_data: details.return_data.as_ref().map(|return_data| RecordedReturnData {
+            program_id: return_data.program_id.to_string(),
+            data: return_data.data.clone(),
+        }),
+        events,
+    }
+}
+
+// "Program <whirlpool program> consumed N of M compute units" at depth 1 (self-CPIs for events are at depth 2)
+// None if the line is not found (e.g. the log is truncated)
+pub(crate) fn whirlpool_instruction_compute_units(log_messages: &[String]) -> Option<u64> {
+    let whirlpool_program_id = pubkeys::ORCA_WHIRLPOOL_PROGRAM_ID.to_string();
+
+    let mut depth = 0usize;
+    for log_message in log_messages.iter() {
+        let Some(message) = log_message.strip_prefix("Program ") else {
+            continue;
+        };
+        // "Program <id> invoke [n]", "Program <id> consumed N of M compute units", "Program <id> success", "Program <id> failed: <reason>"
+        let mut tokens = message.split_whitespace();
+        match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some(_), Some("invoke"), _) => depth += 1,
+            (Some(_), Some("success"), _) | (Some(_), Some("failed:"), _) => depth = depth.saturating_sub(1),
+            (Some(program_id), Some("consumed"), Some(units)) if depth == 1 && program_id == whirlpool_program_id => {
+                return units.parse().ok();
+            }
+            _ => {}
+        }
+    }
+    None
+}
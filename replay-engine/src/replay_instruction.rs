@@ -14,14 +14,19 @@ use anchor_lang::{InstructionData, ToAccountMetas, AnchorSerialize};
 
 use crate::replay_instructions;
 use crate::replay_environment;
-use crate::replay_environment::ReplayEnvironment;
+use crate::replay_environment::{ExecutionRecord, ReplayEnvironment};
 
 use crate::pubkeys;
+use crate::transfer_verification::TokenAmountMismatch;
 
 #[derive(Clone)]
 pub struct ReplayInstructionResult {
   pub execution_result: TransactionExecutionResult,
   pub snapshot: WritableAccountSnapshot,
+  // None if the execution recording is disabled
+  pub execution_record: Option<ExecutionRecord>,
+  // empty if the token transfer verification is disabled or the amounts matched
+  pub token_transfer_mismatches: Vec<TokenAmountMismatch>,
 }
 
 pub struct ReplayInstructionParams<'info, T> {
@@ -37,80 +42,80 @@ pub fn replay_whirlpool_instruction(
 ) -> Result<ReplayInstructionResult, ErrorCode> {
   match instruction {
     // major instructions
-    DecodedWhirlpoolInstruction::Swap(decoded) => Ok(replay_instructions::swap::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::TwoHopSwap(decoded) => Ok(replay_instructions::two_hop_swap::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::UpdateFeesAndRewards(decoded) => Ok(replay_instructions::update_fees_and_rewards::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::CollectFees(decoded) => Ok(replay_instructions::collect_fees::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::CollectReward(decoded) => Ok(replay_instructions::collect_reward::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::CollectProtocolFees(decoded) => Ok(replay_instructions::collect_protocol_fees::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::IncreaseLiquidity(decoded) => Ok(replay_instructions::increase_liquidity::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::DecreaseLiquidity(decoded) => Ok(replay_instructions::decrease_liquidity::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::OpenPosition(decoded) => Ok(replay_instructions::open_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::OpenPositionWithMetadata(decoded) => Ok(replay_instructions::open_position_with_metadata::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::ClosePosition(decoded) => Ok(replay_instructions::close_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::OpenBundledPosition(decoded) => Ok(replay_instructions::open_bundled_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::CloseBundledPosition(decoded) => Ok(replay_instructions::close_bundled_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializeTickArray(decoded) => Ok(replay_instructions::initialize_tick_array::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::Swap(decoded) => replay_instructions::swap::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::TwoHopSwap(decoded) => replay_instructions::two_hop_swap::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::UpdateFeesAndRewards(decoded) => replay_instructions::update_fees_and_rewards::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::CollectFees(decoded) => replay_instructions::collect_fees::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::CollectReward(decoded) => replay_instructions::collect_reward::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::CollectProtocolFees(decoded) => replay_instructions::collect_protocol_fees::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::IncreaseLiquidity(decoded) => replay_instructions::increase_liquidity::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::DecreaseLiquidity(decoded) => replay_instructions::decrease_liquidity::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::OpenPosition(decoded) => replay_instructions::open_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::OpenPositionWithMetadata(decoded) => replay_instructions::open_position_with_metadata::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::ClosePosition(decoded) => replay_instructions::close_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::OpenBundledPosition(decoded) => replay_instructions::open_bundled_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::CloseBundledPosition(decoded) => replay_instructions::close_bundled_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializeTickArray(decoded) => replay_instructions::initialize_tick_array::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // minor instructions
-    DecodedWhirlpoolInstruction::InitializePool(decoded) => Ok(replay_instructions::initialize_pool::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializeReward(decoded) => Ok(replay_instructions::initialize_reward::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetRewardEmissions(decoded) => Ok(replay_instructions::set_reward_emissions::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializePositionBundle(decoded) => Ok(replay_instructions::initialize_position_bundle::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializePositionBundleWithMetadata(decoded) => Ok(replay_instructions::initialize_position_bundle_with_metadata::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::DeletePositionBundle(decoded) => Ok(replay_instructions::delete_position_bundle::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::InitializePool(decoded) => replay_instructions::initialize_pool::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializeReward(decoded) => replay_instructions::initialize_reward::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetRewardEmissions(decoded) => replay_instructions::set_reward_emissions::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializePositionBundle(decoded) => replay_instructions::initialize_position_bundle::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializePositionBundleWithMetadata(decoded) => replay_instructions::initialize_position_bundle_with_metadata::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::DeletePositionBundle(decoded) => replay_instructions::delete_position_bundle::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // very rare instructions
-    DecodedWhirlpoolInstruction::InitializeFeeTier(decoded) => Ok(replay_instructions::initialize_fee_tier::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetFeeRate(decoded) => Ok(replay_instructions::set_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializeConfig(decoded) => Ok(replay_instructions::initialize_config::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetCollectProtocolFeesAuthority(decoded) => Ok(replay_instructions::set_collect_protocol_fees_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetDefaultFeeRate(decoded) => Ok(replay_instructions::set_default_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetDefaultProtocolFeeRate(decoded) => Ok(replay_instructions::set_default_protocol_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetFeeAuthority(decoded) => Ok(replay_instructions::set_fee_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetProtocolFeeRate(decoded) => Ok(replay_instructions::set_protocol_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetRewardAuthority(decoded) => Ok(replay_instructions::set_reward_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetRewardAuthorityBySuperAuthority(decoded) => Ok(replay_instructions::set_reward_authority_by_super_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetRewardEmissionsSuperAuthority(decoded) => Ok(replay_instructions::set_reward_emissions_super_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::InitializeFeeTier(decoded) => replay_instructions::initialize_fee_tier::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetFeeRate(decoded) => replay_instructions::set_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializeConfig(decoded) => replay_instructions::initialize_config::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetCollectProtocolFeesAuthority(decoded) => replay_instructions::set_collect_protocol_fees_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetDefaultFeeRate(decoded) => replay_instructions::set_default_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetDefaultProtocolFeeRate(decoded) => replay_instructions::set_default_protocol_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetFeeAuthority(decoded) => replay_instructions::set_fee_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetProtocolFeeRate(decoded) => replay_instructions::set_protocol_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetRewardAuthority(decoded) => replay_instructions::set_reward_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetRewardAuthorityBySuperAuthority(decoded) => replay_instructions::set_reward_authority_by_super_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetRewardEmissionsSuperAuthority(decoded) => replay_instructions::set_reward_emissions_super_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // v2 instructions
-    DecodedWhirlpoolInstruction::CollectFeesV2(decoded) => Ok(replay_instructions::collect_fees_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::CollectProtocolFeesV2(decoded) => Ok(replay_instructions::collect_protocol_fees_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::CollectRewardV2(decoded) => Ok(replay_instructions::collect_reward_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::DecreaseLiquidityV2(decoded) => Ok(replay_instructions::decrease_liquidity_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::IncreaseLiquidityV2(decoded) => Ok(replay_instructions::increase_liquidity_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SwapV2(decoded) => Ok(replay_instructions::swap_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::TwoHopSwapV2(decoded) => Ok(replay_instructions::two_hop_swap_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializePoolV2(decoded) => Ok(replay_instructions::initialize_pool_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializeRewardV2(decoded) => Ok(replay_instructions::initialize_reward_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetRewardEmissionsV2(decoded) => Ok(replay_instructions::set_reward_emissions_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializeConfigExtension(decoded) => Ok(replay_instructions::initialize_config_extension::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializeTokenBadge(decoded) => Ok(replay_instructions::initialize_token_badge::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::DeleteTokenBadge(decoded) => Ok(replay_instructions::delete_token_badge::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetConfigExtensionAuthority(decoded) => Ok(replay_instructions::set_config_extension_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetTokenBadgeAuthority(decoded) => Ok(replay_instructions::set_token_badge_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::CollectFeesV2(decoded) => replay_instructions::collect_fees_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::CollectProtocolFeesV2(decoded) => replay_instructions::collect_protocol_fees_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::CollectRewardV2(decoded) => replay_instructions::collect_reward_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::DecreaseLiquidityV2(decoded) => replay_instructions::decrease_liquidity_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::IncreaseLiquidityV2(decoded) => replay_instructions::increase_liquidity_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SwapV2(decoded) => replay_instructions::swap_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::TwoHopSwapV2(decoded) => replay_instructions::two_hop_swap_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializePoolV2(decoded) => replay_instructions::initialize_pool_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializeRewardV2(decoded) => replay_instructions::initialize_reward_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetRewardEmissionsV2(decoded) => replay_instructions::set_reward_emissions_v2::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializeConfigExtension(decoded) => replay_instructions::initialize_config_extension::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializeTokenBadge(decoded) => replay_instructions::initialize_token_badge::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::DeleteTokenBadge(decoded) => replay_instructions::delete_token_badge::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetConfigExtensionAuthority(decoded) => replay_instructions::set_config_extension_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetTokenBadgeAuthority(decoded) => replay_instructions::set_token_badge_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // TokenExtensions based Position NFT instructions
-    DecodedWhirlpoolInstruction::OpenPositionWithTokenExtensions(decoded) => Ok(replay_instructions::open_position_with_token_extensions::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::ClosePositionWithTokenExtensions(decoded) => Ok(replay_instructions::close_position_with_token_extensions::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::OpenPositionWithTokenExtensions(decoded) => replay_instructions::open_position_with_token_extensions::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::ClosePositionWithTokenExtensions(decoded) => replay_instructions::close_position_with_token_extensions::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // Liquidity Lock
-    DecodedWhirlpoolInstruction::LockPosition(decoded) => Ok(replay_instructions::lock_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::LockPosition(decoded) => replay_instructions::lock_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // Reset Position Range
-    DecodedWhirlpoolInstruction::ResetPositionRange(decoded) => Ok(replay_instructions::reset_position_range::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::ResetPositionRange(decoded) => replay_instructions::reset_position_range::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // Transfer Locked Position
-    DecodedWhirlpoolInstruction::TransferLockedPosition(decoded) => Ok(replay_instructions::transfer_locked_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::TransferLockedPosition(decoded) => replay_instructions::transfer_locked_position::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // Adaptive Fee instructions
-    DecodedWhirlpoolInstruction::InitializeAdaptiveFeeTier(decoded) => Ok(replay_instructions::initialize_adaptive_fee_tier::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::InitializePoolWithAdaptiveFee(decoded) => Ok(replay_instructions::initialize_pool_with_adaptive_fee::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetInitializePoolAuthority(decoded) => Ok(replay_instructions::set_initialize_pool_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetDelegatedFeeAuthority(decoded) => Ok(replay_instructions::set_delegated_fee_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetDefaultBaseFeeRate(decoded) => Ok(replay_instructions::set_default_base_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetFeeRateByDelegatedFeeAuthority(decoded) => Ok(replay_instructions::set_fee_rate_by_delegated_fee_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetPresetAdaptiveFeeConstants(decoded) => Ok(replay_instructions::set_preset_adaptive_fee_constants::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::InitializeAdaptiveFeeTier(decoded) => replay_instructions::initialize_adaptive_fee_tier::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::InitializePoolWithAdaptiveFee(decoded) => replay_instructions::initialize_pool_with_adaptive_fee::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetInitializePoolAuthority(decoded) => replay_instructions::set_initialize_pool_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetDelegatedFeeAuthority(decoded) => replay_instructions::set_delegated_fee_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetDefaultBaseFeeRate(decoded) => replay_instructions::set_default_base_fee_rate::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetFeeRateByDelegatedFeeAuthority(decoded) => replay_instructions::set_fee_rate_by_delegated_fee_authority::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetPresetAdaptiveFeeConstants(decoded) => replay_instructions::set_preset_adaptive_fee_constants::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // Dynamic Tick Array
-    DecodedWhirlpoolInstruction::InitializeDynamicTickArray(decoded) => Ok(replay_instructions::initialize_dynamic_tick_array::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::InitializeDynamicTickArray(decoded) => replay_instructions::initialize_dynamic_tick_array::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // Non Transferable Position
-    DecodedWhirlpoolInstruction::SetConfigFeatureFlag(decoded) => Ok(replay_instructions::set_config_feature_flag::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::SetTokenBadgeAttribute(decoded) => Ok(replay_instructions::set_token_badge_attribute::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::SetConfigFeatureFlag(decoded) => replay_instructions::set_config_feature_flag::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::SetTokenBadgeAttribute(decoded) => replay_instructions::set_token_badge_attribute::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     // temporary patch instructions
-    DecodedWhirlpoolInstruction::AdminIncreaseLiquidity(decoded) => Ok(replay_instructions::admin_increase_liquidity::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
-    DecodedWhirlpoolInstruction::MigrateRepurposeRewardAuthoritySpace(decoded) => Ok(replay_instructions::migrate_repurpose_reward_authority_space::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts })),
+    DecodedWhirlpoolInstruction::AdminIncreaseLiquidity(decoded) => replay_instructions::admin_increase_liquidity::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
+    DecodedWhirlpoolInstruction::MigrateRepurposeRewardAuthoritySpace(decoded) => replay_instructions::migrate_repurpose_reward_authority_space::replay(ReplayInstructionParams { replayer, decoded_instruction: &decoded, accounts }),
     //_ => {
     //  Err(ErrorCode::UnknownWhirlpoolInstruction("not implemented yet".to_string()))
     //}
@@ -129,6 +134,8 @@ impl ReplayInstructionResult {
         pre_snapshot,
         post_snapshot,
       },
+      execution_record: None,
+      token_transfer_mismatches: vec![],
     }
   }
 }
@@ -1,5 +1,6 @@
 use crate::decoded_instructions;
 use crate::replay_instruction::{ReplayInstructionParams, ReplayInstructionResult};
+use crate::errors::ErrorCode;
 use crate::util::pubkey; // abbr
 
 use anchor_lang::{InstructionData, ToAccountMetas, Discriminator, AnchorSerialize};
@@ -29,7 +30,7 @@ impl ToAccountMetas for AdminIncreaseLiquidityInstructionAccounts {
   }
 }
 
-pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedAdminIncreaseLiquidity>) -> ReplayInstructionResult {
+pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedAdminIncreaseLiquidity>) -> Result<ReplayInstructionResult, ErrorCode> {
   let replayer = req.replayer;
   let ix = req.decoded_instruction;
   let accounts = req.accounts;
@@ -61,5 +62,5 @@ pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedAdminInc
     &ix.key_whirlpool,
   ]);
 
-  ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot)
+  Ok(ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot))
 }
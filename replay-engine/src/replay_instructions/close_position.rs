@@ -3,15 +3,16 @@ use whirlpool_base::instruction as whirlpool_ix_args;
 
 use crate::decoded_instructions;
 use crate::replay_instruction::{ReplayInstructionParams, ReplayInstructionResult};
+use crate::errors::ErrorCode;
 use crate::util;
 use crate::util::pubkey; // abbr
 
-pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedClosePosition>) -> ReplayInstructionResult {
+pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedClosePosition>) -> Result<ReplayInstructionResult, ErrorCode> {
   let replayer = req.replayer;
   let ix = req.decoded_instruction;
   let accounts = req.accounts;
 
-  let position_data = util::get_position_data(&ix.key_position, accounts);
+  let position_data = util::get_position_data(&ix.key_position, accounts)?;
   let position_mint = position_data.position_mint;
 
   // position_authority
@@ -58,5 +59,5 @@ pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedClosePos
     // closed
   ]);
 
-  ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot)
+  Ok(ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot))
 }
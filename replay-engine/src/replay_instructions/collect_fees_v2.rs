@@ -3,19 +3,20 @@ use whirlpool_base::instruction as whirlpool_ix_args;
 
 use crate::decoded_instructions;
 use crate::replay_instruction::{ReplayInstructionParams, ReplayInstructionResult};
+use crate::errors::ErrorCode;
 use crate::util;
 use crate::util::pubkey; // abbr
 
-pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedCollectFeesV2>) -> ReplayInstructionResult {
+pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedCollectFeesV2>) -> Result<ReplayInstructionResult, ErrorCode> {
   let replayer = req.replayer;
   let ix = req.decoded_instruction;
   let accounts = req.accounts;
 
-  let whirlpool_data = util::get_whirlpool_data(&ix.key_whirlpool, accounts);
+  let whirlpool_data = util::get_whirlpool_data(&ix.key_whirlpool, accounts)?;
   let mint_a = whirlpool_data.token_mint_a;
   let mint_b = whirlpool_data.token_mint_b;
 
-  let position_data = util::get_position_data(&ix.key_position, accounts);
+  let position_data = util::get_position_data(&ix.key_position, accounts)?;
   let position_mint = position_data.position_mint;
 
   let amount_a = ix.transfer_0.amount;
@@ -122,5 +123,5 @@ pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedCollectF
     &ix.key_position,
   ]);
 
-  ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot)
+  Ok(ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot))
 }
@@ -3,15 +3,16 @@ use whirlpool_base::instruction as whirlpool_ix_args;
 
 use crate::decoded_instructions;
 use crate::replay_instruction::{ReplayInstructionParams, ReplayInstructionResult};
+use crate::errors::ErrorCode;
 use crate::util;
 use crate::util::pubkey; // abbr
 
-pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedDeletePositionBundle>) -> ReplayInstructionResult {
+pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedDeletePositionBundle>) -> Result<ReplayInstructionResult, ErrorCode> {
   let replayer = req.replayer;
   let ix = req.decoded_instruction;
   let accounts = req.accounts;
 
-  let position_bundle_data = util::get_position_bundle_data(&ix.key_position_bundle, accounts);
+  let position_bundle_data = util::get_position_bundle_data(&ix.key_position_bundle, accounts)?;
   let position_bundle_mint = position_bundle_data.position_bundle_mint;
 
   // position_bundle
@@ -58,5 +59,5 @@ pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedDeletePo
     // closed
   ]);
 
-  ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot)
+  Ok(ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot))
 }
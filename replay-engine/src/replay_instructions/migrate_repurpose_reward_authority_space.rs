@@ -1,5 +1,6 @@
 use crate::decoded_instructions;
 use crate::replay_instruction::{ReplayInstructionParams, ReplayInstructionResult};
+use crate::errors::ErrorCode;
 use crate::util::pubkey; // abbr
 
 use anchor_lang::{InstructionData, ToAccountMetas, Discriminator, AnchorSerialize};
@@ -24,7 +25,7 @@ impl ToAccountMetas for MigrateRepurposeRewardAuthoritySpaceInstructionAccounts
   }
 }
 
-pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedMigrateRepurposeRewardAuthoritySpace>) -> ReplayInstructionResult {
+pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedMigrateRepurposeRewardAuthoritySpace>) -> Result<ReplayInstructionResult, ErrorCode> {
   let replayer = req.replayer;
   let ix = req.decoded_instruction;
   let accounts = req.accounts;
@@ -50,5 +51,5 @@ pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedMigrateR
     &ix.key_whirlpool,
   ]);
 
-  ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot)
+  Ok(ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot))
 }
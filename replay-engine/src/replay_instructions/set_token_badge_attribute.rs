@@ -4,9 +4,10 @@ use whirlpool_base::instruction as whirlpool_ix_args;
 use crate::decoded_instructions;
 use crate::decoded_instructions::TokenBadgeAttribute;
 use crate::replay_instruction::{ReplayInstructionParams, ReplayInstructionResult};
+use crate::errors::ErrorCode;
 use crate::util::pubkey; // abbr
 
-pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedSetTokenBadgeAttribute>) -> ReplayInstructionResult {
+pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedSetTokenBadgeAttribute>) -> Result<ReplayInstructionResult, ErrorCode> {
   let replayer = req.replayer;
   let ix = req.decoded_instruction;
   let accounts = req.accounts;
@@ -54,5 +55,5 @@ pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedSetToken
     &ix.key_token_badge,
   ]);
 
-  ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot)
+  Ok(ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot))
 }
@@ -3,16 +3,17 @@ use whirlpool_base::instruction as whirlpool_ix_args;
 
 use crate::decoded_instructions;
 use crate::replay_instruction::{ReplayInstructionParams, ReplayInstructionResult};
+use crate::errors::ErrorCode;
 use crate::util;
 use crate::util::pubkey; // abbr
 
-pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedTwoHopSwapV2>) -> ReplayInstructionResult {
+pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedTwoHopSwapV2>) -> Result<ReplayInstructionResult, ErrorCode> {
   let replayer = req.replayer;
   let ix = req.decoded_instruction;
   let accounts = req.accounts;
 
-  let whirlpool_one_data = util::get_whirlpool_data(&ix.key_whirlpool_one, accounts);
-  let whirlpool_two_data = util::get_whirlpool_data(&ix.key_whirlpool_two, accounts);
+  let whirlpool_one_data = util::get_whirlpool_data(&ix.key_whirlpool_one, accounts)?;
+  let whirlpool_two_data = util::get_whirlpool_data(&ix.key_whirlpool_two, accounts)?;
   let mint_one_a = whirlpool_one_data.token_mint_a;
   let mint_one_b = whirlpool_one_data.token_mint_b;
   let mint_two_a = whirlpool_two_data.token_mint_a;
@@ -236,5 +237,5 @@ pub fn replay(req: ReplayInstructionParams<decoded_instructions::DecodedTwoHopSw
     &writable_accounts,
   );
 
-  ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot)
+  Ok(ReplayInstructionResult::new(execution_result, pre_snapshot, post_snapshot))
 }
@@ -5,6 +5,8 @@ mod test_lock_position;
 mod test_transfer_locked_position;
 mod test_reset_position_range;
 mod test_dynamic_tick_array;
+mod test_execution_recording;
+mod test_account_data_store;
 
 mod test_utils;
 pub use test_utils::*;
@@ -0,0 +1,33 @@
+use crate::account_data_store::AccountDataStore;
+
+fn traverse_sorted_pubkeys(store: &AccountDataStore) -> Vec<String> {
+    let mut pubkeys = vec![];
+    store.traverse_sorted(|pubkey, _data| {
+        pubkeys.push(pubkey.clone());
+        Ok(())
+    }).unwrap();
+    pubkeys
+}
+
+#[test]
+fn test_traverse_sorted() {
+    let pubkeys = [
+        "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D",
+        "3Q4pMMf5e2wcqYMGZhjpDWEmWaAVAVzMxKJtsv2iMu1R",
+        "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6",
+        "C7HVbbKnAnuXfhZ87mefqWPXDo2cjrj35yYmq3HhV1D6",
+        "BsGwEuUqbfeUSDN4mmxhcGFhNYKypKH8NZjoQ7DQrFfC",
+    ];
+    let mut sorted: Vec<String> = pubkeys.iter().map(|pubkey| pubkey.to_string()).collect();
+    sorted.sort();
+
+    for mut store in [AccountDataStore::new_on_memory(), AccountDataStore::new_on_disk::<&str>(None)] {
+        for (i, pubkey) in pubkeys.iter().enumerate() {
+            store.upsert(&pubkey.to_string(), &vec![i as u8]).unwrap();
+        }
+        store.delete(&pubkeys[0].to_string()).unwrap();
+
+        let expected: Vec<String> = sorted.iter().filter(|pubkey| *pubkey != pubkeys[0]).cloned().collect();
+        assert_eq!(traverse_sorted_pubkeys(&store), expected);
+    }
+}
@@ -0,0 +1,79 @@
+use solana_accounts_db::transaction_results::TransactionExecutionResult;
+
+use super::{create_engine, ix, replay};
+use crate::replay_environment::whirlpool_instruction_compute_units;
+
+#[test]
+fn test_execution_recording() {
+    let mut engine = create_engine();
+
+    let initialize_config = ix(
+        "initializeConfig",
+        r#"{"dataDefaultProtocolFeeRate": 300, "dataFeeAuthority": "3Q4pMMf5e2wcqYMGZhjpDWEmWaAVAVzMxKJtsv2iMu1R", "dataCollectProtocolFeesAuthority": "EXz6jkK22aa6jqgkG4q3GXm5Ankb6gh8Dsk4VytWupf7", "dataRewardEmissionsSuperAuthority": "34H4AgZt4CpS4488bWNcXUZFqpbXrfCnS7ika2BUwY3p", "keyWhirlpoolsConfig": "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D", "keyFunder": "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6", "keySystemProgram": "11111111111111111111111111111111"}"#,
+    );
+    let initialize_fee_tier = ix(
+        "initializeFeeTier",
+        r#"{"dataTickSpacing": 64, "dataDefaultFeeRate": 3000, "keyWhirlpoolsConfig": "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D", "keyFeeTier": "C7HVbbKnAnuXfhZ87mefqWPXDo2cjrj35yYmq3HhV1D6", "keyFunder": "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6", "keyFeeAuthority": "3Q4pMMf5e2wcqYMGZhjpDWEmWaAVAVzMxKJtsv2iMu1R", "keySystemProgram": "11111111111111111111111111111111"}"#,
+    );
+    let initialize_pool_v2 = ix(
+        "initializePoolV2",
+        r#"{"dataTickSpacing": 64, "dataInitialSqrtPrice": "92233720368547758080", "keyWhirlpoolsConfig": "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D", "keyTokenMintA": "7Xws5FruPQGB3Jq9xj4Cc55rruVjY8mdzmmr6wpUHeqB", "keyTokenMintB": "7j8yxRszXTonjCS7LsnvLtitRSBCggPM2Bx9yzJjcT9y", "keyTokenBadgeA": "FpegWLV3Gj8ye8gP9Jc5VPWXm6q4cWCSHoc6g56KYEyv", "keyTokenBadgeB": "AKC42rsBPMDu2F4DfSjzj3SeB2CGMEdbhVTDVJjrxM9N", "keyFunder": "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6", "keyWhirlpool": "BsGwEuUqbfeUSDN4mmxhcGFhNYKypKH8NZjoQ7DQrFfC", "keyTokenVaultA": "FNiNQiXYgFhKcKuU16DuNDxZynVAmNG2DVs3ukXe1JeB", "keyTokenVaultB": "6tMEfTsiby8m1jh861Zb23aTQt65c8mjVvL6PgkjXmjh", "keyFeeTier": "C7HVbbKnAnuXfhZ87mefqWPXDo2cjrj35yYmq3HhV1D6", "keyTokenProgramA": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", "keyTokenProgramB": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", "keySystemProgram": "11111111111111111111111111111111", "keyRent": "SysvarRent111111111111111111111111111111111", "decimalsTokenMintA": 0, "decimalsTokenMintB": 0}"#,
+    );
+
+    // disabled by default
+    replay(&mut engine, &initialize_config);
+    assert!(engine.get_last_execution_record().is_none());
+
+    engine.set_execution_recording(true);
+
+    let result = engine.replay_instruction(&initialize_fee_tier).unwrap();
+    let record = result.execution_record.unwrap();
+    assert!(record.compute_units_consumed > 0);
+    // the memo instruction in the same transaction is not counted
+    let TransactionExecutionResult::Executed { details, .. } = &result.execution_result else {
+        panic!("not executed");
+    };
+    assert!(record.compute_units_consumed < details.executed_units);
+    assert!(record.log_messages.iter().any(|log| log.starts_with("Program Memo") && log.contains(" consumed ")));
+    assert!(record.log_messages.iter().any(|log| log == "Program log: Instruction: InitializeFeeTier"));
+
+    // vaults are initialized via CPI
+    let result = engine.replay_instruction(&initialize_pool_v2).unwrap();
+    let record = result.execution_record.unwrap();
+    assert!(record.inner_instructions.iter().any(|inner| inner.program_id == "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"));
+    assert!(record.inner_instructions.iter().all(|inner| inner.stack_height >= 2));
+    assert_eq!(engine.get_last_execution_record(), Some(&record));
+}
+
+#[test]
+fn test_whirlpool_instruction_compute_units() {
+    let logs = |lines: &[&str]| lines.iter().map(|line| line.to_string()).collect::<Vec<String>>();
+
+    // the self-CPI for the event (depth 2) and the memo instruction are not counted
+    let log_messages = logs(&[
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [1]",
+        "Program log: Instruction: Swap",
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [2]",
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc consumed 2000 of 150000 compute units",
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc success",
+        "Program log: success",
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc consumed 45000 of 200000 compute units",
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc success",
+        "Program Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo invoke [1]",
+        "Program Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo consumed 3000 of 155000 compute units",
+        "Program Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo success",
+    ]);
+    assert_eq!(whirlpool_instruction_compute_units(&log_messages), Some(45000));
+
+    // failed instruction
+    let log_messages = logs(&[
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [1]",
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc consumed 1200 of 200000 compute units",
+        "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc failed: custom program error: 0x1771",
+    ]);
+    assert_eq!(whirlpool_instruction_compute_units(&log_messages), Some(1200));
+
+    // truncated log
+    let log_messages = logs(&["Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [1]", "Log truncated"]);
+    assert_eq!(whirlpool_instruction_compute_units(&log_messages), None);
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::decoded_instructions::{DecodedWhirlpoolInstruction, TransferAmountWithTransferFeeConfig};
+
+// token transfer recorded in the original transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenTransfer {
+  pub source: String,
+  pub destination: String,
+  pub amount: u64,
+  // withheld at the destination (TokenExtensions TransferFee)
+  pub transfer_fee: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenAmountMismatch {
+  pub token_account: String,
+  pub expected_delta: i128,
+  pub actual_delta: i128,
+}
+
+impl fmt::Display for TokenAmountMismatch {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} (expected_delta={} actual_delta={})", self.token_account, self.expected_delta, self.actual_delta)
+  }
+}
+
+pub fn expected_token_transfers(ix: &DecodedWhirlpoolInstruction) -> Vec<TokenTransfer> {
+  match ix {
+    DecodedWhirlpoolInstruction::Swap(ix) => {
+      // deposit first, then withdrawal
+      if ix.data_a_to_b {
+        vec![
+          transfer(&ix.key_token_owner_account_a, &ix.key_vault_a, ix.transfer_amount_0),
+          transfer(&ix.key_vault_b, &ix.key_token_owner_account_b, ix.transfer_amount_1),
+        ]
+      } else {
+        vec![
+          transfer(&ix.key_token_owner_account_b, &ix.key_vault_b, ix.transfer_amount_0),
+          transfer(&ix.key_vault_a, &ix.key_token_owner_account_a, ix.transfer_amount_1),
+        ]
+      }
+    }
+    DecodedWhirlpoolInstruction::SwapV2(ix) => {
+      if ix.data_a_to_b {
+        vec![
+          transfer_v2(&ix.key_token_owner_account_a, &ix.key_vault_a, &ix.transfer_0),
+          transfer_v2(&ix.key_vault_b, &ix.key_token_owner_account_b, &ix.transfer_1),
+        ]
+      } else {
+        vec![
+          transfer_v2(&ix.key_token_owner_account_b, &ix.key_vault_b, &ix.transfer_0),
+          transfer_v2(&ix.key_vault_a, &ix.key_token_owner_account_a, &ix.transfer_1),
+        ]
+      }
+    }
+    DecodedWhirlpoolInstruction::TwoHopSwap(ix) => {
+      let (owner_input_one, vault_input_one, vault_output_one, owner_output_one) = if ix.data_a_to_b_one {
+        (&ix.key_token_owner_account_one_a, &ix.key_vault_one_a, &ix.key_vault_one_b, &ix.key_token_owner_account_one_b)
+      } else {
+        (&ix.key_token_owner_account_one_b, &ix.key_vault_one_b, &ix.key_vault_one_a, &ix.key_token_owner_account_one_a)
+      };
+      let (owner_input_two, vault_input_two, vault_output_two, owner_output_two) = if ix.data_a_to_b_two {
+        (&ix.key_token_owner_account_two_a, &ix.key_vault_two_a, &ix.key_vault_two_b, &ix.key_token_owner_account_two_b)
+      } else {
+        (&ix.key_token_owner_account_two_b, &ix.key_vault_two_b, &ix.key_vault_two_a, &ix.key_token_owner_account_two_a)
+      };
+      vec![
+        transfer(owner_input_one, vault_input_one, ix.transfer_amount_0),
+        transfer(vault_output_one, owner_output_one, ix.transfer_amount_1),
+        transfer(owner_input_two, vault_input_two, ix.transfer_amount_2),
+        transfer(vault_output_two, owner_output_two, ix.transfer_amount_3),
+      ]
+    }
+    DecodedWhirlpoolInstruction::TwoHopSwapV2(ix) => {
+      // the intermediate token is transferred from vault to vault directly
+      vec![
+        transfer_v2(&ix.key_token_owner_account_input, &ix.key_vault_one_input, &ix.transfer_0),
+        transfer_v2(&ix.key_vault_one_intermediate, &ix.key_vault_two_intermediate, &ix.transfer_1),
+        transfer_v2(&ix.key_vault_two_output, &ix.key_token_owner_account_output, &ix.transfer_2),
+      ]
+    }
+    DecodedWhirlpoolInstruction::IncreaseLiquidity(ix) => vec![
+      transfer(&ix.key_token_owner_account_a, &ix.key_token_vault_a, ix.transfer_amount_0),
+      transfer(&ix.key_token_owner_account_b, &ix.key_token_vault_b, ix.transfer_amount_1),
+    ],
+    DecodedWhirlpoolInstruction::IncreaseLiquidityV2(ix) => vec![
+      transfer_v2(&ix.key_token_owner_account_a, &ix.key_token_vault_a, &ix.transfer_0),
+      transfer_v2(&ix.key_token_owner_account_b, &ix.key_token_vault_b, &ix.transfer_1),
+    ],
+    DecodedWhirlpoolInstruction::DecreaseLiquidity(ix) => vec![
+      transfer(&ix.key_token_vault_a, &ix.key_token_owner_account_a, ix.transfer_amount_0),
+      transfer(&ix.key_token_vault_b, &ix.key_token_owner_account_b, ix.transfer_amount_1),
+    ],
+    DecodedWhirlpoolInstruction::DecreaseLiquidityV2(ix) => vec![
+      transfer_v2(&ix.key_token_vault_a, &ix.key_token_owner_account_a, &ix.transfer_0),
+      transfer_v2(&ix.key_token_vault_b, &ix.key_token_owner_account_b, &ix.transfer_1),
+    ],
+    DecodedWhirlpoolInstruction::CollectFees(ix) => vec![
+      transfer(&ix.key_token_vault_a, &ix.key_token_owner_account_a, ix.transfer_amount_0),
+      transfer(&ix.key_token_vault_b, &ix.key_token_owner_account_b, ix.transfer_amount_1),
+    ],
+    DecodedWhirlpoolInstruction::CollectFeesV2(ix) => vec![
+      transfer_v2(&ix.key_token_vault_a, &ix.key_token_owner_account_a, &ix.transfer_0),
+      transfer_v2(&ix.key_token_vault_b, &ix.key_token_owner_account_b, &ix.transfer_1),
+    ],
+    DecodedWhirlpoolInstruction::CollectReward(ix) => vec![
+      transfer(&ix.key_reward_vault, &ix.key_reward_owner_account, ix.transfer_amount_0),
+    ],
+    DecodedWhirlpoolInstruction::CollectRewardV2(ix) => vec![
+      transfer_v2(&ix.key_reward_vault, &ix.key_reward_owner_account, &ix.transfer_0),
+    ],
+    DecodedWhirlpoolInstruction::CollectProtocolFees(ix) => vec![
+      transfer(&ix.key_token_vault_a, &ix.key_token_destination_a, ix.transfer_amount_0),
+      transfer(&ix.key_token_vault_b, &ix.key_token_destination_b, ix.transfer_amount_1),
+    ],
+    DecodedWhirlpoolInstruction::CollectProtocolFeesV2(ix) => vec![
+      transfer_v2(&ix.key_token_vault_a, &ix.key_token_destination_a, &ix.transfer_0),
+      transfer_v2(&ix.key_token_vault_b, &ix.key_token_destination_b, &ix.transfer_1),
+    ],
+    // no token transfer (or only position NFT mint/burn)
+    _ => vec![],
+  }
+}
+
+pub fn token_accounts(transfers: &Vec<TokenTransfer>) -> Vec<String> {
+  let mut accounts = vec![];
+  for transfer in transfers {
+    for account in [&transfer.source, &transfer.destination] {
+      if !accounts.contains(account) {
+        accounts.push(account.clone());
+      }
+    }
+  }
+  accounts
+}
+
+// compare the net amount change of each token account with the recorded transfers
+// (the same account may appear in multiple transfers, e.g. two hop swap from SOL to SOL)
+pub fn find_token_amount_mismatches(
+  transfers: &Vec<TokenTransfer>,
+  pre_amounts: &HashMap<String, u64>,
+  post_amounts: &HashMap<String, u64>,
+) -> Vec<TokenAmountMismatch> {
+  let mut expected_deltas: HashMap<&String, i128> = HashMap::new();
+  for transfer in transfers {
+    *expected_deltas.entry(&transfer.source).or_insert(0) -= transfer.amount as i128;
+    *expected_deltas.entry(&transfer.destination).or_insert(0) += transfer.amount as i128 - transfer.transfer_fee as i128;
+  }
+
+  let mut mismatches = vec![];
+  for token_account in token_accounts(transfers) {
+    let expected_delta = expected_deltas[&token_account];
+    let pre_amount = pre_amounts.get(&token_account).copied().unwrap_or(0);
+    let post_amount = post_amounts.get(&token_account).copied().unwrap_or(0);
+    let actual_delta = post_amount as i128 - pre_amount as i128;
+    if expected_delta != actual_delta {
+      mismatches.push(TokenAmountMismatch {
+        token_account,
+        expected_delta,
+        actual_delta,
+      });
+    }
+  }
+  mismatches
+}
+
+fn transfer(source: &String, destination: &String, amount: u64) -> TokenTransfer {
+  TokenTransfer {
+    source: source.clone(),
+    destination: destination.clone(),
+    amount,
+    transfer_fee: 0,
+  }
+}
+
+fn transfer_v2(source: &String, destination: &String, transfer: &TransferAmountWithTransferFeeConfig) -> TokenTransfer {
+  TokenTransfer {
+    source: source.clone(),
+    destination: destination.clone(),
+    amount: transfer.amount,
+    transfer_fee: calculate_transfer_fee(transfer),
+  }
+}
+
+// same as TransferFee::calculate_fee of Token-2022 program (ceil, capped by maximum fee)
+fn calculate_transfer_fee(transfer: &TransferAmountWithTransferFeeConfig) -> u64 {
+  if !transfer.transfer_fee_config_opt || transfer.transfer_fee_config_bps == 0 || transfer.amount == 0 {
+    return 0;
+  }
+  let numerator = transfer.amount as u128 * transfer.transfer_fee_config_bps as u128;
+  let fee = (numerator + 10_000 - 1) / 10_000;
+  std::cmp::min(fee, transfer.transfer_fee_config_max as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn transfer_with_fee(amount: u64, bps: u16, max: u64) -> TransferAmountWithTransferFeeConfig {
+    TransferAmountWithTransferFeeConfig {
+      amount,
+      transfer_fee_config_opt: true,
+      transfer_fee_config_bps: bps,
+      transfer_fee_config_max: max,
+    }
+  }
+
+  #[test]
+  fn test_calculate_transfer_fee() {
+    assert_eq!(calculate_transfer_fee(&transfer_with_fee(10_000, 100, u64::MAX)), 100);
+    // rounded up
+    assert_eq!(calculate_transfer_fee(&transfer_with_fee(10_001, 100, u64::MAX)), 101);
+    // capped
+    assert_eq!(calculate_transfer_fee(&transfer_with_fee(10_000, 100, 50)), 50);
+    assert_eq!(calculate_transfer_fee(&transfer_with_fee(0, 100, u64::MAX)), 0);
+
+    let mut no_fee = transfer_with_fee(10_000, 100, u64::MAX);
+    no_fee.transfer_fee_config_opt = false;
+    assert_eq!(calculate_transfer_fee(&no_fee), 0);
+  }
+
+  #[test]
+  fn test_find_token_amount_mismatches() {
+    let owner = "owner".to_string();
+    let vault_a = "vault_a".to_string();
+    let vault_b = "vault_b".to_string();
+    let transfers = vec![
+      transfer(&owner, &vault_a, 1_000),
+      transfer_v2(&vault_b, &owner, &transfer_with_fee(500, 100, u64::MAX)),
+    ];
+
+    let pre_amounts = HashMap::from([
+      (owner.clone(), 1_000),
+      (vault_a.clone(), 0),
+      (vault_b.clone(), 500),
+    ]);
+
+    // owner: -1000 + (500 - 5)
+    let post_amounts = HashMap::from([
+      (owner.clone(), 495),
+      (vault_a.clone(), 1_000),
+      (vault_b.clone(), 0),
+    ]);
+    assert!(find_token_amount_mismatches(&transfers, &pre_amounts, &post_amounts).is_empty());
+
+    let post_amounts = HashMap::from([
+      (owner.clone(), 495),
+      (vault_a.clone(), 999),
+      (vault_b.clone(), 0),
+    ]);
+    let mismatches = find_token_amount_mismatches(&transfers, &pre_amounts, &post_amounts);
+    assert_eq!(mismatches, vec![TokenAmountMismatch {
+      token_account: vault_a.clone(),
+      expected_delta: 1_000,
+      actual_delta: 999,
+    }]);
+  }
+}
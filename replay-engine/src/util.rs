@@ -7,33 +7,44 @@ use whirlpool_base::state::{Position, PositionBundle, Whirlpool};
 use whirlpool_base::util::remaining_accounts_utils;
 
 use crate::account_data_store::AccountDataStore;
+use crate::errors::ErrorCode;
 use crate::decoded_instructions::{RemainingAccountsInfo, RemainingAccountsKeys, TransferAmountWithTransferFeeConfig};
 use crate::pubkeys::{ORCA_WHIRLPOOL_PROGRAM_ID, SPL_TOKEN_PROGRAM_ID, SPL_TOKEN_2022_PROGRAM_ID};
 use crate::replay_instruction::TokenTrait;
 use crate::types::WritableAccountSnapshot;
 
-pub fn get_whirlpool_data(pubkey_string: &String, accounts: &AccountDataStore) -> Whirlpool {
-    let data = accounts.get(pubkey_string).unwrap().unwrap();
+fn get_account_data(pubkey_string: &String, accounts: &AccountDataStore) -> std::result::Result<Vec<u8>, ErrorCode> {
+    let data = accounts.get(pubkey_string)
+        .map_err(|e| ErrorCode::AccountDataStoreError(e.to_string()))?
+        .ok_or(ErrorCode::AccountNotFound(pubkey_string.clone()))?;
+    return Ok(data);
+}
+
+pub fn get_whirlpool_data(pubkey_string: &String, accounts: &AccountDataStore) -> std::result::Result<Whirlpool, ErrorCode> {
+    let data = get_account_data(pubkey_string, accounts)?;
     let whirlpool_data =
-        whirlpool_base::state::Whirlpool::try_deserialize(&mut data.as_slice()).unwrap();
-    return whirlpool_data;
+        whirlpool_base::state::Whirlpool::try_deserialize(&mut data.as_slice())
+            .map_err(|e| ErrorCode::AccountDeserializeFailed(format!("{}: {}", pubkey_string, e)))?;
+    return Ok(whirlpool_data);
 }
 
-pub fn get_position_data(pubkey_string: &String, accounts: &AccountDataStore) -> Position {
-    let data = accounts.get(pubkey_string).unwrap().unwrap();
+pub fn get_position_data(pubkey_string: &String, accounts: &AccountDataStore) -> std::result::Result<Position, ErrorCode> {
+    let data = get_account_data(pubkey_string, accounts)?;
     let position_data =
-        whirlpool_base::state::Position::try_deserialize(&mut data.as_slice()).unwrap();
-    return position_data;
+        whirlpool_base::state::Position::try_deserialize(&mut data.as_slice())
+            .map_err(|e| ErrorCode::AccountDeserializeFailed(format!("{}: {}", pubkey_string, e)))?;
+    return Ok(position_data);
 }
 
 pub fn get_position_bundle_data(
     pubkey_string: &String,
     accounts: &AccountDataStore,
-) -> PositionBundle {
-    let data = accounts.get(pubkey_string).unwrap().unwrap();
+) -> std::result::Result<PositionBundle, ErrorCode> {
+    let data = get_account_data(pubkey_string, accounts)?;
     let position_bundle_data =
-        whirlpool_base::state::PositionBundle::try_deserialize(&mut data.as_slice()).unwrap();
-    return position_bundle_data;
+        whirlpool_base::state::PositionBundle::try_deserialize(&mut data.as_slice())
+            .map_err(|e| ErrorCode::AccountDeserializeFailed(format!("{}: {}", pubkey_string, e)))?;
+    return Ok(position_bundle_data);
 }
 
 pub fn get_remaining_accounts(
@@ -295,4 +306,19 @@ mod tests {
         assert_eq!(result[1], "h");
         assert_eq!(result[2], "i");
     }
+
+    #[test]
+    fn test_get_whirlpool_data_account_not_found() {
+        let accounts = crate::account_data_store::AccountDataStore::new_on_memory();
+        let result = super::get_whirlpool_data(&"missing".to_string(), &accounts);
+        assert!(matches!(result, Err(crate::errors::ErrorCode::AccountNotFound(pubkey)) if pubkey == "missing"));
+    }
+
+    #[test]
+    fn test_get_position_data_deserialize_failed() {
+        let mut accounts = crate::account_data_store::AccountDataStore::new_on_memory();
+        accounts.upsert(&"broken".to_string(), &vec![0u8; 16]).unwrap();
+        let result = super::get_position_data(&"broken".to_string(), &accounts);
+        assert!(matches!(result, Err(crate::errors::ErrorCode::AccountDeserializeFailed(_))));
+    }
 }
@@ -0,0 +1,278 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use replay_engine::types::{ProgramData, Slot};
+
+use crate::errors::ReplayError;
+use crate::io::{self, Compression, CompressedFileWriter};
+use crate::schema::WhirlpoolStateAccount;
+use crate::serde::{AccountDataStoreConfig, WhirlpoolCanonicalStateSerializer};
+
+/*
+
+Snapshot conversion
+
+Conversions between snapshot CSV files (see schema.rs) and state files.
+- snapshot CSV to state file (IDL accounts are skipped because state files don't have them)
+- state file to snapshot CSV (sorted by pubkey)
+- program data replacement of state file (accounts are sorted by pubkey)
+- full-columns snapshot CSV to compact snapshot CSV
+
+CSV files are streamed line by line, and state files are loaded into AccountDataStore (use the on-disk store for large states).
+The output is written into a temporary file next to it and renamed at the end, so conversions can run concurrently
+and the input file can be overwritten (e.g. program data replacement in place).
+The compression of the output is determined by the extension (.zst or .gz).
+
+*/
+
+// returns the number of accounts written
+pub fn convert_snapshot_csv_to_state_file(
+    csv_file_path: &String,
+    slot: &Slot,
+    program_data: &ProgramData,
+    state_file_path: &String,
+) -> Result<u64, ReplayError> {
+    let mut account_count = 0u64;
+    write_atomically(state_file_path, |writer| {
+        // same layout as WhirlpoolCanonicalStateSerializer (slot, blockHeight and blockTime precede accounts)
+        write!(
+            writer,
+            "{{\"slot\":{},\"blockHeight\":{},\"blockTime\":{},\"accounts\":[",
+            slot.slot, slot.block_height, slot.block_time,
+        )?;
+        io::traverse_whirlpool_snapshot_csv_file(csv_file_path, |pubkey, data| {
+            if io::is_whirlpool_idl_account(pubkey) {
+                return Ok(());
+            }
+            if account_count > 0 {
+                writer.write_all(b",")?;
+            }
+            let account = WhirlpoolStateAccount {
+                pubkey: pubkey.to_string(),
+                data: io::decode_snapshot_csv_data(pubkey, data)?,
+            };
+            serde_json::to_writer(&mut *writer, &account)?;
+            account_count += 1;
+            Ok(())
+        })?;
+        write!(writer, "],\"programData\":\"{}\"}}", BASE64_STANDARD.encode(program_data))?;
+        Ok(())
+    })?;
+    Ok(account_count)
+}
+
+// returns the number of accounts written
+pub fn convert_state_file_to_snapshot_csv(
+    state_file_path: &String,
+    csv_file_path: &String,
+    account_data_store_config: &AccountDataStoreConfig,
+) -> Result<u64, ReplayError> {
+    let state = io::load_from_local_whirlpool_state_file(state_file_path, account_data_store_config)?;
+
+    let mut account_count = 0u64;
+    write_atomically(csv_file_path, |writer| {
+        state.accounts
+            .traverse_sorted(|pubkey, data| {
+                writeln!(writer, "{},{}", pubkey, BASE64_STANDARD.encode(data))?;
+                account_count += 1;
+                Ok(())
+            })
+            .map_err(|e| ReplayError::AccountDataStore(e.to_string()))
+    })?;
+    Ok(account_count)
+}
+
+// output_file_path can be the same as state_file_path (the state file is loaded before writing)
+pub fn replace_program_data_in_state_file(
+    state_file_path: &String,
+    program_data: &ProgramData,
+    output_file_path: &String,
+    account_data_store_config: &AccountDataStoreConfig,
+) -> Result<(), ReplayError> {
+    let state = io::load_from_local_whirlpool_state_file(state_file_path, account_data_store_config)?;
+
+    write_atomically(output_file_path, |writer| {
+        // canonical, so replacing the same program data always produces the same bytes
+        let serializer = WhirlpoolCanonicalStateSerializer {
+            slot: state.slot,
+            block_height: state.block_height,
+            block_time: state.block_time,
+            program_data,
+            accounts: &state.accounts,
+        };
+        Ok(serde_json::to_writer(writer, &serializer)?)
+    })
+}
+
+// returns the number of accounts written
+pub fn convert_full_column_snapshot_csv_to_compact(
+    full_column_csv_file_path: &String,
+    compact_csv_file_path: &String,
+) -> Result<u64, ReplayError> {
+    let mut account_count = 0u64;
+    write_atomically(compact_csv_file_path, |writer| {
+        io::traverse_whirlpool_snapshot_csv_file(full_column_csv_file_path, |pubkey, data| {
+            writeln!(writer, "{},{}", pubkey, data)?;
+            account_count += 1;
+            Ok(())
+        })
+    })?;
+    Ok(account_count)
+}
+
+// the temporary file is removed if the conversion fails
+fn write_atomically<F>(file_path: &String, write: F) -> Result<(), ReplayError>
+where
+    F: FnOnce(&mut CompressedFileWriter) -> Result<(), ReplayError>,
+{
+    let temporary_file_path = temporary_file_path(file_path);
+    let written = CompressedFileWriter::create_with_compression(&temporary_file_path, Compression::from_path(file_path))
+        .and_then(|mut writer| {
+            write(&mut writer)?;
+            writer.finish()
+        });
+
+    match written {
+        Ok(()) => Ok(std::fs::rename(&temporary_file_path, file_path)?),
+        Err(err) => {
+            let _ = std::fs::remove_file(&temporary_file_path);
+            Err(err)
+        }
+    }
+}
+
+// unique in the process (counter) and among processes (pid)
+fn temporary_file_path(file_path: &String) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    format!("{}.{}-{}.tmp", file_path, std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::test_utils::pubkey;
+
+    const WHIRLPOOL_IDL_ACCOUNT: &str = "8if9aDeshh3iGLasCCzBGQyxAvU8Q4jUZGF3N5sVm8GE";
+
+    fn accounts() -> Vec<(String, Vec<u8>)> {
+        vec![(pubkey(3), vec![3; 10]), (pubkey(1), vec![1; 20]), (pubkey(2), vec![2; 1])]
+    }
+
+    fn save_csv_file(file_path: &String, full_columns: bool) {
+        let mut lines = vec![];
+        let idl_account = (WHIRLPOOL_IDL_ACCOUNT.to_string(), vec![0xff; 8]);
+        for (pubkey, data) in accounts().iter().chain(std::iter::once(&idl_account)) {
+            let data = BASE64_STANDARD.encode(data);
+            if full_columns {
+                // pubkey,owner,data length,lamports,write version,data
+                lines.push(format!("{},whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc,{},1000000,1,{}", pubkey, data.len(), data));
+            } else {
+                lines.push(format!("{},{}", pubkey, data));
+            }
+        }
+        let csv = lines.join("\n") + "\n";
+        io::save_to_compressed_file(file_path, |writer| Ok(writer.write_all(csv.as_bytes())?)).unwrap();
+    }
+
+    fn load_state_file(file_path: &String) -> (Slot, ProgramData, BTreeMap<String, Vec<u8>>) {
+        let state = io::load_from_local_whirlpool_state_file(file_path, &AccountDataStoreConfig::OnMemory).unwrap();
+        let mut accounts = BTreeMap::new();
+        state.accounts
+            .traverse(|pubkey, data| {
+                accounts.insert(pubkey.clone(), data.clone());
+                Ok(())
+            })
+            .unwrap();
+        (Slot::new(state.slot, state.block_height, state.block_time), state.program_data, accounts)
+    }
+
+    fn file_names(dir: &std::path::Path) -> Vec<String> {
+        let mut file_names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_str().unwrap().to_string())
+            .collect();
+        file_names.sort();
+        file_names
+    }
+
+    #[test]
+    fn test_convert_snapshot_csv_to_state_file() {
+        let slot = Slot::new(215135999, 195000000, 1694303999);
+        let program_data: ProgramData = vec![7; 32];
+        let expected_accounts: BTreeMap<String, Vec<u8>> = accounts().into_iter().collect();
+
+        for (csv_file_name, full_columns, state_file_name) in [
+            ("compact.csv.gz", false, "compact.json.gz"),
+            ("full.csv.zst", true, "full.json.zst"),
+        ] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let csv_file_path = tempdir.path().join(csv_file_name).to_str().unwrap().to_string();
+            let state_file_path = tempdir.path().join(state_file_name).to_str().unwrap().to_string();
+            save_csv_file(&csv_file_path, full_columns);
+
+            // the IDL account is skipped
+            let account_count = convert_snapshot_csv_to_state_file(&csv_file_path, &slot, &program_data, &state_file_path).unwrap();
+            assert_eq!(account_count, 3);
+
+            let (loaded_slot, loaded_program_data, loaded_accounts) = load_state_file(&state_file_path);
+            assert_eq!(loaded_slot, slot);
+            assert_eq!(loaded_program_data, program_data);
+            assert_eq!(loaded_accounts, expected_accounts);
+            assert_eq!(io::load_slot_from_local_whirlpool_state_file(&state_file_path).unwrap(), slot);
+
+            // the temporary file is renamed to the output
+            assert_eq!(file_names(tempdir.path()), vec![csv_file_name.to_string(), state_file_name.to_string()]);
+        }
+    }
+
+    #[test]
+    fn test_failed_conversion_keeps_output() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let csv_file_path = tempdir.path().join("broken.csv.gz").to_str().unwrap().to_string();
+        let state_file_path = tempdir.path().join("state.json.gz").to_str().unwrap().to_string();
+
+        let csv = format!("{},{}\n{},not base64!\n", pubkey(1), BASE64_STANDARD.encode([1u8; 4]), pubkey(2));
+        io::save_to_compressed_file(&csv_file_path, |writer| Ok(writer.write_all(csv.as_bytes())?)).unwrap();
+        std::fs::write(&state_file_path, b"previous").unwrap();
+
+        let slot = Slot::new(1, 1, 1);
+        assert!(convert_snapshot_csv_to_state_file(&csv_file_path, &slot, &vec![], &state_file_path).is_err());
+
+        // the existing output is not overwritten and the temporary file is removed
+        assert_eq!(std::fs::read(&state_file_path).unwrap(), b"previous");
+        assert_eq!(file_names(tempdir.path()), vec!["broken.csv.gz".to_string(), "state.json.gz".to_string()]);
+    }
+
+    #[test]
+    fn test_state_file_roundtrip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = |file_name: &str| tempdir.path().join(file_name).to_str().unwrap().to_string();
+        let slot = Slot::new(215135999, 195000000, 1694303999);
+        save_csv_file(&path("full.csv.gz"), true);
+        convert_snapshot_csv_to_state_file(&path("full.csv.gz"), &slot, &vec![7; 32], &path("state.json.gz")).unwrap();
+
+        // state file to compact CSV (sorted by pubkey) and compact CSV from full-columns CSV
+        let config = AccountDataStoreConfig::OnMemory;
+        assert_eq!(convert_state_file_to_snapshot_csv(&path("state.json.gz"), &path("state.csv.gz"), &config).unwrap(), 3);
+        assert_eq!(convert_full_column_snapshot_csv_to_compact(&path("full.csv.gz"), &path("compact.csv.gz")).unwrap(), 4);
+
+        let mut state_csv_pubkeys = vec![];
+        io::traverse_whirlpool_snapshot_csv_file(&path("state.csv.gz"), |pubkey, _data| {
+            state_csv_pubkeys.push(pubkey.to_string());
+            Ok(())
+        })
+        .unwrap();
+        let mut expected_pubkeys: Vec<String> = accounts().into_iter().map(|(pubkey, _)| pubkey).collect();
+        expected_pubkeys.sort();
+        assert_eq!(state_csv_pubkeys, expected_pubkeys);
+
+        // program data replacement in place
+        replace_program_data_in_state_file(&path("state.json.gz"), &vec![8; 16], &path("state.json.gz"), &config).unwrap();
+        let (loaded_slot, loaded_program_data, loaded_accounts) = load_state_file(&path("state.json.gz"));
+        assert_eq!(loaded_slot, slot);
+        assert_eq!(loaded_program_data, vec![8; 16]);
+        assert_eq!(loaded_accounts, accounts().into_iter().collect::<BTreeMap<String, Vec<u8>>>());
+    }
+}
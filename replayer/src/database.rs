@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use mysql::prelude::*;
+use mysql::*;
+
+use crate::errors::ReplayError;
+use crate::schema::{Transaction, TransactionInstruction, WhirlpoolTransaction};
+use crate::source::TransactionSource;
+
+pub const DEFAULT_PAGE_SIZE: u16 = 100;
+
+// txid = slot << 24 | index of the transaction in the block
+const TXID_SLOT_SHIFT: u32 = 24;
+const TXID_INDEX_MASK: u64 = (1 << TXID_SLOT_SHIFT) - 1;
+
+struct DatabaseSlot {
+    slot: u64,
+    block_height: u64,
+    block_time: i64,
+}
+
+struct DatabaseInstruction {
+    txid: u64,
+    order: u32,
+    name: String,
+    json: String,
+}
+
+// stream slots and instructions from the database written by the ingestion (sedimentology)
+// slots are fetched page by page (page_size slots at once) from the slots table and instruction views (vwixs*)
+//
+// program upgrades are NOT streamed: the instruction views have no programDeploy rows, so the program data of the state
+// is used for all slots. Start from a state after the last upgrade (or stop before the next one),
+// otherwise the replay diverges silently after the upgrade.
+pub struct MySqlTransactionSource {
+    conn: PooledConn,
+    instruction_views: Vec<String>,
+    // the last slot returned (or the slot of the state)
+    last_slot: u64,
+    page_size: u16,
+    buffer: VecDeque<WhirlpoolTransaction>,
+    exhausted: bool,
+}
+
+impl MySqlTransactionSource {
+    // slots after start_slot (exclusive) will be returned
+    pub fn new(database_url: &String, start_slot: u64, page_size: u16) -> Result<MySqlTransactionSource, ReplayError> {
+        let opts = Opts::from_url(database_url).map_err(database_error)?;
+        let pool = Pool::new(opts).map_err(database_error)?;
+        let mut conn = pool.get_conn().map_err(database_error)?;
+
+        let instruction_views = fetch_instruction_views(&mut conn)?;
+
+        Ok(MySqlTransactionSource {
+            conn,
+            instruction_views,
+            last_slot: start_slot,
+            page_size,
+            buffer: VecDeque::new(),
+            exhausted: false,
+        })
+    }
+
+    fn fetch_next_page(&mut self) -> Result<(), ReplayError> {
+        let slots = fetch_next_slots(&mut self.conn, self.last_slot, self.page_size)?;
+        if slots.is_empty() {
+            self.exhausted = true;
+            return Ok(());
+        }
+
+        let first_slot = slots.first().unwrap().slot;
+        let last_slot = slots.last().unwrap().slot;
+        let txid_start = first_slot << TXID_SLOT_SHIFT;
+        let txid_end = ((last_slot + 1) << TXID_SLOT_SHIFT) - 1;
+
+        let instructions = fetch_instructions(&mut self.conn, &self.instruction_views, txid_start, txid_end)?;
+        let signatures = fetch_signatures(&mut self.conn, txid_start, txid_end)?;
+
+        // txid -> instructions (ordered by txid)
+        let mut instructions_by_txid = BTreeMap::<u64, Vec<DatabaseInstruction>>::new();
+        for instruction in instructions {
+            instructions_by_txid.entry(instruction.txid).or_default().push(instruction);
+        }
+
+        let mut transactions_by_slot = HashMap::<u64, Vec<Transaction>>::new();
+        for (txid, mut instructions) in instructions_by_txid {
+            instructions.sort_by_key(|instruction| instruction.order);
+
+            let mut transaction_instructions = Vec::with_capacity(instructions.len());
+            for instruction in instructions {
+                let payload = serde_json::from_str(&instruction.json).map_err(|e| {
+                    ReplayError::Database(format!("invalid instruction json (txid={} name={}): {}", txid, instruction.name, e))
+                })?;
+                transaction_instructions.push(TransactionInstruction {
+                    name: instruction.name,
+                    payload,
+                });
+            }
+
+            let (signature, payer) = signatures.get(&txid).cloned().unwrap_or_default();
+            transactions_by_slot.entry(txid >> TXID_SLOT_SHIFT).or_default().push(Transaction {
+                index: (txid & TXID_INDEX_MASK) as u32,
+                signature,
+                payer,
+                // balances are not stored in the database
+                balances: vec![],
+                instructions: transaction_instructions,
+            });
+        }
+
+        for slot in slots {
+            self.buffer.push_back(WhirlpoolTransaction {
+                slot: slot.slot,
+                block_height: slot.block_height,
+                block_time: slot.block_time,
+                transactions: transactions_by_slot.remove(&slot.slot).unwrap_or_default(),
+            });
+        }
+        self.last_slot = last_slot;
+
+        Ok(())
+    }
+}
+
+impl TransactionSource for MySqlTransactionSource {
+    fn next_transaction(&mut self) -> Option<Result<WhirlpoolTransaction, ReplayError>> {
+        if self.buffer.is_empty() && !self.exhausted {
+            if let Err(err) = self.fetch_next_page() {
+                // stop streaming after the error
+                self.exhausted = true;
+                return Some(Err(err));
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+fn database_error(err: impl std::fmt::Display) -> ReplayError {
+    ReplayError::Database(err.to_string())
+}
+
+// instruction views (vwixsSwap, vwixsTwoHopSwapV2, ...) defined in the database
+fn fetch_instruction_views(conn: &mut PooledConn) -> Result<Vec<String>, ReplayError> {
+    let views: Vec<String> = conn.query(
+        "SELECT TABLE_NAME FROM information_schema.VIEWS WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME LIKE 'vwixs%' ORDER BY TABLE_NAME",
+    ).map_err(database_error)?;
+
+    if views.is_empty() {
+        return Err(ReplayError::Database("no instruction view (vwixs*) found".to_string()));
+    }
+    Ok(views)
+}
+
+fn fetch_next_slots(conn: &mut PooledConn, last_slot: u64, limit: u16) -> Result<Vec<DatabaseSlot>, ReplayError> {
+    conn.exec_map(
+        "SELECT slot, blockHeight, blockTime FROM slots WHERE slot > :s ORDER BY slot ASC LIMIT :l",
+        params! {
+            "s" => last_slot,
+            "l" => limit,
+        },
+        |(slot, block_height, block_time)| {
+            DatabaseSlot {
+                slot,
+                block_height,
+                block_time,
+            }
+        },
+    ).map_err(database_error)
+}
+
+fn fetch_instructions(
+    conn: &mut PooledConn,
+    instruction_views: &Vec<String>,
+    txid_start: u64,
+    txid_end: u64,
+) -> Result<Vec<DatabaseInstruction>, ReplayError> {
+    // Since select for UNION ALL view of these views was too slow, each view is selected with UNION ALL.
+    // no ORDER BY clause, sort at the client side
+    let query = instruction_views
+        .iter()
+        .map(|view| format!("SELECT * FROM {} WHERE txid BETWEEN :s AND :e", view))
+        .collect::<Vec<String>>()
+        .join(" UNION ALL ");
+
+    conn.exec_map(
+        query,
+        params! {
+            "s" => txid_start,
+            "e" => txid_end,
+        },
+        |(txid, order, name, json)| {
+            DatabaseInstruction {
+                txid,
+                order,
+                name,
+                json,
+            }
+        },
+    ).map_err(database_error)
+}
+
+// txid -> (signature, payer)
+fn fetch_signatures(conn: &mut PooledConn, txid_start: u64, txid_end: u64) -> Result<HashMap<u64, (String, String)>, ReplayError> {
+    let rows: Vec<(u64, String, String)> = conn.exec(
+        "SELECT txid, signature, payer FROM txs WHERE txid BETWEEN :s AND :e",
+        params! {
+            "s" => txid_start,
+            "e" => txid_end,
+        },
+    ).map_err(database_error)?;
+
+    Ok(rows.into_iter().map(|(txid, signature, payer)| (txid, (signature, payer))).collect())
+}
@@ -0,0 +1,674 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountDeserialize;
+use replay_engine::account_data_store::AccountDataStore;
+use serde_json::{json, Value};
+use whirlpool_base::state::{
+    AdaptiveFeeTier, FeeTier, LockConfig, Position, PositionBundle, TokenBadge, Whirlpool, WhirlpoolsConfig,
+    WhirlpoolsConfigExtension,
+};
+
+use crate::errors::ReplayError;
+
+/*
+
+Account decoder
+
+Whirlpool program owned accounts are decoded based on the Anchor discriminator (the first 8 bytes).
+Borsh accounts are deserialized with whirlpool_base types, and zero-copy accounts (TickArray, DynamicTickArray, Oracle)
+are read based on their layout.
+
+In JSON, pubkeys are base58 strings, u64 / u128 / i128 are strings (same as the transaction file).
+
+*/
+
+pub const TICK_ARRAY_SIZE: usize = 88;
+const TICK_DATA_LEN: usize = 16 * 7; // liquidity_net, liquidity_gross, fee_growth_outside_a/b, reward_growths_outside[3]
+const FIXED_TICK_LEN: usize = 1 + TICK_DATA_LEN; // initialized + data
+// discriminator(8) + start_tick_index(4) + ticks(113 * 88) + whirlpool(32)
+pub(crate) const FIXED_TICK_ARRAY_LEN: usize = 8 + 4 + FIXED_TICK_LEN * TICK_ARRAY_SIZE + 32;
+// discriminator(8) + start_tick_index(4) + whirlpool(32) + tick_bitmap(16)
+const DYNAMIC_TICK_ARRAY_HEADER_LEN: usize = 8 + 4 + 32 + 16;
+// discriminator(8) + whirlpool(32) + trade_enable_timestamp(8) + adaptive_fee_constants(34) + adaptive_fee_variables(28, without reserved)
+const ORACLE_MIN_LEN: usize = 8 + 32 + 8 + 34 + 28;
+
+pub enum DecodedAccount {
+    Whirlpool(Whirlpool),
+    Position(Position),
+    PositionBundle(PositionBundle),
+    TickArray(DecodedTickArray),
+    DynamicTickArray(DecodedTickArray),
+    FeeTier(FeeTier),
+    AdaptiveFeeTier(AdaptiveFeeTier),
+    Oracle(DecodedOracle),
+    WhirlpoolsConfig(WhirlpoolsConfig),
+    WhirlpoolsConfigExtension(WhirlpoolsConfigExtension),
+    TokenBadge(TokenBadge),
+    LockConfig(LockConfig),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedTickArray {
+    pub start_tick_index: i32,
+    pub whirlpool: Pubkey,
+    // DynamicTickArray only
+    pub tick_bitmap: Option<u128>,
+    pub ticks: Vec<DecodedTick>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DecodedTick {
+    pub initialized: bool,
+    pub liquidity_net: i128,
+    pub liquidity_gross: u128,
+    pub fee_growth_outside_a: u128,
+    pub fee_growth_outside_b: u128,
+    pub reward_growths_outside: [u128; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedOracle {
+    pub whirlpool: Pubkey,
+    pub trade_enable_timestamp: u64,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub adaptive_fee_control_factor: u32,
+    pub max_volatility_accumulator: u32,
+    pub tick_group_size: u16,
+    pub major_swap_threshold_ticks: u16,
+    pub last_reference_update_timestamp: u64,
+    pub last_major_swap_timestamp: u64,
+    pub volatility_reference: u32,
+    pub tick_group_index_reference: i32,
+    pub volatility_accumulator: u32,
+}
+
+type DecodeFn = fn(&[u8]) -> Result<DecodedAccount, String>;
+
+// decoders keyed by the Anchor discriminator
+pub struct AccountDecoderRegistry {
+    decoders: HashMap<[u8; 8], (&'static str, DecodeFn)>,
+}
+
+impl AccountDecoderRegistry {
+    pub fn new() -> AccountDecoderRegistry {
+        let decoders: [(&'static str, DecodeFn); 12] = [
+            ("Whirlpool", |data| borsh_account(data).map(DecodedAccount::Whirlpool)),
+            ("Position", |data| borsh_account(data).map(DecodedAccount::Position)),
+            ("PositionBundle", |data| borsh_account(data).map(DecodedAccount::PositionBundle)),
+            ("TickArray", |data| fixed_tick_array(data).map(DecodedAccount::TickArray)),
+            ("DynamicTickArray", |data| dynamic_tick_array(data).map(DecodedAccount::DynamicTickArray)),
+            ("FeeTier", |data| borsh_account(data).map(DecodedAccount::FeeTier)),
+            ("AdaptiveFeeTier", |data| borsh_account(data).map(DecodedAccount::AdaptiveFeeTier)),
+            ("Oracle", |data| oracle(data).map(DecodedAccount::Oracle)),
+            ("WhirlpoolsConfig", |data| borsh_account(data).map(DecodedAccount::WhirlpoolsConfig)),
+            ("WhirlpoolsConfigExtension", |data| borsh_account(data).map(DecodedAccount::WhirlpoolsConfigExtension)),
+            ("TokenBadge", |data| borsh_account(data).map(DecodedAccount::TokenBadge)),
+            ("LockConfig", |data| borsh_account(data).map(DecodedAccount::LockConfig)),
+        ];
+
+        AccountDecoderRegistry {
+            decoders: decoders
+                .into_iter()
+                .map(|(name, decode)| (account_discriminator(name), (name, decode)))
+                .collect(),
+        }
+    }
+
+    // account type name of the data (None if the discriminator is unknown)
+    pub fn account_type(&self, data: &[u8]) -> Option<&'static str> {
+        discriminator(data).and_then(|discriminator| self.decoders.get(&discriminator)).map(|(name, _)| *name)
+    }
+
+    // None if the discriminator is unknown, error if the data is broken
+    pub fn decode(&self, data: &[u8]) -> Result<Option<DecodedAccount>, ReplayError> {
+        let Some((name, decode)) = discriminator(data).and_then(|discriminator| self.decoders.get(&discriminator)) else {
+            return Ok(None);
+        };
+        decode(data)
+            .map(Some)
+            .map_err(|e| ReplayError::AccountDeserialize(format!("{}: {}", name, e)))
+    }
+}
+
+impl Default for AccountDecoderRegistry {
+    fn default() -> Self {
+        AccountDecoderRegistry::new()
+    }
+}
+
+// shared registry (discriminators are computed only once)
+pub fn registry() -> &'static AccountDecoderRegistry {
+    static REGISTRY: OnceLock<AccountDecoderRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(AccountDecoderRegistry::new)
+}
+
+pub fn decode_account_data(data: &[u8]) -> Result<Option<DecodedAccount>, ReplayError> {
+    registry().decode(data)
+}
+
+// None if the account doesn't exist or its discriminator is unknown
+pub fn decode_account(pubkey: &String, accounts: &AccountDataStore) -> Result<Option<DecodedAccount>, ReplayError> {
+    let data = accounts
+        .get(pubkey)
+        .map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+    match data {
+        Some(data) => decode_account_data(&data),
+        None => Ok(None),
+    }
+}
+
+impl DecodedAccount {
+    pub fn account_type(&self) -> &'static str {
+        match self {
+            DecodedAccount::Whirlpool(_) => "Whirlpool",
+            DecodedAccount::Position(_) => "Position",
+            DecodedAccount::PositionBundle(_) => "PositionBundle",
+            DecodedAccount::TickArray(_) => "TickArray",
+            DecodedAccount::DynamicTickArray(_) => "DynamicTickArray",
+            DecodedAccount::FeeTier(_) => "FeeTier",
+            DecodedAccount::AdaptiveFeeTier(_) => "AdaptiveFeeTier",
+            DecodedAccount::Oracle(_) => "Oracle",
+            DecodedAccount::WhirlpoolsConfig(_) => "WhirlpoolsConfig",
+            DecodedAccount::WhirlpoolsConfigExtension(_) => "WhirlpoolsConfigExtension",
+            DecodedAccount::TokenBadge(_) => "TokenBadge",
+            DecodedAccount::LockConfig(_) => "LockConfig",
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            DecodedAccount::Whirlpool(whirlpool) => json!({
+                "whirlpoolsConfig": whirlpool.whirlpools_config.to_string(),
+                "tickSpacing": whirlpool.tick_spacing,
+                "feeRate": whirlpool.fee_rate,
+                "protocolFeeRate": whirlpool.protocol_fee_rate,
+                "liquidity": whirlpool.liquidity.to_string(),
+                "sqrtPrice": whirlpool.sqrt_price.to_string(),
+                "tickCurrentIndex": whirlpool.tick_current_index,
+                "protocolFeeOwedA": whirlpool.protocol_fee_owed_a.to_string(),
+                "protocolFeeOwedB": whirlpool.protocol_fee_owed_b.to_string(),
+                "tokenMintA": whirlpool.token_mint_a.to_string(),
+                "tokenVaultA": whirlpool.token_vault_a.to_string(),
+                "feeGrowthGlobalA": whirlpool.fee_growth_global_a.to_string(),
+                "tokenMintB": whirlpool.token_mint_b.to_string(),
+                "tokenVaultB": whirlpool.token_vault_b.to_string(),
+                "feeGrowthGlobalB": whirlpool.fee_growth_global_b.to_string(),
+                "rewardLastUpdatedTimestamp": whirlpool.reward_last_updated_timestamp.to_string(),
+                "rewardInfos": whirlpool.reward_infos.iter().map(|reward_info| json!({
+                    "mint": reward_info.mint.to_string(),
+                    "vault": reward_info.vault.to_string(),
+                    "emissionsPerSecondX64": reward_info.emissions_per_second_x64.to_string(),
+                    "growthGlobalX64": reward_info.growth_global_x64.to_string(),
+                })).collect::<Vec<Value>>(),
+            }),
+            DecodedAccount::Position(position) => json!({
+                "whirlpool": position.whirlpool.to_string(),
+                "positionMint": position.position_mint.to_string(),
+                "liquidity": position.liquidity.to_string(),
+                "tickLowerIndex": position.tick_lower_index,
+                "tickUpperIndex": position.tick_upper_index,
+                "feeGrowthCheckpointA": position.fee_growth_checkpoint_a.to_string(),
+                "feeOwedA": position.fee_owed_a.to_string(),
+                "feeGrowthCheckpointB": position.fee_growth_checkpoint_b.to_string(),
+                "feeOwedB": position.fee_owed_b.to_string(),
+                "rewardInfos": position.reward_infos.iter().map(|reward_info| json!({
+                    "growthInsideCheckpoint": reward_info.growth_inside_checkpoint.to_string(),
+                    "amountOwed": reward_info.amount_owed.to_string(),
+                })).collect::<Vec<Value>>(),
+            }),
+            DecodedAccount::PositionBundle(position_bundle) => json!({
+                "positionBundleMint": position_bundle.position_bundle_mint.to_string(),
+                // bundle indexes of the opened positions
+                "openedBundleIndexes": (0..position_bundle.position_bitmap.len() * 8)
+                    .filter(|index| position_bundle.position_bitmap[index / 8] & (1 << (index % 8)) != 0)
+                    .collect::<Vec<usize>>(),
+            }),
+            DecodedAccount::TickArray(tick_array) | DecodedAccount::DynamicTickArray(tick_array) => {
+                let mut value = json!({
+                    "startTickIndex": tick_array.start_tick_index,
+                    "whirlpool": tick_array.whirlpool.to_string(),
+                    "ticks": tick_array.ticks.iter().map(|tick| json!({
+                        "initialized": tick.initialized,
+                        "liquidityNet": tick.liquidity_net.to_string(),
+                        "liquidityGross": tick.liquidity_gross.to_string(),
+                        "feeGrowthOutsideA": tick.fee_growth_outside_a.to_string(),
+                        "feeGrowthOutsideB": tick.fee_growth_outside_b.to_string(),
+                        "rewardGrowthsOutside": tick.reward_growths_outside.iter().map(|growth| growth.to_string()).collect::<Vec<String>>(),
+                    })).collect::<Vec<Value>>(),
+                });
+                if let Some(tick_bitmap) = tick_array.tick_bitmap {
+                    value["tickBitmap"] = json!(tick_bitmap.to_string());
+                }
+                value
+            }
+            DecodedAccount::FeeTier(fee_tier) => json!({
+                "whirlpoolsConfig": fee_tier.whirlpools_config.to_string(),
+                "tickSpacing": fee_tier.tick_spacing,
+                "defaultFeeRate": fee_tier.default_fee_rate,
+            }),
+            DecodedAccount::AdaptiveFeeTier(adaptive_fee_tier) => json!({
+                "whirlpoolsConfig": adaptive_fee_tier.whirlpools_config.to_string(),
+                "feeTierIndex": adaptive_fee_tier.fee_tier_index,
+                "tickSpacing": adaptive_fee_tier.tick_spacing,
+                "initializePoolAuthority": adaptive_fee_tier.initialize_pool_authority.to_string(),
+                "delegatedFeeAuthority": adaptive_fee_tier.delegated_fee_authority.to_string(),
+                "defaultBaseFeeRate": adaptive_fee_tier.default_base_fee_rate,
+                "filterPeriod": adaptive_fee_tier.filter_period,
+                "decayPeriod": adaptive_fee_tier.decay_period,
+                "reductionFactor": adaptive_fee_tier.reduction_factor,
+                "adaptiveFeeControlFactor": adaptive_fee_tier.adaptive_fee_control_factor,
+                "maxVolatilityAccumulator": adaptive_fee_tier.max_volatility_accumulator,
+                "tickGroupSize": adaptive_fee_tier.tick_group_size,
+                "majorSwapThresholdTicks": adaptive_fee_tier.major_swap_threshold_ticks,
+            }),
+            DecodedAccount::Oracle(oracle) => json!({
+                "whirlpool": oracle.whirlpool.to_string(),
+                "tradeEnableTimestamp": oracle.trade_enable_timestamp.to_string(),
+                "adaptiveFeeConstants": {
+                    "filterPeriod": oracle.filter_period,
+                    "decayPeriod": oracle.decay_period,
+                    "reductionFactor": oracle.reduction_factor,
+                    "adaptiveFeeControlFactor": oracle.adaptive_fee_control_factor,
+                    "maxVolatilityAccumulator": oracle.max_volatility_accumulator,
+                    "tickGroupSize": oracle.tick_group_size,
+                    "majorSwapThresholdTicks": oracle.major_swap_threshold_ticks,
+                },
+                "adaptiveFeeVariables": {
+                    "lastReferenceUpdateTimestamp": oracle.last_reference_update_timestamp.to_string(),
+                    "lastMajorSwapTimestamp": oracle.last_major_swap_timestamp.to_string(),
+                    "volatilityReference": oracle.volatility_reference,
+                    "tickGroupIndexReference": oracle.tick_group_index_reference,
+                    "volatilityAccumulator": oracle.volatility_accumulator,
+                },
+            }),
+            DecodedAccount::WhirlpoolsConfig(config) => json!({
+                "feeAuthority": config.fee_authority.to_string(),
+                "collectProtocolFeesAuthority": config.collect_protocol_fees_authority.to_string(),
+                "rewardEmissionsSuperAuthority": config.reward_emissions_super_authority.to_string(),
+                "defaultProtocolFeeRate": config.default_protocol_fee_rate,
+                "featureFlags": config.feature_flags,
+            }),
+            DecodedAccount::WhirlpoolsConfigExtension(extension) => json!({
+                "whirlpoolsConfig": extension.whirlpools_config.to_string(),
+                "configExtensionAuthority": extension.config_extension_authority.to_string(),
+                "tokenBadgeAuthority": extension.token_badge_authority.to_string(),
+            }),
+            DecodedAccount::TokenBadge(token_badge) => json!({
+                "whirlpoolsConfig": token_badge.whirlpools_config.to_string(),
+                "tokenMint": token_badge.token_mint.to_string(),
+                "attributeRequireNonTransferablePosition": token_badge.attribute_require_non_transferable_position,
+            }),
+            DecodedAccount::LockConfig(lock_config) => json!({
+                "position": lock_config.position.to_string(),
+                "positionOwner": lock_config.position_owner.to_string(),
+                "whirlpool": lock_config.whirlpool.to_string(),
+                "lockedTimestamp": lock_config.locked_timestamp.to_string(),
+                "lockType": format!("{:?}", lock_config.lock_type),
+            }),
+        }
+    }
+}
+
+// Anchor discriminator of the account type (the first 8 bytes of sha256("account:<name>"))
+pub(crate) fn account_discriminator(account_name: &str) -> [u8; 8] {
+    let hash = anchor_lang::solana_program::hash::hash(format!("account:{}", account_name).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash.to_bytes()[..8]);
+    discriminator
+}
+
+fn discriminator(data: &[u8]) -> Option<[u8; 8]> {
+    data.get(0..8).map(|bytes| bytes.try_into().unwrap())
+}
+
+fn borsh_account<T: AccountDeserialize>(data: &[u8]) -> Result<T, String> {
+    T::try_deserialize(&mut &data[..]).map_err(|e| e.to_string())
+}
+
+// TickArray is zero-copy account, so we read it based on its layout
+fn fixed_tick_array(data: &[u8]) -> Result<DecodedTickArray, String> {
+    if data.len() != FIXED_TICK_ARRAY_LEN {
+        return Err(format!("unexpected length: {}", data.len()));
+    }
+
+    let ticks = (0..TICK_ARRAY_SIZE)
+        .map(|i| {
+            let offset = 8 + 4 + FIXED_TICK_LEN * i;
+            DecodedTick {
+                initialized: data[offset] != 0,
+                ..tick_data(data, offset + 1)
+            }
+        })
+        .collect();
+
+    Ok(DecodedTickArray {
+        start_tick_index: read_i32(data, 8),
+        whirlpool: read_pubkey(data, 8 + 4 + FIXED_TICK_LEN * TICK_ARRAY_SIZE),
+        tick_bitmap: None,
+        ticks,
+    })
+}
+
+// only initialized ticks have their data (uninitialized ticks are 1 byte)
+fn dynamic_tick_array(data: &[u8]) -> Result<DecodedTickArray, String> {
+    if data.len() < DYNAMIC_TICK_ARRAY_HEADER_LEN {
+        return Err(format!("unexpected length: {}", data.len()));
+    }
+
+    let mut ticks = Vec::with_capacity(TICK_ARRAY_SIZE);
+    let mut offset = DYNAMIC_TICK_ARRAY_HEADER_LEN;
+    for _ in 0..TICK_ARRAY_SIZE {
+        if data.len() < offset + 1 {
+            return Err(format!("unexpected length: {}", data.len()));
+        }
+        let initialized = data[offset] != 0;
+        offset += 1;
+        if initialized {
+            if data.len() < offset + TICK_DATA_LEN {
+                return Err(format!("unexpected length: {}", data.len()));
+            }
+            ticks.push(DecodedTick {
+                initialized,
+                ..tick_data(data, offset)
+            });
+            offset += TICK_DATA_LEN;
+        } else {
+            ticks.push(DecodedTick::default());
+        }
+    }
+
+    Ok(DecodedTickArray {
+        start_tick_index: read_i32(data, 8),
+        whirlpool: read_pubkey(data, 12),
+        tick_bitmap: Some(read_u128(data, 44)),
+        ticks,
+    })
+}
+
+fn tick_data(data: &[u8], offset: usize) -> DecodedTick {
+    DecodedTick {
+        initialized: false,
+        liquidity_net: read_i128(data, offset),
+        liquidity_gross: read_u128(data, offset + 16),
+        fee_growth_outside_a: read_u128(data, offset + 32),
+        fee_growth_outside_b: read_u128(data, offset + 48),
+        reward_growths_outside: [
+            read_u128(data, offset + 64),
+            read_u128(data, offset + 80),
+            read_u128(data, offset + 96),
+        ],
+    }
+}
+
+// Oracle is zero-copy account, so we read it based on its layout
+fn oracle(data: &[u8]) -> Result<DecodedOracle, String> {
+    if data.len() < ORACLE_MIN_LEN {
+        return Err(format!("unexpected length: {}", data.len()));
+    }
+
+    Ok(DecodedOracle {
+        whirlpool: read_pubkey(data, 8),
+        trade_enable_timestamp: read_u64(data, 40),
+        filter_period: read_u16(data, 48),
+        decay_period: read_u16(data, 50),
+        reduction_factor: read_u16(data, 52),
+        adaptive_fee_control_factor: read_u32(data, 54),
+        max_volatility_accumulator: read_u32(data, 58),
+        tick_group_size: read_u16(data, 62),
+        major_swap_threshold_ticks: read_u16(data, 64),
+        // 16 bytes reserved
+        last_reference_update_timestamp: read_u64(data, 82),
+        last_major_swap_timestamp: read_u64(data, 90),
+        volatility_reference: read_u32(data, 98),
+        tick_group_index_reference: read_i32(data, 102),
+        volatility_accumulator: read_u32(data, 106),
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i32(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_u128(data: &[u8], offset: usize) -> u128 {
+    u128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+fn read_i128(data: &[u8], offset: usize) -> i128 {
+    i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    let bytes: [u8; 32] = data[offset..offset + 32].try_into().unwrap();
+    Pubkey::new_from_array(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::io;
+    use crate::serde::AccountDataStoreConfig;
+
+    const SAMPLE_STATE_FILE: &str = "../data/sample_local_storage/2022/0406/whirlpool-state-20220406.json.gz";
+
+    fn tick(n: u128) -> DecodedTick {
+        DecodedTick {
+            initialized: true,
+            liquidity_net: -(n as i128),
+            liquidity_gross: n,
+            fee_growth_outside_a: n + 1,
+            fee_growth_outside_b: n + 2,
+            reward_growths_outside: [n + 3, n + 4, n + 5],
+        }
+    }
+
+    fn tick_data_bytes(tick: &DecodedTick) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&tick.liquidity_net.to_le_bytes());
+        data.extend_from_slice(&tick.liquidity_gross.to_le_bytes());
+        data.extend_from_slice(&tick.fee_growth_outside_a.to_le_bytes());
+        data.extend_from_slice(&tick.fee_growth_outside_b.to_le_bytes());
+        for reward_growth_outside in tick.reward_growths_outside.iter() {
+            data.extend_from_slice(&reward_growth_outside.to_le_bytes());
+        }
+        data
+    }
+
+    fn header(name: &str, start_tick_index: i32) -> Vec<u8> {
+        let mut data = account_discriminator(name).to_vec();
+        data.extend_from_slice(&start_tick_index.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_sample_state() {
+        let state = io::load_from_local_whirlpool_state_file(&SAMPLE_STATE_FILE.to_string(), &AccountDataStoreConfig::OnMemory).unwrap();
+
+        let mut counts: BTreeMap<&'static str, u64> = BTreeMap::new();
+        let mut whirlpools = HashMap::new();
+        let mut positions = vec![];
+        let mut tick_arrays = vec![];
+        state.accounts
+            .traverse(|pubkey, data| {
+                // all accounts in the state have known discriminators and can be decoded
+                let decoded = decode_account_data(data).unwrap().unwrap();
+                assert_eq!(registry().account_type(data), Some(decoded.account_type()));
+                *counts.entry(decoded.account_type()).or_default() += 1;
+                match decoded {
+                    DecodedAccount::Whirlpool(whirlpool) => {
+                        whirlpools.insert(pubkey.clone(), whirlpool);
+                    }
+                    DecodedAccount::Position(position) => positions.push(position),
+                    DecodedAccount::TickArray(tick_array) => tick_arrays.push(tick_array),
+                    _ => {}
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        // 2022-04-06 is before the dynamic tick array and the adaptive fee
+        assert!(counts["Whirlpool"] > 0);
+        assert!(counts["Position"] > 0);
+        assert!(counts["TickArray"] > 0);
+        assert!(counts["WhirlpoolsConfig"] > 0);
+        assert!(counts["FeeTier"] > 0);
+        assert!(!counts.contains_key("DynamicTickArray"));
+        assert!(!counts.contains_key("Oracle"));
+
+        for position in positions.iter() {
+            let whirlpool = &whirlpools[&position.whirlpool.to_string()];
+            assert!(position.tick_lower_index < position.tick_upper_index);
+            assert_eq!(position.tick_lower_index % whirlpool.tick_spacing as i32, 0);
+            assert_eq!(position.tick_upper_index % whirlpool.tick_spacing as i32, 0);
+        }
+
+        for tick_array in tick_arrays.iter() {
+            let whirlpool = &whirlpools[&tick_array.whirlpool.to_string()];
+            assert_eq!(tick_array.start_tick_index % (whirlpool.tick_spacing as i32 * TICK_ARRAY_SIZE as i32), 0);
+            assert_eq!(tick_array.tick_bitmap, None);
+            assert_eq!(tick_array.ticks.len(), TICK_ARRAY_SIZE);
+            // a tick is initialized while it has liquidity
+            for tick in tick_array.ticks.iter() {
+                assert_eq!(tick.initialized, tick.liquidity_gross > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixed_tick_array() {
+        let whirlpool = Pubkey::new_from_array([7; 32]);
+        let mut data = header("TickArray", -5632);
+        for i in 0..TICK_ARRAY_SIZE {
+            if i == 5 {
+                data.push(1);
+                data.extend_from_slice(&tick_data_bytes(&tick(100)));
+            } else {
+                data.extend_from_slice(&[0u8; FIXED_TICK_LEN]);
+            }
+        }
+        data.extend_from_slice(whirlpool.as_ref());
+        assert_eq!(data.len(), FIXED_TICK_ARRAY_LEN);
+
+        let Some(DecodedAccount::TickArray(tick_array)) = decode_account_data(&data).unwrap() else {
+            panic!("not decoded as TickArray");
+        };
+        assert_eq!(tick_array.start_tick_index, -5632);
+        assert_eq!(tick_array.whirlpool, whirlpool);
+        assert_eq!(tick_array.tick_bitmap, None);
+        assert_eq!(tick_array.ticks.len(), TICK_ARRAY_SIZE);
+        assert_eq!(tick_array.ticks[5], tick(100));
+        assert!(tick_array.ticks.iter().enumerate().all(|(i, t)| i == 5 || *t == DecodedTick::default()));
+
+        // length must be exact
+        assert!(decode_account_data(&data[..FIXED_TICK_ARRAY_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_dynamic_tick_array() {
+        let whirlpool = Pubkey::new_from_array([7; 32]);
+        let tick_bitmap = 1u128 | (1u128 << 87);
+        let mut data = header("DynamicTickArray", 5632);
+        data.extend_from_slice(whirlpool.as_ref());
+        data.extend_from_slice(&tick_bitmap.to_le_bytes());
+        assert_eq!(data.len(), DYNAMIC_TICK_ARRAY_HEADER_LEN);
+        for i in 0..TICK_ARRAY_SIZE {
+            if i == 0 || i == 87 {
+                data.push(1);
+                data.extend_from_slice(&tick_data_bytes(&tick(i as u128 + 1)));
+            } else {
+                data.push(0);
+            }
+        }
+
+        let Some(DecodedAccount::DynamicTickArray(tick_array)) = decode_account_data(&data).unwrap() else {
+            panic!("not decoded as DynamicTickArray");
+        };
+        assert_eq!(tick_array.start_tick_index, 5632);
+        assert_eq!(tick_array.whirlpool, whirlpool);
+        assert_eq!(tick_array.tick_bitmap, Some(tick_bitmap));
+        assert_eq!(tick_array.ticks.len(), TICK_ARRAY_SIZE);
+        assert_eq!(tick_array.ticks[0], tick(1));
+        assert_eq!(tick_array.ticks[87], tick(88));
+        assert!(tick_array.ticks[1..87].iter().all(|t| *t == DecodedTick::default()));
+
+        // the data of the last initialized tick is truncated
+        assert!(decode_account_data(&data[..data.len() - 1]).is_err());
+        assert!(decode_account_data(&data[..DYNAMIC_TICK_ARRAY_HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_oracle() {
+        let whirlpool = Pubkey::new_from_array([7; 32]);
+        let mut data = account_discriminator("Oracle").to_vec();
+        data.extend_from_slice(whirlpool.as_ref());
+        data.extend_from_slice(&1_700_000_000u64.to_le_bytes()); // trade_enable_timestamp
+        data.extend_from_slice(&30u16.to_le_bytes()); // filter_period
+        data.extend_from_slice(&600u16.to_le_bytes()); // decay_period
+        data.extend_from_slice(&5000u16.to_le_bytes()); // reduction_factor
+        data.extend_from_slice(&4000u32.to_le_bytes()); // adaptive_fee_control_factor
+        data.extend_from_slice(&350_000u32.to_le_bytes()); // max_volatility_accumulator
+        data.extend_from_slice(&64u16.to_le_bytes()); // tick_group_size
+        data.extend_from_slice(&16u16.to_le_bytes()); // major_swap_threshold_ticks
+        data.extend_from_slice(&[0xff; 16]); // reserved
+        data.extend_from_slice(&1_700_000_100u64.to_le_bytes()); // last_reference_update_timestamp
+        data.extend_from_slice(&1_700_000_200u64.to_le_bytes()); // last_major_swap_timestamp
+        data.extend_from_slice(&12_345u32.to_le_bytes()); // volatility_reference
+        data.extend_from_slice(&(-77i32).to_le_bytes()); // tick_group_index_reference
+        data.extend_from_slice(&23_456u32.to_le_bytes()); // volatility_accumulator
+        assert_eq!(data.len(), ORACLE_MIN_LEN);
+
+        let expected = DecodedOracle {
+            whirlpool,
+            trade_enable_timestamp: 1_700_000_000,
+            filter_period: 30,
+            decay_period: 600,
+            reduction_factor: 5000,
+            adaptive_fee_control_factor: 4000,
+            max_volatility_accumulator: 350_000,
+            tick_group_size: 64,
+            major_swap_threshold_ticks: 16,
+            last_reference_update_timestamp: 1_700_000_100,
+            last_major_swap_timestamp: 1_700_000_200,
+            volatility_reference: 12_345,
+            tick_group_index_reference: -77,
+            volatility_accumulator: 23_456,
+        };
+        let Some(DecodedAccount::Oracle(oracle)) = decode_account_data(&data).unwrap() else {
+            panic!("not decoded as Oracle");
+        };
+        assert_eq!(oracle, expected);
+
+        // trailing reserved bytes are ignored
+        data.extend_from_slice(&[0xff; 144]);
+        let Some(DecodedAccount::Oracle(oracle)) = decode_account_data(&data).unwrap() else {
+            panic!("not decoded as Oracle");
+        };
+        assert_eq!(oracle, expected);
+
+        assert!(decode_account_data(&data[..ORACLE_MIN_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_discriminator() {
+        assert!(decode_account_data(&[0u8; 100]).unwrap().is_none());
+        assert!(decode_account_data(&[0u8; 4]).unwrap().is_none());
+        assert_eq!(registry().account_type(&[0u8; 100]), None);
+        assert_eq!(registry().account_type(&account_discriminator("Whirlpool")), Some("Whirlpool"));
+    }
+}
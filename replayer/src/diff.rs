@@ -0,0 +1,431 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use replay_engine::account_data_store::AccountDataStore;
+use replay_engine::types::WritableAccountSnapshot;
+use serde_derive::Serialize;
+use serde_json::Value;
+
+use crate::decoder::{decode_account_data, DecodedAccount, DecodedTickArray};
+
+/*
+
+Account diff
+
+Both sides of an account are decoded with the account decoder and flattened into fields.
+Field names are snake_case paths of the decoded account (e.g. sqrt_price, reward_infos[0].growth_global_x64, ticks[-5568].liquidity_net).
+Ticks are named by tick index (start_tick_index + i * tick_spacing) with the tick spacing of the whirlpool in the same snapshot.
+If the whirlpool is not available, they are named by the slot in the tick array instead (e.g. ticks[slot=12].liquidity_net).
+
+Accounts that cannot be decoded are compared as raw data (base64 encoded "data" field).
+
+*/
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    pub pubkey: String,
+    pub account_type: String,
+    pub kind: AccountChangeKind,
+    pub field_changes: Vec<FieldChange>,
+}
+
+// old is None if the field doesn't exist on the old side (and vice versa)
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// tick spacing of the whirlpool (None if the whirlpool is not available)
+pub type TickSpacingLookup<'a> = dyn Fn(&String) -> Option<u16> + 'a;
+
+// changed accounts in the snapshot (sorted by pubkey)
+pub fn diff_snapshot(snapshot: &WritableAccountSnapshot) -> Vec<AccountDiff> {
+    let tick_spacing = |whirlpool: &String| {
+        snapshot.post_snapshot.get(whirlpool)
+            .or_else(|| snapshot.pre_snapshot.get(whirlpool))
+            .and_then(|data| whirlpool_tick_spacing(data))
+    };
+    let pubkeys: BTreeSet<&String> = snapshot.pre_snapshot.keys().chain(snapshot.post_snapshot.keys()).collect();
+    pubkeys
+        .into_iter()
+        .filter_map(|pubkey| {
+            diff_account(
+                pubkey,
+                snapshot.pre_snapshot.get(pubkey).map(|data| data.as_slice()),
+                snapshot.post_snapshot.get(pubkey).map(|data| data.as_slice()),
+                &tick_spacing,
+            )
+        })
+        .collect()
+}
+
+// tick spacing of the whirlpools in the store (for diffs between states)
+pub fn store_tick_spacing(store: &AccountDataStore) -> impl Fn(&String) -> Option<u16> + '_ {
+    move |whirlpool| store.get(whirlpool).ok().flatten().and_then(|data| whirlpool_tick_spacing(&data))
+}
+
+// None if the data is not a whirlpool
+pub fn whirlpool_tick_spacing(data: &[u8]) -> Option<u16> {
+    match decode_account_data(data) {
+        Ok(Some(DecodedAccount::Whirlpool(whirlpool))) => Some(whirlpool.tick_spacing),
+        _ => None,
+    }
+}
+
+// None if both sides are the same
+// the same tick spacing lookup is used for both sides, so their tick fields have the same names
+pub fn diff_account(pubkey: &String, old: Option<&[u8]>, new: Option<&[u8]>, tick_spacing: &TickSpacingLookup) -> Option<AccountDiff> {
+    // empty data is treated as non-existent account (snapshot of uninitialized account)
+    let old = old.filter(|data| !data.is_empty());
+    let new = new.filter(|data| !data.is_empty());
+    if old == new {
+        return None;
+    }
+
+    let kind = match (old, new) {
+        (None, Some(_)) => AccountChangeKind::Created,
+        (Some(_), None) => AccountChangeKind::Deleted,
+        _ => AccountChangeKind::Updated,
+    };
+
+    let old_decoded = old.map(|data| decode_fields(data, tick_spacing));
+    let new_decoded = new.map(|data| decode_fields(data, tick_spacing));
+    let old_type = old_decoded.as_ref().map(|(account_type, _)| account_type.clone());
+    let new_type = new_decoded.as_ref().map(|(account_type, _)| account_type.clone());
+
+    let (account_type, field_changes) = if old_type.is_some() && new_type.is_some() && old_type != new_type {
+        // account type is changed (an undecodable side has the type "Unknown", so its data is compared as raw data)
+        ("Unknown".to_string(), vec![raw_data_change(old, new)])
+    } else {
+        let account_type = old_type.or(new_type).unwrap();
+        let old_fields = old_decoded.map(|(_, fields)| fields).unwrap_or_default();
+        let new_fields = new_decoded.map(|(_, fields)| fields).unwrap_or_default();
+        let mut field_changes = diff_fields(&old_fields, &new_fields);
+        if field_changes.is_empty() {
+            // some bytes not covered by the decoded fields are different
+            field_changes.push(raw_data_change(old, new));
+        }
+        (account_type, field_changes)
+    };
+
+    Some(AccountDiff {
+        pubkey: pubkey.clone(),
+        account_type,
+        kind,
+        field_changes,
+    })
+}
+
+// account type and flattened fields (raw data if the account cannot be decoded)
+pub fn decode_fields(data: &[u8], tick_spacing: &TickSpacingLookup) -> (String, Vec<(String, String)>) {
+    match decode_account_data(data) {
+        Ok(Some(decoded)) => {
+            let mut fields = vec![];
+            match &decoded {
+                DecodedAccount::TickArray(tick_array) | DecodedAccount::DynamicTickArray(tick_array) => {
+                    flatten_tick_array(&decoded.to_json(), tick_array, tick_spacing(&tick_array.whirlpool.to_string()), &mut fields);
+                }
+                _ => flatten_json(&decoded.to_json(), String::new(), &mut fields),
+            }
+            (decoded.account_type().to_string(), fields)
+        }
+        _ => ("Unknown".to_string(), vec![("data".to_string(), BASE64_STANDARD.encode(data))]),
+    }
+}
+
+pub fn diff_fields(old: &Vec<(String, String)>, new: &Vec<(String, String)>) -> Vec<FieldChange> {
+    let old_map: HashMap<&String, &String> = old.iter().map(|(k, v)| (k, v)).collect();
+    let new_map: HashMap<&String, &String> = new.iter().map(|(k, v)| (k, v)).collect();
+
+    let mut changes = vec![];
+    for (field, old_value) in old.iter() {
+        let new_value = new_map.get(field).copied();
+        if new_value != Some(old_value) {
+            changes.push(FieldChange {
+                field: field.clone(),
+                old: Some(old_value.clone()),
+                new: new_value.cloned(),
+            });
+        }
+    }
+    for (field, new_value) in new.iter() {
+        if !old_map.contains_key(field) {
+            changes.push(FieldChange {
+                field: field.clone(),
+                old: None,
+                new: Some(new_value.clone()),
+            });
+        }
+    }
+    changes
+}
+
+pub fn format_account_diffs(diffs: &[AccountDiff]) -> String {
+    diffs.iter().map(|diff| diff.to_string()).collect::<Vec<String>>().join("")
+}
+
+impl fmt::Display for AccountDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ({}, {:?})", self.pubkey, self.account_type, self.kind)?;
+        for change in self.field_changes.iter() {
+            writeln!(f, "  {}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let none = "(none)".to_string();
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.field,
+            self.old.as_ref().unwrap_or(&none),
+            self.new.as_ref().unwrap_or(&none),
+        )
+    }
+}
+
+fn raw_data_change(old: Option<&[u8]>, new: Option<&[u8]>) -> FieldChange {
+    FieldChange {
+        field: "data".to_string(),
+        old: old.map(|data| BASE64_STANDARD.encode(data)),
+        new: new.map(|data| BASE64_STANDARD.encode(data)),
+    }
+}
+
+// the fields other than ticks are flattened as usual
+fn flatten_tick_array(value: &Value, tick_array: &DecodedTickArray, tick_spacing: Option<u16>, fields: &mut Vec<(String, String)>) {
+    let mut value = value.clone();
+    let ticks = value.as_object_mut().and_then(|map| map.remove("ticks")).unwrap_or_default();
+    flatten_json(&value, String::new(), fields);
+
+    if let Value::Array(ticks) = ticks {
+        for (i, tick) in ticks.iter().enumerate() {
+            let path = match tick_spacing {
+                Some(tick_spacing) => format!("ticks[{}]", tick_array.start_tick_index + i as i32 * tick_spacing as i32),
+                None => format!("ticks[slot={}]", i),
+            };
+            flatten_json(tick, path, fields);
+        }
+    }
+}
+
+fn flatten_json(value: &Value, path: String, fields: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter() {
+                let key = to_snake_case(key);
+                let path = if path.is_empty() { key } else { format!("{}.{}", path, key) };
+                flatten_json(value, path, fields);
+            }
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                flatten_json(value, format!("{}[{}]", path, i), fields);
+            }
+        }
+        Value::String(s) => fields.push((path, s.clone())),
+        _ => fields.push((path, value.to_string())),
+    }
+}
+
+// JSON keys are camelCase (e.g. feeGrowthGlobalA to fee_growth_global_a, growthGlobalX64 to growth_global_x64)
+fn to_snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{AccountDeserialize, AccountSerialize};
+    use serde_json::json;
+    use whirlpool_base::state::Whirlpool;
+
+    use crate::decoder::{account_discriminator, FIXED_TICK_ARRAY_LEN, TICK_ARRAY_SIZE};
+    use crate::test_utils::{pubkey, whirlpool_data, writable_account_snapshot, WhirlpoolParams};
+
+    fn no_tick_spacing(_whirlpool: &String) -> Option<u16> {
+        None
+    }
+
+    fn whirlpool(liquidity: u128, tick_spacing: u16) -> Vec<u8> {
+        let data = whirlpool_data(&WhirlpoolParams {
+            token_mint_a: 10,
+            token_mint_b: 11,
+            fee_rate: 3000,
+            sqrt_price: 1 << 64,
+            tick_current_index: 0,
+            liquidity,
+        });
+        let mut whirlpool = Whirlpool::try_deserialize(&mut data.as_slice()).unwrap();
+        whirlpool.tick_spacing = tick_spacing;
+        let mut data = vec![];
+        whirlpool.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    // fixed tick array of whirlpool pubkey(n) (only liquidity_net of the tick at the slot is set)
+    fn tick_array(whirlpool: u8, start_tick_index: i32, slot: usize, liquidity_net: i128) -> Vec<u8> {
+        let tick_len = (FIXED_TICK_ARRAY_LEN - 8 - 4 - 32) / TICK_ARRAY_SIZE;
+        let mut data = vec![0u8; FIXED_TICK_ARRAY_LEN];
+        data[0..8].copy_from_slice(&account_discriminator("TickArray"));
+        data[8..12].copy_from_slice(&start_tick_index.to_le_bytes());
+        // initialized flag is followed by liquidity_net
+        let offset = 8 + 4 + tick_len * slot + 1;
+        data[offset..offset + 16].copy_from_slice(&liquidity_net.to_le_bytes());
+        data[FIXED_TICK_ARRAY_LEN - 32..].copy_from_slice(&[whirlpool; 32]);
+        data
+    }
+
+    fn change(field: &str, old: Option<&str>, new: Option<&str>) -> FieldChange {
+        FieldChange {
+            field: field.to_string(),
+            old: old.map(|s| s.to_string()),
+            new: new.map(|s| s.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_diff_account_updated() {
+        let (old, new) = (whirlpool(100, 64), whirlpool(200, 64));
+
+        let diff = diff_account(&pubkey(1), Some(old.as_slice()), Some(new.as_slice()), &no_tick_spacing).unwrap();
+        assert_eq!(diff.pubkey, pubkey(1));
+        assert_eq!(diff.account_type, "Whirlpool");
+        assert_eq!(diff.kind, AccountChangeKind::Updated);
+        assert_eq!(diff.field_changes, vec![change("liquidity", Some("100"), Some("200"))]);
+
+        assert_eq!(diff_account(&pubkey(1), Some(old.as_slice()), Some(old.as_slice()), &no_tick_spacing), None);
+        assert_eq!(diff_account(&pubkey(1), None, None, &no_tick_spacing), None);
+    }
+
+    #[test]
+    fn test_diff_account_created_and_deleted() {
+        let data = whirlpool(100, 64);
+        let (_, fields) = decode_fields(&data, &no_tick_spacing);
+
+        let created = diff_account(&pubkey(1), None, Some(data.as_slice()), &no_tick_spacing).unwrap();
+        assert_eq!(created.account_type, "Whirlpool");
+        assert_eq!(created.kind, AccountChangeKind::Created);
+        assert_eq!(created.field_changes.len(), fields.len());
+        assert!(created.field_changes.iter().all(|change| change.old.is_none()));
+        assert!(created.field_changes.contains(&change("liquidity", None, Some("100"))));
+
+        let deleted = diff_account(&pubkey(1), Some(data.as_slice()), None, &no_tick_spacing).unwrap();
+        assert_eq!(deleted.kind, AccountChangeKind::Deleted);
+        assert_eq!(deleted.field_changes.len(), fields.len());
+        assert!(deleted.field_changes.iter().all(|change| change.new.is_none()));
+        assert!(deleted.field_changes.contains(&change("liquidity", Some("100"), None)));
+    }
+
+    #[test]
+    fn test_diff_account_empty_data_is_absent() {
+        let data = whirlpool(100, 64);
+        let empty: &[u8] = &[];
+
+        assert_eq!(diff_account(&pubkey(1), Some(empty), Some(data.as_slice()), &no_tick_spacing).unwrap().kind, AccountChangeKind::Created);
+        assert_eq!(diff_account(&pubkey(1), Some(data.as_slice()), Some(empty), &no_tick_spacing).unwrap().kind, AccountChangeKind::Deleted);
+        assert_eq!(diff_account(&pubkey(1), Some(empty), None, &no_tick_spacing), None);
+        assert_eq!(diff_account(&pubkey(1), None, Some(empty), &no_tick_spacing), None);
+    }
+
+    #[test]
+    fn test_diff_account_raw_data() {
+        let whirlpool = whirlpool(100, 64);
+        let tick_array = tick_array(1, 0, 0, 0);
+        let raw = |old: &[u8], new: &[u8]| vec![change("data", Some(BASE64_STANDARD.encode(old).as_str()), Some(BASE64_STANDARD.encode(new).as_str()))];
+
+        // account type is changed
+        let diff = diff_account(&pubkey(1), Some(whirlpool.as_slice()), Some(tick_array.as_slice()), &no_tick_spacing).unwrap();
+        assert_eq!(diff.account_type, "Unknown");
+        assert_eq!(diff.kind, AccountChangeKind::Updated);
+        assert_eq!(diff.field_changes, raw(&whirlpool, &tick_array));
+
+        // one side cannot be decoded
+        let diff = diff_account(&pubkey(1), Some([1, 2, 3].as_slice()), Some(whirlpool.as_slice()), &no_tick_spacing).unwrap();
+        assert_eq!(diff.account_type, "Unknown");
+        assert_eq!(diff.field_changes, raw(&[1, 2, 3], &whirlpool));
+
+        // both sides cannot be decoded
+        let diff = diff_account(&pubkey(1), Some([1, 2].as_slice()), Some([1, 3].as_slice()), &no_tick_spacing).unwrap();
+        assert_eq!(diff.account_type, "Unknown");
+        assert_eq!(diff.field_changes, raw(&[1, 2], &[1, 3]));
+    }
+
+    #[test]
+    fn test_tick_fields_named_by_tick_index() {
+        let whirlpool = whirlpool(100, 64);
+        let old = tick_array(7, -5632, 5, 0);
+        let new = tick_array(7, -5632, 5, -300);
+
+        // the whirlpool in the snapshot is not changed (-5632 + 5 * 64 = -5312)
+        let snapshot = writable_account_snapshot(
+            &[(pubkey(7), whirlpool.clone()), (pubkey(1), old.clone())],
+            &[(pubkey(7), whirlpool.clone()), (pubkey(1), new.clone())],
+        );
+        let diffs = diff_snapshot(&snapshot);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].pubkey, pubkey(1));
+        assert_eq!(diffs[0].account_type, "TickArray");
+        assert_eq!(diffs[0].field_changes, vec![change("ticks[-5312].liquidity_net", Some("0"), Some("-300"))]);
+
+        // the slot in the tick array is used without the whirlpool
+        let snapshot = writable_account_snapshot(&[(pubkey(1), old.clone())], &[(pubkey(1), new.clone())]);
+        let diffs = diff_snapshot(&snapshot);
+        assert_eq!(diffs[0].field_changes, vec![change("ticks[slot=5].liquidity_net", Some("0"), Some("-300"))]);
+
+        // the other fields are named as usual
+        let (_, fields) = decode_fields(&new, &|_whirlpool: &String| Some(64));
+        let names: Vec<&str> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert!(names.contains(&"start_tick_index"));
+        assert!(names.contains(&"whirlpool"));
+        assert!(names.contains(&"ticks[-5632].initialized"));
+        assert!(names.contains(&"ticks[-64].reward_growths_outside[2]"));
+        assert!(!names.iter().any(|name| name.starts_with("ticks[slot=")));
+    }
+
+    #[test]
+    fn test_field_names() {
+        assert_eq!(to_snake_case("liquidity"), "liquidity");
+        assert_eq!(to_snake_case("feeGrowthGlobalA"), "fee_growth_global_a");
+        assert_eq!(to_snake_case("growthGlobalX64"), "growth_global_x64");
+
+        let value = json!({
+            "tickCurrentIndex": -5,
+            "rewardInfos": [{ "growthGlobalX64": "1" }, { "growthGlobalX64": "2" }],
+            "adaptiveFeeConstants": { "tickGroupSize": 64, "isEnabled": true },
+        });
+        let mut fields = vec![];
+        flatten_json(&value, String::new(), &mut fields);
+        let expected = vec![
+            ("adaptive_fee_constants.is_enabled", "true"),
+            ("adaptive_fee_constants.tick_group_size", "64"),
+            ("reward_infos[0].growth_global_x64", "1"),
+            ("reward_infos[1].growth_global_x64", "2"),
+            ("tick_current_index", "-5"),
+        ];
+        assert_eq!(fields, expected.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>());
+    }
+}
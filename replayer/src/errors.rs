@@ -0,0 +1,80 @@
+use replay_engine::errors::{ErrorCode, TransactionError};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    // a broken line of a JSON Lines file (line_number is 1-based)
+    #[error("json error at {location} line {line_number}: {source}")]
+    JsonLine {
+        location: String,
+        line_number: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("csv error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("invalid date: {0}")]
+    InvalidDate(String),
+
+    #[error("database error: {0}")]
+    Database(String),
+
+    #[error("account data store error: {0}")]
+    AccountDataStore(String),
+
+    #[error("failed to deserialize account: {0}")]
+    AccountDeserialize(String),
+
+    #[error("ohlcv error: {0}")]
+    Ohlcv(String),
+
+    #[error("invalid repro bundle: {0}")]
+    ReproBundle(String),
+
+    #[error("failed to replay instruction (slot={slot} signature={signature} index={instruction_index} name={instruction_name}): {source}")]
+    Instruction {
+        slot: u64,
+        signature: String,
+        instruction_index: usize,
+        instruction_name: String,
+        #[source]
+        source: ErrorCode,
+    },
+}
+
+impl ReplayError {
+    pub fn instruction(
+        slot: u64,
+        signature: &String,
+        instruction_index: usize,
+        instruction_name: &String,
+        source: ErrorCode,
+    ) -> ReplayError {
+        ReplayError::Instruction {
+            slot,
+            signature: signature.clone(),
+            instruction_index,
+            instruction_name: instruction_name.clone(),
+            source,
+        }
+    }
+
+    // the underlying error of the failed transaction (if the instruction was executed)
+    pub fn transaction_error(&self) -> Option<&TransactionError> {
+        match self {
+            ReplayError::Instruction { source: ErrorCode::InstructionReplayFailed(err), .. } => Some(err),
+            _ => None,
+        }
+    }
+}
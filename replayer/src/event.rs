@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::rc::Rc;
+
+use replay_engine::decoded_instructions::DecodedWhirlpoolInstruction;
+use replay_engine::replay_environment::ExecutionRecord;
+use replay_engine::types::{Slot, WritableAccountSnapshot};
+use serde_json::Value;
+
+use crate::errors::ReplayError;
+use crate::io::CompressedFileWriter;
+use crate::schema::{EventAccount, EventTransaction, InstructionEvent, Transaction, WhirlpoolEvent};
+use crate::SyncInstructionCallback;
+
+// write a whirlpool event file (JSON Lines, one line per slot) while replaying
+// instructions are buffered until the slot changes, so finish must be called to write the last slot
+pub struct WhirlpoolEventWriter {
+    writer: CompressedFileWriter,
+    current: Option<WhirlpoolEvent>,
+    // the first error in the instruction callback (callback cannot return error)
+    error: Option<ReplayError>,
+}
+
+impl WhirlpoolEventWriter {
+    // the compression is determined by the extension (.zst or .gz)
+    pub fn create(file_path: &String) -> Result<WhirlpoolEventWriter, ReplayError> {
+        Ok(WhirlpoolEventWriter {
+            writer: CompressedFileWriter::create(file_path)?,
+            current: None,
+            error: None,
+        })
+    }
+
+    // instruction callback to feed the replayed instructions into the writer
+    // (programEvents are written only if the execution recording is enabled)
+    pub fn instruction_callback(writer: &Rc<RefCell<WhirlpoolEventWriter>>) -> SyncInstructionCallback {
+        let writer = Rc::clone(writer);
+        Rc::new(move |slot, transaction, name, instruction, _accounts, snapshot, execution_record| {
+            let mut writer = writer.borrow_mut();
+            if writer.error.is_some() {
+                return;
+            }
+            if let Err(err) = writer.write_instruction(slot, transaction, name, instruction, snapshot, execution_record) {
+                writer.error = Some(err);
+            }
+        })
+    }
+
+    pub fn write_instruction(
+        &mut self,
+        slot: &Slot,
+        transaction: &Transaction,
+        name: &String,
+        instruction: &DecodedWhirlpoolInstruction,
+        snapshot: &WritableAccountSnapshot,
+        execution_record: Option<&ExecutionRecord>,
+    ) -> Result<(), ReplayError> {
+        if self.current.as_ref().map_or(false, |current| current.slot != slot.slot) {
+            self.flush_slot()?;
+        }
+
+        let current = self.current.get_or_insert_with(|| WhirlpoolEvent {
+            slot: slot.slot,
+            block_height: slot.block_height,
+            block_time: slot.block_time,
+            transactions: vec![],
+        });
+
+        if current.transactions.last().map_or(true, |last| last.signature != transaction.signature) {
+            current.transactions.push(EventTransaction {
+                index: transaction.index,
+                signature: transaction.signature.clone(),
+                payer: transaction.payer.clone(),
+                events: vec![],
+            });
+        }
+
+        current.transactions.last_mut().unwrap().events.push(InstructionEvent {
+            name: name.clone(),
+            payload: instruction_payload(instruction)?,
+            program_events: execution_record
+                .map(|execution_record| execution_record.events.iter().map(|event| event.name().to_string()).collect())
+                .unwrap_or_default(),
+            accounts: event_accounts(snapshot),
+        });
+
+        Ok(())
+    }
+
+    // write the last slot and complete the compression
+    pub fn finish(mut self) -> Result<(), ReplayError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.flush_slot()?;
+        self.writer.finish()
+    }
+
+    fn flush_slot(&mut self) -> Result<(), ReplayError> {
+        if let Some(current) = self.current.take() {
+            serde_json::to_writer(&mut self.writer, &current)?;
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+// fields of the decoded instruction (without the enum variant name)
+fn instruction_payload(instruction: &DecodedWhirlpoolInstruction) -> Result<Value, ReplayError> {
+    match serde_json::to_value(instruction)? {
+        Value::Object(variant) => Ok(variant.into_iter().next().map(|(_, payload)| payload).unwrap_or(Value::Null)),
+        payload => Ok(payload),
+    }
+}
+
+fn event_accounts(snapshot: &WritableAccountSnapshot) -> Vec<EventAccount> {
+    let pubkeys: BTreeSet<&String> = snapshot.pre_snapshot.keys().chain(snapshot.post_snapshot.keys()).collect();
+    pubkeys
+        .into_iter()
+        .map(|pubkey| EventAccount {
+            pubkey: pubkey.clone(),
+            pre: snapshot.pre_snapshot.get(pubkey).cloned(),
+            post: snapshot.post_snapshot.get(pubkey).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io;
+    use crate::test_utils::{pubkey, swap, writable_account_snapshot};
+
+    fn transaction(index: u32) -> Transaction {
+        Transaction {
+            index,
+            signature: format!("signature-{}", index),
+            payer: pubkey(99),
+            balances: vec![],
+            instructions: vec![],
+        }
+    }
+
+    fn write_and_read(file_path: &String, instructions: &[(Slot, Transaction, DecodedWhirlpoolInstruction, WritableAccountSnapshot)]) -> Vec<WhirlpoolEvent> {
+        let mut writer = WhirlpoolEventWriter::create(file_path).unwrap();
+        for (slot, transaction, instruction, snapshot) in instructions.iter() {
+            writer.write_instruction(slot, transaction, &"swap".to_string(), instruction, snapshot, None).unwrap();
+        }
+        writer.finish().unwrap();
+
+        io::load_from_local_whirlpool_event_file(file_path)
+            .unwrap()
+            .collect::<Result<Vec<WhirlpoolEvent>, ReplayError>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_write_and_read_roundtrip() {
+        let slot_1 = Slot::new(100, 90, 1704067200);
+        let slot_2 = Slot::new(102, 91, 1704067201);
+        let whirlpool = pubkey(10);
+        let oracle = pubkey(11);
+
+        // the oracle is created in the first instruction (pre is None)
+        let snapshot = |n: u8| writable_account_snapshot(
+            &[(whirlpool.clone(), vec![n; 4])],
+            &[(whirlpool.clone(), vec![n + 1; 4]), (oracle.clone(), vec![n; 2])],
+        );
+        let instructions = vec![
+            // two instructions in the first transaction
+            (slot_1, transaction(0), swap(&whirlpool, &oracle, true, 100, 90), snapshot(1)),
+            (slot_1, transaction(0), swap(&whirlpool, &oracle, false, 200, 210), snapshot(2)),
+            (slot_1, transaction(3), swap(&whirlpool, &oracle, true, 300, 280), snapshot(3)),
+            // the last slot is written by finish
+            (slot_2, transaction(1), swap(&whirlpool, &oracle, true, 400, 370), snapshot(4)),
+        ];
+
+        let tempdir = tempfile::tempdir().unwrap();
+        for file_name in ["whirlpool-event.jsonl.gz", "whirlpool-event.jsonl.zst"] {
+            let file_path = tempdir.path().join(file_name).to_str().unwrap().to_string();
+            let events = write_and_read(&file_path, &instructions);
+
+            assert_eq!(events.len(), 2);
+            assert_eq!((events[0].slot, events[0].block_height, events[0].block_time), (100, 90, 1704067200));
+            assert_eq!((events[1].slot, events[1].block_height, events[1].block_time), (102, 91, 1704067201));
+
+            let transactions = |event: &WhirlpoolEvent| event.transactions.iter().map(|t| (t.index, t.signature.clone(), t.events.len())).collect::<Vec<_>>();
+            assert_eq!(transactions(&events[0]), vec![(0, "signature-0".to_string(), 2), (3, "signature-3".to_string(), 1)]);
+            assert_eq!(transactions(&events[1]), vec![(1, "signature-1".to_string(), 1)]);
+            assert_eq!(events[0].transactions[0].payer, pubkey(99));
+
+            // events are in the instruction order
+            let all_events = events.iter().flat_map(|event| event.transactions.iter()).flat_map(|t| t.events.iter()).collect::<Vec<_>>();
+            assert_eq!(all_events.len(), instructions.len());
+            for (event, (_, _, instruction, _)) in all_events.iter().zip(instructions.iter()) {
+                assert_eq!(event.name, "swap");
+                assert_eq!(event.payload, instruction_payload(instruction).unwrap());
+                // no execution record
+                assert!(event.program_events.is_empty());
+            }
+            assert_eq!(all_events[0].payload["keyWhirlpool"], whirlpool.as_str());
+            assert_eq!(all_events[1].payload["transferAmount0"], 200);
+
+            // accounts are sorted by pubkey
+            let mut expected_accounts = vec![
+                EventAccount { pubkey: whirlpool.clone(), pre: Some(vec![2; 4]), post: Some(vec![3; 4]) },
+                EventAccount { pubkey: oracle.clone(), pre: None, post: Some(vec![2; 2]) },
+            ];
+            expected_accounts.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+            assert_eq!(all_events[1].accounts, expected_accounts);
+        }
+    }
+
+    #[test]
+    fn test_finish_without_instructions() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_path = tempdir.path().join("whirlpool-event.jsonl.gz").to_str().unwrap().to_string();
+        assert!(write_and_read(&file_path, &[]).is_empty());
+    }
+}
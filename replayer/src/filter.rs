@@ -0,0 +1,422 @@
+use std::collections::{HashMap, HashSet};
+
+use anchor_lang::prelude::Pubkey;
+use replay_engine::account_data_store::AccountDataStore;
+use replay_engine::decoded_instructions::DecodedWhirlpoolInstruction;
+
+use crate::decoder::{account_discriminator, FIXED_TICK_ARRAY_LEN};
+use crate::errors::ReplayError;
+use crate::schema::WhirlpoolTransaction;
+
+const TWO_HOP_SWAP_INSTRUCTIONS: [&str; 2] = ["twoHopSwap", "twoHopSwapV2"];
+
+/*
+
+Whirlpool filter
+
+Only the instructions that touch the whirlpools in the filter are replayed.
+The accounts needed to replay them are:
+- Whirlpool: the whirlpools in the filter
+- Position, TickArray (fixed and dynamic), Oracle, LockConfig: the ones belonging to the whirlpools in the filter
+- other accounts (WhirlpoolsConfig, FeeTier, PositionBundle, ...): all of them (not whirlpool specific and few)
+
+A two-hop swap needs both pools, so expand_by_two_hop_swaps adds the pools connected with the whirlpools
+in the filter through two-hop swaps (in the transactions to be replayed) before loading the state.
+The *_with_filter builders of WhirlpoolReplayer scan the transactions and expand the filter by themselves.
+
+Instructions not belonging to a whirlpool (config, fee tier, token badge, position bundle initialization) are always replayed.
+DeletePositionBundle is skipped because bundled positions of other whirlpools are not tracked (the bundle account stays in the state).
+
+*/
+
+#[derive(Debug, Clone)]
+pub struct WhirlpoolFilter {
+    whirlpools: HashSet<String>,
+    discriminators: AccountDiscriminators,
+}
+
+#[derive(Debug, Clone)]
+struct AccountDiscriminators {
+    whirlpool: [u8; 8],
+    position: [u8; 8],
+    fixed_tick_array: [u8; 8],
+    dynamic_tick_array: [u8; 8],
+    oracle: [u8; 8],
+    lock_config: [u8; 8],
+}
+
+impl WhirlpoolFilter {
+    pub fn new<I: IntoIterator<Item = String>>(whirlpools: I) -> WhirlpoolFilter {
+        WhirlpoolFilter {
+            whirlpools: whirlpools.into_iter().collect(),
+            discriminators: AccountDiscriminators {
+                whirlpool: account_discriminator("Whirlpool"),
+                position: account_discriminator("Position"),
+                fixed_tick_array: account_discriminator("TickArray"),
+                dynamic_tick_array: account_discriminator("DynamicTickArray"),
+                oracle: account_discriminator("Oracle"),
+                lock_config: account_discriminator("LockConfig"),
+            },
+        }
+    }
+
+    pub fn get_whirlpools(&self) -> &HashSet<String> {
+        &self.whirlpools
+    }
+
+    pub fn contains_whirlpool(&self, whirlpool: &String) -> bool {
+        self.whirlpools.contains(whirlpool)
+    }
+
+    // add the whirlpools connected through two-hop swaps (transitively)
+    pub fn expand_by_two_hop_swaps<I>(&mut self, transaction_iter: I) -> Result<(), ReplayError>
+    where
+        I: Iterator<Item = Result<WhirlpoolTransaction, ReplayError>>,
+    {
+        // union-find over whirlpools connected by two-hop swaps
+        let mut parent = HashMap::<String, String>::new();
+        for whirlpool_transaction in transaction_iter {
+            for transaction in whirlpool_transaction?.transactions {
+                for instruction in transaction.instructions {
+                    if !TWO_HOP_SWAP_INSTRUCTIONS.contains(&instruction.name.as_str()) {
+                        continue;
+                    }
+                    let one = instruction.payload["keyWhirlpoolOne"].as_str();
+                    let two = instruction.payload["keyWhirlpoolTwo"].as_str();
+                    if let (Some(one), Some(two)) = (one, two) {
+                        union(&mut parent, one.to_string(), two.to_string());
+                    }
+                }
+            }
+        }
+
+        let roots: HashSet<String> = self.whirlpools.iter().map(|whirlpool| find(&mut parent, whirlpool.clone())).collect();
+        let whirlpools: Vec<String> = parent.keys().cloned().collect();
+        for whirlpool in whirlpools {
+            if roots.contains(&find(&mut parent, whirlpool.clone())) {
+                self.whirlpools.insert(whirlpool);
+            }
+        }
+        Ok(())
+    }
+
+    // used by the state loader to keep only the accounts needed
+    pub fn is_account_needed(&self, pubkey: &String, data: &[u8]) -> bool {
+        if data.len() < 8 {
+            return true;
+        }
+
+        let discriminator = &data[0..8];
+        let d = &self.discriminators;
+        if discriminator == d.whirlpool {
+            return self.whirlpools.contains(pubkey);
+        }
+        // whirlpool field offset of each account
+        let whirlpool_offset = if discriminator == d.position {
+            8
+        } else if discriminator == d.fixed_tick_array {
+            FIXED_TICK_ARRAY_LEN - 32
+        } else if discriminator == d.dynamic_tick_array {
+            8 + 4
+        } else if discriminator == d.oracle {
+            8
+        } else if discriminator == d.lock_config {
+            // position(32) + position_owner(32) + whirlpool(32)
+            8 + 32 + 32
+        } else {
+            return true;
+        };
+
+        if data.len() < whirlpool_offset + 32 {
+            return true;
+        }
+        let whirlpool = Pubkey::new_from_array(data[whirlpool_offset..whirlpool_offset + 32].try_into().unwrap());
+        self.whirlpools.contains(&whirlpool.to_string())
+    }
+
+    // positions of the whirlpools in the filter
+    pub(crate) fn collect_positions(&self, accounts: &AccountDataStore) -> Result<HashSet<String>, ReplayError> {
+        let mut positions = HashSet::new();
+        accounts
+            .traverse(|pubkey, data| {
+                if data.len() >= 8 && data[0..8] == self.discriminators.position && self.is_account_needed(pubkey, data) {
+                    positions.insert(pubkey.clone());
+                }
+                Ok(())
+            })
+            .map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+        Ok(positions)
+    }
+
+    // remove the accounts not needed from the store (for the state loaded without filter)
+    pub(crate) fn prune(&self, accounts: &mut AccountDataStore) -> Result<(), ReplayError> {
+        let mut unneeded = vec![];
+        accounts
+            .traverse(|pubkey, data| {
+                if !self.is_account_needed(pubkey, data) {
+                    unneeded.push(pubkey.clone());
+                }
+                Ok(())
+            })
+            .map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+
+        for pubkey in unneeded {
+            accounts.delete(&pubkey).map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+// filter state during the replay (positions are tracked to handle instructions without whirlpool key)
+pub(crate) struct ReplayFilter {
+    filter: WhirlpoolFilter,
+    positions: HashSet<String>,
+}
+
+impl ReplayFilter {
+    pub(crate) fn new(filter: WhirlpoolFilter, positions: HashSet<String>) -> ReplayFilter {
+        ReplayFilter { filter, positions }
+    }
+
+    pub(crate) fn get_filter(&self) -> &WhirlpoolFilter {
+        &self.filter
+    }
+
+    pub(crate) fn should_replay(&mut self, instruction: &DecodedWhirlpoolInstruction) -> bool {
+        let filter = &self.filter;
+        match instruction {
+            // position is opened
+            DecodedWhirlpoolInstruction::OpenPosition(params) => self.track_position(&params.key_whirlpool, &params.key_position),
+            DecodedWhirlpoolInstruction::OpenPositionWithMetadata(params) => self.track_position(&params.key_whirlpool, &params.key_position),
+            DecodedWhirlpoolInstruction::OpenPositionWithTokenExtensions(params) => self.track_position(&params.key_whirlpool, &params.key_position),
+            DecodedWhirlpoolInstruction::OpenBundledPosition(params) => self.track_position(&params.key_whirlpool, &params.key_bundled_position),
+            // position only
+            DecodedWhirlpoolInstruction::ClosePosition(params) => self.positions.contains(&params.key_position),
+            DecodedWhirlpoolInstruction::ClosePositionWithTokenExtensions(params) => self.positions.contains(&params.key_position),
+            DecodedWhirlpoolInstruction::CloseBundledPosition(params) => self.positions.contains(&params.key_bundled_position),
+            DecodedWhirlpoolInstruction::TransferLockedPosition(params) => self.positions.contains(&params.key_position),
+            // two-hop swap
+            DecodedWhirlpoolInstruction::TwoHopSwap(params) => filter.contains_whirlpool(&params.key_whirlpool_one) || filter.contains_whirlpool(&params.key_whirlpool_two),
+            DecodedWhirlpoolInstruction::TwoHopSwapV2(params) => filter.contains_whirlpool(&params.key_whirlpool_one) || filter.contains_whirlpool(&params.key_whirlpool_two),
+            // whirlpool
+            DecodedWhirlpoolInstruction::AdminIncreaseLiquidity(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::CollectFees(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::CollectFeesV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::CollectProtocolFees(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::CollectProtocolFeesV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::CollectReward(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::CollectRewardV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::DecreaseLiquidity(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::DecreaseLiquidityV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::IncreaseLiquidity(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::IncreaseLiquidityV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::InitializePool(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::InitializePoolV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::InitializePoolWithAdaptiveFee(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::InitializeReward(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::InitializeRewardV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::InitializeTickArray(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::InitializeDynamicTickArray(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::LockPosition(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::ResetPositionRange(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SetFeeRate(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SetFeeRateByDelegatedFeeAuthority(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SetProtocolFeeRate(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SetRewardAuthority(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SetRewardAuthorityBySuperAuthority(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SetRewardEmissions(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SetRewardEmissionsV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::Swap(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::SwapV2(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::UpdateFeesAndRewards(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            DecodedWhirlpoolInstruction::MigrateRepurposeRewardAuthoritySpace(params) => filter.contains_whirlpool(&params.key_whirlpool),
+            // bundled positions of other whirlpools are not tracked
+            DecodedWhirlpoolInstruction::DeletePositionBundle(_) => false,
+            // config, fee tier, token badge, position bundle initialization
+            _ => true,
+        }
+    }
+
+    fn track_position(&mut self, whirlpool: &String, position: &String) -> bool {
+        if !self.filter.contains_whirlpool(whirlpool) {
+            return false;
+        }
+        self.positions.insert(position.clone());
+        true
+    }
+}
+
+fn find(parent: &mut HashMap<String, String>, whirlpool: String) -> String {
+    let mut root = whirlpool.clone();
+    while let Some(next) = parent.get(&root) {
+        if *next == root {
+            break;
+        }
+        root = next.clone();
+    }
+    // path compression
+    let mut current = whirlpool;
+    while current != root {
+        let next = parent.insert(current, root.clone()).unwrap_or_else(|| root.clone());
+        current = next;
+    }
+    root
+}
+
+fn union(parent: &mut HashMap<String, String>, one: String, two: String) {
+    parent.entry(one.clone()).or_insert_with(|| one.clone());
+    parent.entry(two.clone()).or_insert_with(|| two.clone());
+    let root_one = find(parent, one);
+    let root_two = find(parent, two);
+    if root_one != root_two {
+        parent.insert(root_one, root_two);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Transaction, TransactionInstruction};
+    use crate::test_utils::pubkey;
+
+    fn instruction(name: &str, whirlpool_one: u8, whirlpool_two: u8) -> TransactionInstruction {
+        TransactionInstruction {
+            name: name.to_string(),
+            payload: serde_json::json!({
+                "keyWhirlpool": pubkey(whirlpool_one),
+                "keyWhirlpoolOne": pubkey(whirlpool_one),
+                "keyWhirlpoolTwo": pubkey(whirlpool_two),
+            }),
+        }
+    }
+
+    // one slot per instruction
+    fn transactions(instructions: Vec<TransactionInstruction>) -> Vec<Result<WhirlpoolTransaction, ReplayError>> {
+        instructions
+            .into_iter()
+            .enumerate()
+            .map(|(i, instruction)| {
+                Ok(WhirlpoolTransaction {
+                    slot: i as u64,
+                    block_height: i as u64,
+                    block_time: i as i64,
+                    transactions: vec![Transaction {
+                        index: 0,
+                        signature: String::new(),
+                        payer: String::new(),
+                        balances: vec![],
+                        instructions: vec![instruction],
+                    }],
+                })
+            })
+            .collect()
+    }
+
+    fn expanded(whirlpools: &[u8], instructions: Vec<TransactionInstruction>) -> HashSet<String> {
+        let mut filter = WhirlpoolFilter::new(whirlpools.iter().map(|n| pubkey(*n)));
+        filter.expand_by_two_hop_swaps(transactions(instructions).into_iter()).unwrap();
+        filter.get_whirlpools().clone()
+    }
+
+    fn pubkeys(ns: &[u8]) -> HashSet<String> {
+        ns.iter().map(|n| pubkey(*n)).collect()
+    }
+
+    #[test]
+    fn test_expand_by_two_hop_swaps_transitively() {
+        // 1-2 and 3-4 are connected by 2-3 (appears after them), 5-6 is not connected, swap on 7 is ignored
+        let instructions = || vec![
+            instruction("twoHopSwap", 1, 2),
+            instruction("twoHopSwapV2", 3, 4),
+            instruction("twoHopSwap", 5, 6),
+            instruction("swap", 1, 7),
+            instruction("twoHopSwapV2", 2, 3),
+        ];
+
+        assert_eq!(expanded(&[1], instructions()), pubkeys(&[1, 2, 3, 4]));
+        assert_eq!(expanded(&[4], instructions()), pubkeys(&[1, 2, 3, 4]));
+        assert_eq!(expanded(&[6], instructions()), pubkeys(&[5, 6]));
+        assert_eq!(expanded(&[1, 5], instructions()), pubkeys(&[1, 2, 3, 4, 5, 6]));
+        // not connected by two-hop swap
+        assert_eq!(expanded(&[7], instructions()), pubkeys(&[7]));
+    }
+
+    #[test]
+    fn test_expand_by_two_hop_swaps_long_chain() {
+        // 1-2, 3-4, 5-6, 7-8 and then 2-3, 6-7, 4-5 (the roots are merged in different orders)
+        let instructions = vec![
+            instruction("twoHopSwap", 1, 2),
+            instruction("twoHopSwap", 3, 4),
+            instruction("twoHopSwap", 5, 6),
+            instruction("twoHopSwap", 7, 8),
+            instruction("twoHopSwap", 2, 3),
+            instruction("twoHopSwap", 6, 7),
+            instruction("twoHopSwap", 4, 5),
+        ];
+        assert_eq!(expanded(&[8], instructions), pubkeys(&[1, 2, 3, 4, 5, 6, 7, 8]));
+    }
+
+    fn account(name: &str, whirlpool_offset: usize, len: usize, whirlpool: u8) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        data[0..8].copy_from_slice(&account_discriminator(name));
+        data[whirlpool_offset..whirlpool_offset + 32].copy_from_slice(&[whirlpool; 32]);
+        data
+    }
+
+    #[test]
+    fn test_is_account_needed() {
+        let filter = WhirlpoolFilter::new([pubkey(1)]);
+        let other = pubkey(2);
+
+        // whirlpool is checked by its pubkey
+        let whirlpool = account("Whirlpool", 8, 653, 0);
+        assert!(filter.is_account_needed(&pubkey(1), &whirlpool));
+        assert!(!filter.is_account_needed(&pubkey(2), &whirlpool));
+
+        // accounts belonging to a whirlpool are checked by the whirlpool field
+        let belonging = [
+            ("Position", 8, 216),
+            ("TickArray", FIXED_TICK_ARRAY_LEN - 32, FIXED_TICK_ARRAY_LEN),
+            ("DynamicTickArray", 8 + 4, 8 + 4 + 32 + 16 + 88),
+            ("Oracle", 8, 254),
+            ("LockConfig", 8 + 32 + 32, 128),
+        ];
+        for (name, whirlpool_offset, len) in belonging {
+            assert!(filter.is_account_needed(&other, &account(name, whirlpool_offset, len, 1)), "{}", name);
+            assert!(!filter.is_account_needed(&other, &account(name, whirlpool_offset, len, 2)), "{}", name);
+        }
+
+        // other accounts are always needed
+        assert!(filter.is_account_needed(&other, &account("WhirlpoolsConfig", 8, 108, 2)));
+        assert!(filter.is_account_needed(&other, &account("PositionBundle", 8, 136, 2)));
+        // too short to have the whirlpool field
+        assert!(filter.is_account_needed(&other, &account("Position", 8, 40, 2)[..39]));
+        assert!(filter.is_account_needed(&other, &[0u8; 4]));
+    }
+
+    #[test]
+    fn test_prune_and_collect_positions() {
+        let filter = WhirlpoolFilter::new([pubkey(1)]);
+
+        let mut accounts = AccountDataStore::new_on_memory();
+        accounts.upsert(&pubkey(1), &account("Whirlpool", 8, 653, 0)).unwrap();
+        accounts.upsert(&pubkey(2), &account("Whirlpool", 8, 653, 0)).unwrap();
+        accounts.upsert(&pubkey(11), &account("Position", 8, 216, 1)).unwrap();
+        accounts.upsert(&pubkey(12), &account("Position", 8, 216, 2)).unwrap();
+        accounts.upsert(&pubkey(21), &account("TickArray", FIXED_TICK_ARRAY_LEN - 32, FIXED_TICK_ARRAY_LEN, 1)).unwrap();
+        accounts.upsert(&pubkey(22), &account("TickArray", FIXED_TICK_ARRAY_LEN - 32, FIXED_TICK_ARRAY_LEN, 2)).unwrap();
+        accounts.upsert(&pubkey(31), &account("WhirlpoolsConfig", 8, 108, 2)).unwrap();
+
+        filter.prune(&mut accounts).unwrap();
+
+        let mut remaining = HashSet::new();
+        accounts
+            .traverse(|pubkey, _data| {
+                remaining.insert(pubkey.clone());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(remaining, pubkeys(&[1, 11, 21, 31]));
+        assert_eq!(filter.collect_positions(&accounts).unwrap(), pubkeys(&[11]));
+    }
+}
@@ -1,65 +1,288 @@
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use replay_engine::{account_data_store::AccountDataStore, types::Slot};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
 use reqwest;
 use std::{
     fs::File,
-    io::{BufRead, BufReader, BufWriter},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
 };
 
+use crate::errors::ReplayError;
+use crate::filter::WhirlpoolFilter;
 use crate::schema::*;
 use crate::serde::*;
 
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC_BYTES: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const ZSTD_COMPRESSION_LEVEL: i32 = 3;
+const STATE_FILE_HEADER_MAX_LEN: u64 = 4096;
+
+// IDL related accounts owned by the whirlpool program (not included in state files)
+const WHIRLPOOL_IDL_ACCOUNTS: [&str; 2] = [
+    "8if9aDeshh3iGLasCCzBGQyxAvU8Q4jUZGF3N5sVm8GE",
+    "2KFqE4RWoPVbvodo8vbggCFeHPS8TDvgpwp79ALMrcyn",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    // order to try when looking for a file (Zstd is smaller and faster to decompress)
+    pub const PRIORITY: [Compression; 2] = [Compression::Zstd, Compression::Gzip];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    // .zst is Zstd, others are treated as GZip (default format of the archive)
+    pub fn from_path(path: &String) -> Compression {
+        if path.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::Gzip
+        }
+    }
+
+    pub fn from_magic_bytes(bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(&ZSTD_MAGIC_BYTES) {
+            Some(Compression::Zstd)
+        } else if bytes.starts_with(&GZIP_MAGIC_BYTES) {
+            Some(Compression::Gzip)
+        } else {
+            None
+        }
+    }
+}
+
 pub fn get_whirlpool_state_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_state_file_relative_path_with_compression(date, Compression::Gzip)
+}
+
+pub fn get_whirlpool_state_file_relative_path_with_compression(date: &chrono::NaiveDate, compression: Compression) -> String {
     format!(
-        "{}/{}/whirlpool-state-{}.json.gz",
+        "{}/{}/whirlpool-state-{}.json.{}",
         date.format("%Y"),
         date.format("%m%d"),
         date.format("%Y%m%d"),
+        compression.extension(),
     )
 }
 
+pub fn get_whirlpool_state_file_relative_paths(date: &chrono::NaiveDate) -> Vec<String> {
+    Compression::PRIORITY
+        .iter()
+        .map(|compression| get_whirlpool_state_file_relative_path_with_compression(date, *compression))
+        .collect()
+}
+
 pub fn get_whirlpool_transaction_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_transaction_file_relative_path_with_compression(date, Compression::Gzip)
+}
+
+pub fn get_whirlpool_transaction_file_relative_path_with_compression(date: &chrono::NaiveDate, compression: Compression) -> String {
     format!(
-        "{}/{}/whirlpool-transaction-{}.jsonl.gz",
+        "{}/{}/whirlpool-transaction-{}.jsonl.{}",
         date.format("%Y"),
         date.format("%m%d"),
         date.format("%Y%m%d"),
+        compression.extension(),
     )
 }
 
+pub fn get_whirlpool_transaction_file_relative_paths(date: &chrono::NaiveDate) -> Vec<String> {
+    Compression::PRIORITY
+        .iter()
+        .map(|compression| get_whirlpool_transaction_file_relative_path_with_compression(date, *compression))
+        .collect()
+}
+
 pub fn get_whirlpool_token_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_token_file_relative_path_with_compression(date, Compression::Gzip)
+}
+
+pub fn get_whirlpool_token_file_relative_path_with_compression(date: &chrono::NaiveDate, compression: Compression) -> String {
     format!(
-        "{}/{}/whirlpool-token-{}.json.gz",
+        "{}/{}/whirlpool-token-{}.json.{}",
         date.format("%Y"),
         date.format("%m%d"),
         date.format("%Y%m%d"),
+        compression.extension(),
     )
 }
 
-pub fn load_from_local_whirlpool_state_file(file_path: &String, account_data_store_config: &AccountDataStoreConfig) -> WhirlpoolState {
-    let file = File::open(file_path).unwrap();
-    let decoder = GzDecoder::new(file);
+pub fn get_whirlpool_token_file_relative_paths(date: &chrono::NaiveDate) -> Vec<String> {
+    Compression::PRIORITY
+        .iter()
+        .map(|compression| get_whirlpool_token_file_relative_path_with_compression(date, *compression))
+        .collect()
+}
+
+pub fn get_whirlpool_event_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_event_file_relative_path_with_compression(date, Compression::Gzip)
+}
+
+pub fn get_whirlpool_event_file_relative_path_with_compression(date: &chrono::NaiveDate, compression: Compression) -> String {
+    format!(
+        "{}/{}/whirlpool-event-{}.jsonl.{}",
+        date.format("%Y"),
+        date.format("%m%d"),
+        date.format("%Y%m%d"),
+        compression.extension(),
+    )
+}
+
+pub fn get_whirlpool_event_file_relative_paths(date: &chrono::NaiveDate) -> Vec<String> {
+    Compression::PRIORITY
+        .iter()
+        .map(|compression| get_whirlpool_event_file_relative_path_with_compression(date, *compression))
+        .collect()
+}
+
+pub fn get_whirlpool_ohlcv_daily_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_ohlcv_file_relative_path(date, "daily", "json")
+}
+
+pub fn get_whirlpool_ohlcv_daily_csv_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_ohlcv_file_relative_path(date, "daily", "csv")
+}
+
+pub fn get_whirlpool_ohlcv_minutely_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_ohlcv_file_relative_path(date, "minutely", "json")
+}
+
+pub fn get_whirlpool_ohlcv_minutely_csv_file_relative_path(date: &chrono::NaiveDate) -> String {
+    get_whirlpool_ohlcv_file_relative_path(date, "minutely", "csv")
+}
+
+fn get_whirlpool_ohlcv_file_relative_path(date: &chrono::NaiveDate, interval: &str, format: &str) -> String {
+    format!(
+        "{}/{}/whirlpool-ohlcv-{}-{}.{}.{}",
+        date.format("%Y"),
+        date.format("%m%d"),
+        interval,
+        date.format("%Y%m%d"),
+        format,
+        Compression::Gzip.extension(),
+    )
+}
+
+// the first relative path that exists under base_path
+pub fn resolve_local_relative_path(base_path: &String, relative_paths: &Vec<String>) -> Option<String> {
+    relative_paths
+        .iter()
+        .find(|relative_path| std::path::Path::new(&format!("{}/{}", base_path, relative_path)).exists())
+        .cloned()
+}
+
+// the first relative path that is available on the remote storage (checked with HEAD request)
+pub fn resolve_remote_relative_path(base_url: &String, relative_paths: &Vec<String>) -> Result<Option<String>, ReplayError> {
+    let client = reqwest::blocking::Client::new();
+    for relative_path in relative_paths {
+        let response = client.head(format!("{}/{}", base_url, relative_path)).send()?;
+        if response.status().is_success() {
+            return Ok(Some(relative_path.clone()));
+        }
+    }
+    Ok(None)
+}
+
+// relative_paths are candidates in priority order (the last one is used if none is found)
+pub fn local_file_path(base_path: &String, relative_paths: &Vec<String>) -> String {
+    let relative_path = resolve_local_relative_path(base_path, relative_paths)
+        .unwrap_or_else(|| relative_paths[relative_paths.len() - 1].clone());
+    format!("{}/{}", base_path, relative_path)
+}
+
+pub fn remote_file_url(base_url: &String, relative_paths: &Vec<String>) -> Result<String, ReplayError> {
+    let relative_path = resolve_remote_relative_path(base_url, relative_paths)?
+        .unwrap_or_else(|| relative_paths[relative_paths.len() - 1].clone());
+    Ok(format!("{}/{}", base_url, relative_path))
+}
+
+// download the file into the cache directory if no cached file is available in any format
+pub fn cached_file_path(
+    base_url: &String,
+    cache_dir_path: &String,
+    relative_paths: &Vec<String>,
+    refresh: bool,
+) -> Result<String, ReplayError> {
+    if !refresh {
+        if let Some(relative_path) = resolve_local_relative_path(cache_dir_path, relative_paths) {
+            return Ok(format!("{}/{}", cache_dir_path, relative_path));
+        }
+    }
+
+    let relative_path = resolve_remote_relative_path(base_url, relative_paths)?
+        .unwrap_or_else(|| relative_paths[relative_paths.len() - 1].clone());
+    let file_url = format!("{}/{}", base_url, relative_path);
+    let file_path = format!("{}/{}", cache_dir_path, relative_path);
+    download_from_remote_storage(&file_url, &file_path)?;
+    Ok(file_path)
+}
+
+// detect the compression from the magic bytes (GZip and Zstd)
+pub fn decompress<R: Read + Send + 'static>(reader: R) -> Result<Box<dyn Read + Send>, ReplayError> {
+    let mut buf = BufReader::new(reader);
+    let magic_bytes = buf.fill_buf()?;
+    match Compression::from_magic_bytes(magic_bytes) {
+        Some(Compression::Zstd) => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(buf)?)),
+        Some(Compression::Gzip) => Ok(Box::new(GzDecoder::new(buf))),
+        None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown compression format").into()),
+    }
+}
+
+pub fn load_from_local_whirlpool_state_file(file_path: &String, account_data_store_config: &AccountDataStoreConfig) -> Result<WhirlpoolState, ReplayError> {
+    let file = File::open(file_path)?;
+    let decoder = decompress(file)?;
     let reader = BufReader::new(decoder);
     deserialize_whirlpool_state_from_reader(reader, account_data_store_config.clone())
 }
 
-pub fn load_from_remote_whirlpool_state_file(url: &String, account_data_store_config: &AccountDataStoreConfig) -> WhirlpoolState {
-    let response = reqwest::blocking::get(url).unwrap();
-    let decoder = GzDecoder::new(response);
+pub fn load_from_remote_whirlpool_state_file(url: &String, account_data_store_config: &AccountDataStoreConfig) -> Result<WhirlpoolState, ReplayError> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
+    let decoder = decompress(response)?;
     let reader = BufReader::new(decoder);
     deserialize_whirlpool_state_from_reader(reader, account_data_store_config.clone())
 }
 
+// only the accounts needed by the filter are loaded
+pub fn load_from_local_whirlpool_state_file_with_filter(
+    file_path: &String,
+    account_data_store_config: &AccountDataStoreConfig,
+    filter: &WhirlpoolFilter,
+) -> Result<WhirlpoolState, ReplayError> {
+    let file = File::open(file_path)?;
+    let decoder = decompress(file)?;
+    let reader = BufReader::new(decoder);
+    deserialize_whirlpool_state_from_reader_with_filter(reader, account_data_store_config.clone(), filter.clone())
+}
+
+pub fn load_from_remote_whirlpool_state_file_with_filter(
+    url: &String,
+    account_data_store_config: &AccountDataStoreConfig,
+    filter: &WhirlpoolFilter,
+) -> Result<WhirlpoolState, ReplayError> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
+    let decoder = decompress(response)?;
+    let reader = BufReader::new(decoder);
+    deserialize_whirlpool_state_from_reader_with_filter(reader, account_data_store_config.clone(), filter.clone())
+}
+
+// the compression is determined by the extension (.zst or .gz)
 pub fn save_to_whirlpool_state_file(
     file_path: &String,
     slot: &Slot,
     program_data: &Vec<u8>,
     accounts: &AccountDataStore,
-) {
-    let file = File::create(file_path).unwrap();
-    let encoder = GzEncoder::new(file, flate2::Compression::default());
-    let writer = BufWriter::new(encoder);
+) -> Result<(), ReplayError> {
+    let file = File::create(file_path)?;
     let serializer = WhirlpoolStateSerializer {
         slot: slot.slot,
         block_height: slot.block_height,
@@ -67,60 +290,502 @@ pub fn save_to_whirlpool_state_file(
         program_data,
         accounts,
     };
-    serde_json::to_writer(writer, &serializer).unwrap();
+    write_compressed_json(file, Compression::from_path(file_path), &serializer)
+}
+
+// accounts are sorted by pubkey, so the same state is always saved into the same bytes (for content hashing and diffing)
+// the compression is determined by the extension (.zst or .gz)
+pub fn save_to_whirlpool_state_file_canonical(
+    file_path: &String,
+    slot: &Slot,
+    program_data: &Vec<u8>,
+    accounts: &AccountDataStore,
+) -> Result<(), ReplayError> {
+    let file = File::create(file_path)?;
+    let serializer = WhirlpoolCanonicalStateSerializer {
+        slot: slot.slot,
+        block_height: slot.block_height,
+        block_time: slot.block_time,
+        program_data,
+        accounts,
+    };
+    write_compressed_json(file, Compression::from_path(file_path), &serializer)
+}
+
+// only the beginning of the file is read (slot, blockHeight and blockTime precede accounts in state files)
+pub fn load_slot_from_local_whirlpool_state_file(file_path: &String) -> Result<Slot, ReplayError> {
+    let file = File::open(file_path)?;
+    let decoder = decompress(file)?;
+    read_whirlpool_state_slot(decoder)
+}
+
+pub fn load_slot_from_remote_whirlpool_state_file(url: &String) -> Result<Slot, ReplayError> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
+    let decoder = decompress(response)?;
+    read_whirlpool_state_slot(decoder)
+}
+
+fn read_whirlpool_state_slot(reader: impl Read) -> Result<Slot, ReplayError> {
+    #[derive(serde_derive::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct WhirlpoolStateHeader {
+        slot: u64,
+        block_height: u64,
+        block_time: i64,
+    }
+
+    let mut head = vec![];
+    reader.take(STATE_FILE_HEADER_MAX_LEN).read_to_end(&mut head)?;
+    let accounts_pos = head
+        .windows(b"\"accounts\"".len())
+        .position(|window| window == b"\"accounts\"")
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "accounts not found at the beginning of the state file"))?;
+
+    // {"slot":..,"blockHeight":..,"blockTime":.., + }
+    let mut header = head[..accounts_pos].to_vec();
+    while header.last().is_some_and(|b| *b == b',' || b.is_ascii_whitespace()) {
+        header.pop();
+    }
+    header.push(b'}');
+    let header: WhirlpoolStateHeader = serde_json::from_slice(&header)?;
+    Ok(Slot::new(header.slot, header.block_height, header.block_time))
+}
+
+// compact and full-columns files are both accepted (the data is the last column)
+// IDL accounts are skipped, so the result can be compared with state files
+pub fn load_from_local_whirlpool_snapshot_csv_file(file_path: &String, account_data_store_config: &AccountDataStoreConfig) -> Result<AccountDataStore, ReplayError> {
+    let mut store = match account_data_store_config {
+        AccountDataStoreConfig::OnMemory => AccountDataStore::new_on_memory(),
+        AccountDataStoreConfig::OnDisk(path) => AccountDataStore::new_on_disk(path.clone()),
+    };
+    traverse_whirlpool_snapshot_csv_file(file_path, |pubkey, data| {
+        if is_whirlpool_idl_account(pubkey) {
+            return Ok(());
+        }
+        let data = decode_snapshot_csv_data(pubkey, data)?;
+        store.upsert(&pubkey.to_string(), &data).map_err(|e| ReplayError::AccountDataStore(e.to_string()))
+    })?;
+    Ok(store)
+}
+
+// callback receives the pubkey and the base64 encoded data of each line (in the file order, IDL accounts included)
+pub fn traverse_whirlpool_snapshot_csv_file<F>(file_path: &String, mut callback: F) -> Result<(), ReplayError>
+where
+    F: FnMut(&str, &str) -> Result<(), ReplayError>,
+{
+    let file = File::open(file_path)?;
+    let decoder = decompress(file)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(BufReader::new(decoder));
+
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        if record.len() < 2 {
+            continue;
+        }
+        callback(&record[0], &record[record.len() - 1])?;
+    }
+    Ok(())
+}
+
+pub fn decode_snapshot_csv_data(pubkey: &str, data: &str) -> Result<Vec<u8>, ReplayError> {
+    BASE64_STANDARD.decode(data).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid account data of {}: {}", pubkey, e)).into()
+    })
+}
+
+pub fn is_whirlpool_idl_account(pubkey: &str) -> bool {
+    WHIRLPOOL_IDL_ACCOUNTS.contains(&pubkey)
+}
+
+pub fn load_from_local_whirlpool_checkpoint_file(file_path: &String, account_data_store_config: &AccountDataStoreConfig) -> Result<WhirlpoolCheckpoint, ReplayError> {
+    let file = File::open(file_path)?;
+    let decoder = decompress(file)?;
+    let reader = BufReader::new(decoder);
+    deserialize_whirlpool_checkpoint_from_reader(reader, account_data_store_config.clone())
+}
+
+// written into a temporary file and renamed, so an interrupted save never breaks the previous checkpoint
+pub fn save_to_whirlpool_checkpoint_file(
+    file_path: &String,
+    slot: &Slot,
+    position: &ReplayPosition,
+    program_data: &Vec<u8>,
+    accounts: &AccountDataStore,
+) -> Result<(), ReplayError> {
+    let temporary_file_path = format!("{}.tmp", file_path);
+    let file = File::create(&temporary_file_path)?;
+    let serializer = WhirlpoolCheckpointSerializer {
+        slot: slot.slot,
+        block_height: slot.block_height,
+        block_time: slot.block_time,
+        position,
+        program_data,
+        accounts,
+    };
+    write_compressed_json(file, Compression::from_path(file_path), &serializer)?;
+    std::fs::rename(&temporary_file_path, file_path)?;
+    Ok(())
+}
+
+fn write_compressed_json(file: File, compression: Compression, value: &impl ::serde::Serialize) -> Result<(), ReplayError> {
+    write_compressed(file, compression, |writer| Ok(serde_json::to_writer(writer, value)?))
+}
+
+// create the file (and its parent directories) and write into it through the encoder
+// the compression is determined by the extension (.zst or .gz)
+pub fn save_to_compressed_file<F>(file_path: &String, write: F) -> Result<(), ReplayError>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), ReplayError>,
+{
+    if let Some(parent) = std::path::Path::new(file_path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = File::create(file_path)?;
+    write_compressed(file, Compression::from_path(file_path), write)
+}
+
+// streaming writer into a compressed file (finish must be called to complete the compression)
+// the compression is determined by the extension (.zst or .gz)
+pub struct CompressedFileWriter {
+    encoder: CompressedEncoder,
+}
+
+enum CompressedEncoder {
+    Gzip(BufWriter<GzEncoder<File>>),
+    Zstd(BufWriter<zstd::stream::write::Encoder<'static, File>>),
+}
+
+impl CompressedFileWriter {
+    // create the file (and its parent directories)
+    pub fn create(file_path: &String) -> Result<CompressedFileWriter, ReplayError> {
+        CompressedFileWriter::create_with_compression(file_path, Compression::from_path(file_path))
+    }
+
+    // for temporary files (the extension of the temporary file doesn't tell the compression)
+    pub fn create_with_compression(file_path: &String, compression: Compression) -> Result<CompressedFileWriter, ReplayError> {
+        if let Some(parent) = std::path::Path::new(file_path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = File::create(file_path)?;
+        let encoder = match compression {
+            Compression::Zstd => CompressedEncoder::Zstd(BufWriter::new(zstd::stream::write::Encoder::new(file, ZSTD_COMPRESSION_LEVEL)?)),
+            Compression::Gzip => CompressedEncoder::Gzip(BufWriter::new(GzEncoder::new(file, flate2::Compression::default()))),
+        };
+        Ok(CompressedFileWriter { encoder })
+    }
+
+    pub fn finish(self) -> Result<(), ReplayError> {
+        match self.encoder {
+            CompressedEncoder::Zstd(writer) => {
+                let encoder = writer.into_inner().map_err(|e| ReplayError::Io(e.into_error()))?;
+                encoder.finish()?.sync_all()?;
+            }
+            CompressedEncoder::Gzip(writer) => {
+                let encoder = writer.into_inner().map_err(|e| ReplayError::Io(e.into_error()))?;
+                encoder.finish()?.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for CompressedFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.encoder {
+            CompressedEncoder::Zstd(writer) => writer.write(buf),
+            CompressedEncoder::Gzip(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.encoder {
+            CompressedEncoder::Zstd(writer) => writer.flush(),
+            CompressedEncoder::Gzip(writer) => writer.flush(),
+        }
+    }
+}
+
+fn write_compressed<F>(file: File, compression: Compression, write: F) -> Result<(), ReplayError>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), ReplayError>,
+{
+    match compression {
+        Compression::Zstd => {
+            let encoder = zstd::stream::write::Encoder::new(file, ZSTD_COMPRESSION_LEVEL)?;
+            let encoder = write_buffered(encoder, write)?;
+            encoder.finish()?.sync_all()?;
+        }
+        Compression::Gzip => {
+            let encoder = GzEncoder::new(file, flate2::Compression::default());
+            let encoder = write_buffered(encoder, write)?;
+            encoder.finish()?.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+// returns the inner writer to finish the compression explicitly
+fn write_buffered<W: Write, F>(inner: W, write: F) -> Result<W, ReplayError>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), ReplayError>,
+{
+    let mut writer = BufWriter::new(inner);
+    write(&mut writer)?;
+    writer.into_inner().map_err(|e| ReplayError::Io(e.into_error()))
 }
 
 pub fn load_from_local_whirlpool_transaction_file(
     file_path: &String,
-) -> impl Iterator<Item = WhirlpoolTransaction> {
-    let file = File::open(file_path).unwrap();
+) -> Result<impl Iterator<Item = Result<WhirlpoolTransaction, ReplayError>>, ReplayError> {
+    let file = File::open(file_path)?;
 
-    let decoder = GzDecoder::new(file);
+    let decoder = decompress(file)?;
     let buf = BufReader::new(decoder);
 
-    let iter = buf.lines().map(|jsonl| jsonl.unwrap()).map(|jsonl| {
-        let t: Result<WhirlpoolTransaction, serde_json::Error> =
-            serde_json::from_str(jsonl.as_str());
-        return t.unwrap();
-    });
-
-    return iter;
+    let file_path = file_path.clone();
+    return Ok(buf.lines().enumerate().map(move |(i, jsonl)| parse_whirlpool_transaction_line(&file_path, i + 1, jsonl)));
 }
 
 pub fn load_from_remote_whirlpool_transaction_file(
     url: &String,
-) -> impl Iterator<Item = WhirlpoolTransaction> {
-    let response = reqwest::blocking::get(url).unwrap();
+) -> Result<impl Iterator<Item = Result<WhirlpoolTransaction, ReplayError>>, ReplayError> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
 
-    let decoder = GzDecoder::new(response);
+    let decoder = decompress(response)?;
     let buf = BufReader::new(decoder);
 
-    let iter = buf.lines().map(|jsonl| jsonl.unwrap()).map(|jsonl| {
-        let t: Result<WhirlpoolTransaction, serde_json::Error> =
-            serde_json::from_str(jsonl.as_str());
-        return t.unwrap();
-    });
+    let url = url.clone();
+    return Ok(buf.lines().enumerate().map(move |(i, jsonl)| parse_whirlpool_transaction_line(&url, i + 1, jsonl)));
+}
+
+// location (file path or URL) and line_number (1-based) are reported if the line is broken
+pub(crate) fn parse_whirlpool_transaction_line(location: &String, line_number: usize, jsonl: std::io::Result<String>) -> Result<WhirlpoolTransaction, ReplayError> {
+    let jsonl = jsonl?;
+    let t: WhirlpoolTransaction = serde_json::from_str(jsonl.as_str())
+        .map_err(|source| ReplayError::JsonLine { location: location.clone(), line_number, source })?;
+    return Ok(t);
+}
+
+pub fn load_from_local_whirlpool_event_file(
+    file_path: &String,
+) -> Result<impl Iterator<Item = Result<WhirlpoolEvent, ReplayError>>, ReplayError> {
+    let file = File::open(file_path)?;
+
+    let decoder = decompress(file)?;
+    let buf = BufReader::new(decoder);
+
+    let file_path = file_path.clone();
+    return Ok(buf.lines().enumerate().map(move |(i, jsonl)| parse_whirlpool_event_line(&file_path, i + 1, jsonl)));
+}
+
+pub fn load_from_remote_whirlpool_event_file(
+    url: &String,
+) -> Result<impl Iterator<Item = Result<WhirlpoolEvent, ReplayError>>, ReplayError> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
 
-    return iter;
+    let decoder = decompress(response)?;
+    let buf = BufReader::new(decoder);
+
+    let url = url.clone();
+    return Ok(buf.lines().enumerate().map(move |(i, jsonl)| parse_whirlpool_event_line(&url, i + 1, jsonl)));
+}
+
+fn parse_whirlpool_event_line(location: &String, line_number: usize, jsonl: std::io::Result<String>) -> Result<WhirlpoolEvent, ReplayError> {
+    let jsonl = jsonl?;
+    let e: WhirlpoolEvent = serde_json::from_str(jsonl.as_str())
+        .map_err(|source| ReplayError::JsonLine { location: location.clone(), line_number, source })?;
+    return Ok(e);
 }
 
-pub fn load_from_local_whirlpool_token_file(file_path: &String) -> WhirlpoolToken {
-    let file = File::open(file_path).unwrap();
-    let decoder = GzDecoder::new(file);
+pub fn load_from_local_whirlpool_repro_bundle_file(file_path: &String) -> Result<WhirlpoolReproBundle, ReplayError> {
+    let file = File::open(file_path)?;
+    let decoder = decompress(file)?;
     let reader = BufReader::new(decoder);
-    return serde_json::from_reader(reader).unwrap();
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn save_to_whirlpool_repro_bundle_file(file_path: &String, bundle: &WhirlpoolReproBundle) -> Result<(), ReplayError> {
+    save_to_compressed_file(file_path, |writer| Ok(serde_json::to_writer(writer, bundle)?))
 }
 
-pub fn load_from_remote_whirlpool_token_file(url: &String) -> WhirlpoolToken {
-    let response = reqwest::blocking::get(url).unwrap();
-    let decoder = GzDecoder::new(response);
+pub fn load_from_local_whirlpool_token_file(file_path: &String) -> Result<WhirlpoolToken, ReplayError> {
+    let file = File::open(file_path)?;
+    let decoder = decompress(file)?;
     let reader = BufReader::new(decoder);
-    return serde_json::from_reader(reader).unwrap();
+    return Ok(serde_json::from_reader(reader)?);
+}
+
+pub fn load_from_remote_whirlpool_token_file(url: &String) -> Result<WhirlpoolToken, ReplayError> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
+    let decoder = decompress(response)?;
+    let reader = BufReader::new(decoder);
+    return Ok(serde_json::from_reader(reader)?);
+}
+
+pub fn download_from_remote_storage(url: &String, file_path: &String) -> Result<(), ReplayError> {
+    let mut response = reqwest::blocking::get(url)?.error_for_status()?;
+    if let Some(parent) = std::path::Path::new(file_path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(file_path)?;
+    std::io::copy(&mut response, &mut file)?;
+    Ok(())
 }
 
-pub fn download_from_remote_storage(url: &String, file_path: &String) {
-    let mut response = reqwest::blocking::get(url).unwrap();
-    std::fs::create_dir_all(std::path::Path::new(file_path).parent().unwrap()).unwrap();
-    let mut file = File::create(file_path).unwrap();
-    std::io::copy(&mut response, &mut file).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_STATE_FILE_20220406: &str = "../data/sample_local_storage/2022/0406/whirlpool-state-20220406.json.gz";
+    const SAMPLE_STATE_SLOT_20220406: u64 = 128556169;
+
+    const STATE_JSON: &str = r#"{"slot":100,"blockHeight":90,"blockTime":1700000000,"accounts":[{"pubkey":"11111111111111111111111111111111","data":"AQID"}],"programData":"AA=="}"#;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zstd(bytes: &[u8]) -> Vec<u8> {
+        zstd::stream::encode_all(bytes, ZSTD_COMPRESSION_LEVEL).unwrap()
+    }
+
+    fn decompress_to_vec(compressed: Vec<u8>) -> Vec<u8> {
+        let mut decompressed = vec![];
+        decompress(std::io::Cursor::new(compressed)).unwrap().read_to_end(&mut decompressed).unwrap();
+        decompressed
+    }
+
+    #[test]
+    fn test_compression_from_magic_bytes() {
+        assert_eq!(Compression::from_magic_bytes(&gzip(b"whirlpool")), Some(Compression::Gzip));
+        assert_eq!(Compression::from_magic_bytes(&zstd(b"whirlpool")), Some(Compression::Zstd));
+        assert_eq!(Compression::from_magic_bytes(STATE_JSON.as_bytes()), None);
+        // shorter than the magic bytes
+        assert_eq!(Compression::from_magic_bytes(&[0x28, 0xb5]), None);
+        assert_eq!(Compression::from_magic_bytes(&[]), None);
+    }
+
+    #[test]
+    fn test_compression_from_path() {
+        assert_eq!(Compression::from_path(&"whirlpool-state-20240101.json.zst".to_string()), Compression::Zstd);
+        assert_eq!(Compression::from_path(&"whirlpool-state-20240101.json.gz".to_string()), Compression::Gzip);
+        assert_eq!(Compression::from_path(&"whirlpool-state-20240101.json".to_string()), Compression::Gzip);
+    }
+
+    #[test]
+    fn test_decompress_detects_compression() {
+        assert_eq!(decompress_to_vec(gzip(STATE_JSON.as_bytes())), STATE_JSON.as_bytes());
+        assert_eq!(decompress_to_vec(zstd(STATE_JSON.as_bytes())), STATE_JSON.as_bytes());
+        assert!(decompress(std::io::Cursor::new(STATE_JSON.as_bytes().to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_read_whirlpool_state_slot() {
+        let slot = read_whirlpool_state_slot(STATE_JSON.as_bytes()).unwrap();
+        assert_eq!(slot, Slot::new(100, 90, 1700000000));
+
+        // pretty printed
+        let pretty = "{\n  \"slot\": 100,\n  \"blockHeight\": 90,\n  \"blockTime\": 1700000000,\n  \"accounts\": [],\n  \"programData\": \"\"\n}";
+        assert_eq!(read_whirlpool_state_slot(pretty.as_bytes()).unwrap(), Slot::new(100, 90, 1700000000));
+    }
+
+    #[test]
+    fn test_read_whirlpool_state_slot_reads_only_header() {
+        // accounts must appear in the first STATE_FILE_HEADER_MAX_LEN bytes
+        let padding = " ".repeat(STATE_FILE_HEADER_MAX_LEN as usize);
+        let padded = format!("{{\"slot\":100,\"blockHeight\":90,\"blockTime\":1700000000,{}\"accounts\":[]}}", padding);
+        assert!(read_whirlpool_state_slot(padded.as_bytes()).is_err());
+
+        // accounts precede programData (program data is too large to be in the header)
+        let program_data = BASE64_STANDARD.encode(vec![0u8; STATE_FILE_HEADER_MAX_LEN as usize]);
+        let reordered = format!("{{\"slot\":100,\"blockHeight\":90,\"blockTime\":1700000000,\"programData\":\"{}\",\"accounts\":[]}}", program_data);
+        assert!(read_whirlpool_state_slot(reordered.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_load_slot_from_local_whirlpool_state_file() {
+        let slot = load_slot_from_local_whirlpool_state_file(&SAMPLE_STATE_FILE_20220406.to_string()).unwrap();
+        assert_eq!(slot.slot, SAMPLE_STATE_SLOT_20220406);
+
+        let dir = tempfile::tempdir().unwrap();
+        for compression in Compression::PRIORITY {
+            let file_path = dir.path().join(format!("whirlpool-state.json.{}", compression.extension())).to_str().unwrap().to_string();
+            save_to_compressed_file(&file_path, |writer| Ok(writer.write_all(STATE_JSON.as_bytes())?)).unwrap();
+
+            let mut magic_bytes = vec![];
+            File::open(&file_path).unwrap().take(4).read_to_end(&mut magic_bytes).unwrap();
+            assert_eq!(Compression::from_magic_bytes(&magic_bytes), Some(compression));
+            assert_eq!(load_slot_from_local_whirlpool_state_file(&file_path).unwrap(), Slot::new(100, 90, 1700000000));
+        }
+    }
+
+    #[test]
+    fn test_load_from_local_whirlpool_state_file_zstd() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("whirlpool-state.json.zst").to_str().unwrap().to_string();
+        let mut writer = CompressedFileWriter::create(&file_path).unwrap();
+        writer.write_all(STATE_JSON.as_bytes()).unwrap();
+        writer.finish().unwrap();
+
+        let state = load_from_local_whirlpool_state_file(&file_path, &AccountDataStoreConfig::OnMemory).unwrap();
+        assert_eq!(state.slot, 100);
+        assert_eq!(state.program_data, vec![0u8]);
+        assert_eq!(state.accounts.get(&"11111111111111111111111111111111".to_string()).unwrap(), Some(vec![1u8, 2, 3]));
+    }
+
+    #[test]
+    fn test_save_to_whirlpool_state_file_canonical_is_deterministic() {
+        let accounts = [
+            ("11111111111111111111111111111111".to_string(), vec![1u8, 2, 3]),
+            ("So11111111111111111111111111111111111111112".to_string(), vec![4u8; 8]),
+            ("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".to_string(), vec![5u8; 16]),
+        ];
+        let slot = Slot::new(100, 90, 1700000000);
+        let program_data = vec![0u8, 1, 2];
+
+        let dir = tempfile::tempdir().unwrap();
+        for compression in Compression::PRIORITY {
+            let save = |name: &str, reversed: bool| {
+                // the insertion order must not affect the output
+                let mut store = AccountDataStore::new_on_memory();
+                let mut ordered = accounts.iter().collect::<Vec<_>>();
+                if reversed {
+                    ordered.reverse();
+                }
+                for (pubkey, data) in ordered {
+                    store.upsert(pubkey, data).unwrap();
+                }
+                let file_path = dir.path().join(format!("{}.json.{}", name, compression.extension())).to_str().unwrap().to_string();
+                save_to_whirlpool_state_file_canonical(&file_path, &slot, &program_data, &store).unwrap();
+                std::fs::read(&file_path).unwrap()
+            };
+
+            let first = save("first", false);
+            assert_eq!(first, save("second", false));
+            assert_eq!(first, save("reversed", true));
+        }
+    }
+
+    #[test]
+    fn test_load_from_local_whirlpool_transaction_file_reports_broken_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("whirlpool-transaction.jsonl.gz").to_str().unwrap().to_string();
+        let jsonl = "{\"slot\":1,\"blockHeight\":1,\"blockTime\":1,\"transactions\":[]}\n{\"slot\":2,\"blockHeight\":2,\n";
+        std::fs::write(&file_path, gzip(jsonl.as_bytes())).unwrap();
+
+        let mut transactions = load_from_local_whirlpool_transaction_file(&file_path).unwrap();
+        assert_eq!(transactions.next().unwrap().unwrap().slot, 1);
+        match transactions.next().unwrap() {
+            Err(ReplayError::JsonLine { location, line_number, .. }) => {
+                assert_eq!(location, file_path);
+                assert_eq!(line_number, 2);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|t| t.slot)),
+        }
+    }
 }
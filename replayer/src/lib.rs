@@ -1,22 +1,50 @@
+use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::rc::Rc;
 
 use replay_engine::{account_data_store::AccountDataStore, types::WritableAccountSnapshot};
 use replay_engine::decoded_instructions;
 use replay_engine::decoded_instructions::DecodedWhirlpoolInstruction;
+use replay_engine::errors::ErrorCode;
 use replay_engine::replay_engine::ReplayEngine;
 
+pub use replay_engine::replay_environment::{ExecutionRecord, RecordedInstruction, RecordedReturnData};
+pub use replay_engine::program_events::ProgramEvent;
 pub use replay_engine::replay_instruction::ReplayInstructionResult;
+pub use replay_engine::transfer_verification::TokenAmountMismatch;
 use replay_engine::types::ProgramData;
 pub use replay_engine::types::{AccountSnapshot, Slot};
 
+#[cfg(feature = "mysql")]
+pub mod database;
+pub mod decoder;
+pub mod convert;
+pub mod diff;
+pub mod errors;
+pub mod event;
+pub mod filter;
 pub mod io;
+pub mod liquidity;
+pub mod ohlcv;
+pub mod parallel;
+pub mod reference_snapshot;
+pub mod repro;
 pub mod schema;
 pub mod serde;
+pub mod source;
+pub mod state_diff;
+pub mod trade;
+pub mod verify;
+#[cfg(test)]
+mod test_utils;
 
-use schema::{Transaction, WhirlpoolTransaction};
+pub use errors::ReplayError;
+pub use filter::WhirlpoolFilter;
+pub use source::TransactionSource;
+use schema::{InstructionPosition, ReplayPosition, Transaction, WhirlpoolState, WhirlpoolTransaction};
 use serde::AccountDataStoreConfig;
 use tokio::sync::Mutex;
 
@@ -26,6 +54,32 @@ pub enum ReplayUntil {
     Slot(u64),
     BlockHeight(u64),
     BlockTime(i64),
+    // stop at the end of the day (UTC, based on blockTime)
+    Date(chrono::NaiveDate),
+}
+
+// the token amounts moved by the replayed instruction differ from the recorded ones (see set_token_transfer_verification)
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TokenTransferMismatchReport {
+    pub slot: u64,
+    pub signature: String,
+    pub instruction_index: usize,
+    pub name: String,
+    pub mismatches: Vec<TokenAmountMismatch>,
+}
+
+impl fmt::Display for TokenTransferMismatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "slot: {}, signature: {}, instruction: {} ({}): {}",
+            self.slot,
+            self.signature,
+            self.instruction_index,
+            self.name,
+            self.mismatches.iter().map(|m| m.to_string()).collect::<Vec<String>>().join(", "),
+        )
+    }
 }
 
 pub type SyncSlotCallback = Rc<
@@ -42,6 +96,8 @@ pub type SyncInstructionCallback = Rc<
         &DecodedWhirlpoolInstruction,
         &AccountDataStore,
         &WritableAccountSnapshot,
+        // None if the execution recording is disabled
+        Option<&ExecutionRecord>,
     )
 >;
 
@@ -59,124 +115,472 @@ pub type AsyncInstructionCallback = Arc<Mutex<
         &DecodedWhirlpoolInstruction,
         &AccountDataStore,
         &WritableAccountSnapshot,
+        Option<&ExecutionRecord>,
     ) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send
 >>;
 
 pub struct WhirlpoolReplayer {
     replay_engine: ReplayEngine,
-    transaction_iter: Box<dyn Iterator<Item = WhirlpoolTransaction> + Send>,
+    transaction_iter: TransactionIter,
+    // the slot taken from transaction_iter but not yet (fully) replayed
+    cursor: Option<ReplayCursor>,
+    checkpoint_autosave: Option<CheckpointAutosave>,
+    interrupt_flag: Option<Arc<AtomicBool>>,
+    filter: Option<filter::ReplayFilter>,
+    // write a repro bundle into the directory when an instruction fails
+    repro_bundle_dir: Option<String>,
+    last_repro_bundle: Option<String>,
+    // reported by the token transfer verification (not taken yet)
+    token_transfer_mismatches: Vec<TokenTransferMismatchReport>,
+}
+
+struct CheckpointAutosave {
+    file_path: String,
+    interval_slots: u64,
+    replayed_slots: u64,
+}
+
+type TransactionIter = Box<dyn Iterator<Item = Result<WhirlpoolTransaction, ReplayError>> + Send>;
+
+struct ReplayCursor {
+    whirlpool_transaction: WhirlpoolTransaction,
+    slot_started: bool,
+    transaction_index: usize,
+    instruction_index: usize,
+}
+
+impl ReplayCursor {
+    fn new(whirlpool_transaction: WhirlpoolTransaction) -> ReplayCursor {
+        ReplayCursor {
+            whirlpool_transaction,
+            slot_started: false,
+            transaction_index: 0,
+            instruction_index: 0,
+        }
+    }
+
+    // position (transaction index, instruction index) of the next instruction to be replayed
+    fn next_position(&self) -> Option<(usize, usize)> {
+        let transactions = &self.whirlpool_transaction.transactions;
+        let mut transaction_index = self.transaction_index;
+        let mut instruction_index = self.instruction_index;
+        while transaction_index < transactions.len() {
+            if instruction_index < transactions[transaction_index].instructions.len() {
+                return Some((transaction_index, instruction_index));
+            }
+            transaction_index += 1;
+            instruction_index = 0;
+        }
+        None
+    }
+}
+
+enum ReplayedInstruction {
+    ProgramDeploy,
+    // not replayed by the filter
+    Skipped,
+    Whirlpool {
+        transaction_index: usize,
+        name: String,
+        instruction: DecodedWhirlpoolInstruction,
+        snapshot: WritableAccountSnapshot,
+        execution_record: Option<ExecutionRecord>,
+    },
 }
 
 impl WhirlpoolReplayer {
-    // TODO: build_with_mysql_database
+    fn new(replay_engine: ReplayEngine, transaction_iter: TransactionIter) -> WhirlpoolReplayer {
+        WhirlpoolReplayer {
+            replay_engine,
+            transaction_iter,
+            cursor: None,
+            checkpoint_autosave: None,
+            interrupt_flag: None,
+            filter: None,
+            repro_bundle_dir: None,
+            last_repro_bundle: None,
+            token_transfer_mismatches: vec![],
+        }
+    }
 
-    pub fn build_with_local_file_storage(
-        base_path: &String,
-        yyyymmdd: &String,
+    // state is loaded from the local state file, and the slots after it are streamed from the database
+    // program upgrades are not in the database (see database::MySqlTransactionSource)
+    #[cfg(feature = "mysql")]
+    pub fn build_with_mysql_database(
+        database_url: &String,
+        state_file_path: &String,
         account_data_store_config: &AccountDataStoreConfig,
-    ) -> WhirlpoolReplayer {
-        let current = chrono::NaiveDate::parse_from_str(yyyymmdd, "%Y%m%d").unwrap();
-        let previous = current.pred_opt().unwrap();
-
-        // snapshot of the previous day
-        let state_file_relative_path = io::get_whirlpool_state_file_relative_path(&previous);
-        let state_file_path = format!("{}/{}", base_path, state_file_relative_path);
-        // transactions of the day
-        let transaction_file_relative_path =
-            io::get_whirlpool_transaction_file_relative_path(&current);
-        let transaction_file_path = format!("{}/{}", base_path, transaction_file_relative_path);
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let state = io::load_from_local_whirlpool_state_file(state_file_path, account_data_store_config)?;
+        let source = database::MySqlTransactionSource::new(database_url, state.slot, database::DEFAULT_PAGE_SIZE)?;
 
-        let state = io::load_from_local_whirlpool_state_file(&state_file_path, account_data_store_config);
-        let transaction_iter =
-            io::load_from_local_whirlpool_transaction_file(&transaction_file_path);
+        return Ok(WhirlpoolReplayer::build_with_transaction_source(state, source));
+    }
 
+    // replay transactions provided by any source (e.g. in-memory, filtered or merged ones) on the state
+    pub fn build_with_transaction_source<S: TransactionSource + 'static>(
+        state: WhirlpoolState,
+        source: S,
+    ) -> WhirlpoolReplayer {
         let replay_engine = ReplayEngine::new(
             Slot::new(state.slot, state.block_height, state.block_time),
             state.program_data,
             state.accounts,
         );
 
-        return WhirlpoolReplayer {
-            replay_engine,
-            transaction_iter: Box::new(transaction_iter),
-        };
+        return WhirlpoolReplayer::new(replay_engine, Box::new(source::TransactionSourceIter::new(source)));
+    }
+
+    pub fn build_with_local_file_storage(
+        base_path: &String,
+        yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_day_with_local_file_storage(base_path, yyyymmdd, account_data_store_config, None)
     }
 
     pub fn build_with_remote_file_storage(
         base_url: &String,
         yyyymmdd: &String,
         account_data_store_config: &AccountDataStoreConfig,
-    ) -> WhirlpoolReplayer {
-        let current = chrono::NaiveDate::parse_from_str(yyyymmdd, "%Y%m%d").unwrap();
-        let previous = current.pred_opt().unwrap();
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_day_with_remote_file_storage(base_url, yyyymmdd, account_data_store_config, None)
+    }
+
+    pub fn build_with_remote_file_storage_with_local_cache(
+        base_url: &String,
+        yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        cache_dir_path: &String,
+        refresh: bool,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_day_with_remote_file_storage_with_local_cache(base_url, yyyymmdd, account_data_store_config, cache_dir_path, refresh, None)
+    }
+
+    // only the accounts needed by the filter are loaded, and only the instructions that touch its whirlpools are replayed
+    // (the filter is expanded by the two-hop swaps in the transactions to be replayed, see WhirlpoolFilter::expand_by_two_hop_swaps)
+    pub fn build_with_local_file_storage_with_filter(
+        base_path: &String,
+        yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: WhirlpoolFilter,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_day_with_local_file_storage(base_path, yyyymmdd, account_data_store_config, Some(filter))
+    }
+
+    pub fn build_with_remote_file_storage_with_filter(
+        base_url: &String,
+        yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: WhirlpoolFilter,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_day_with_remote_file_storage(base_url, yyyymmdd, account_data_store_config, Some(filter))
+    }
+
+    pub fn build_with_remote_file_storage_with_local_cache_with_filter(
+        base_url: &String,
+        yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        cache_dir_path: &String,
+        refresh: bool,
+        filter: WhirlpoolFilter,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_day_with_remote_file_storage_with_local_cache(base_url, yyyymmdd, account_data_store_config, cache_dir_path, refresh, Some(filter))
+    }
+
+    fn build_day_with_local_file_storage(
+        base_path: &String,
+        yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: Option<WhirlpoolFilter>,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let current = parse_date(yyyymmdd)?;
+        let previous = previous_date(&current)?;
 
         // snapshot of the previous day
-        let state_file_relative_path = io::get_whirlpool_state_file_relative_path(&previous);
-        let state_file_url = format!("{}/{}", base_url, state_file_relative_path);
+        let state_file_path = io::local_file_path(base_path, &io::get_whirlpool_state_file_relative_paths(&previous));
         // transactions of the day
-        let transaction_file_relative_path =
-            io::get_whirlpool_transaction_file_relative_path(&current);
-        let transaction_file_url = format!("{}/{}", base_url, transaction_file_relative_path);
+        let transaction_file_path = io::local_file_path(base_path, &io::get_whirlpool_transaction_file_relative_paths(&current));
 
-        let state = io::load_from_remote_whirlpool_state_file(&state_file_url, account_data_store_config);
+        let filter = expand_filter(filter, || into_transaction_iter(io::load_from_local_whirlpool_transaction_file(&transaction_file_path)))?;
+        let state = load_local_state(&state_file_path, account_data_store_config, filter.as_ref())?;
         let transaction_iter =
-            io::load_from_remote_whirlpool_transaction_file(&transaction_file_url);
+            io::load_from_local_whirlpool_transaction_file(&transaction_file_path)?;
 
-        let replay_engine = ReplayEngine::new(
-            Slot::new(state.slot, state.block_height, state.block_time),
-            state.program_data,
-            state.accounts,
-        );
+        WhirlpoolReplayer::build(state, Box::new(transaction_iter), filter)
+    }
 
-        return WhirlpoolReplayer {
-            replay_engine,
-            transaction_iter: Box::new(transaction_iter),
-        };
+    fn build_day_with_remote_file_storage(
+        base_url: &String,
+        yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: Option<WhirlpoolFilter>,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let current = parse_date(yyyymmdd)?;
+        let previous = previous_date(&current)?;
+
+        // snapshot of the previous day
+        let state_file_url = io::remote_file_url(base_url, &io::get_whirlpool_state_file_relative_paths(&previous))?;
+        // transactions of the day
+        let transaction_file_url = io::remote_file_url(base_url, &io::get_whirlpool_transaction_file_relative_paths(&current))?;
+
+        let filter = expand_filter(filter, || into_transaction_iter(io::load_from_remote_whirlpool_transaction_file(&transaction_file_url)))?;
+        let state = load_remote_state(&state_file_url, account_data_store_config, filter.as_ref())?;
+        let transaction_iter =
+            io::load_from_remote_whirlpool_transaction_file(&transaction_file_url)?;
+
+        WhirlpoolReplayer::build(state, Box::new(transaction_iter), filter)
     }
 
-    pub fn build_with_remote_file_storage_with_local_cache(
+    fn build_day_with_remote_file_storage_with_local_cache(
         base_url: &String,
         yyyymmdd: &String,
         account_data_store_config: &AccountDataStoreConfig,
         cache_dir_path: &String,
         refresh: bool,
-    ) -> WhirlpoolReplayer {
-        let current = chrono::NaiveDate::parse_from_str(yyyymmdd, "%Y%m%d").unwrap();
-        let previous = current.pred_opt().unwrap();
+        filter: Option<WhirlpoolFilter>,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let current = parse_date(yyyymmdd)?;
+        let previous = previous_date(&current)?;
 
         // snapshot of the previous day
-        let state_file_relative_path = io::get_whirlpool_state_file_relative_path(&previous);
-        let state_file_url = format!("{}/{}", base_url, state_file_relative_path);
+        let state_file_path = io::cached_file_path(
+            base_url,
+            cache_dir_path,
+            &io::get_whirlpool_state_file_relative_paths(&previous),
+            refresh,
+        )?;
         // transactions of the day
-        let transaction_file_relative_path =
-            io::get_whirlpool_transaction_file_relative_path(&current);
-        let transaction_file_url = format!("{}/{}", base_url, transaction_file_relative_path);
+        let transaction_file_path = io::cached_file_path(
+            base_url,
+            cache_dir_path,
+            &io::get_whirlpool_transaction_file_relative_paths(&current),
+            refresh,
+        )?;
 
-        let state_file_path = format!("{}/{}", cache_dir_path, state_file_relative_path);
-        if refresh || !std::path::Path::new(&state_file_path).exists() {
-            io::download_from_remote_storage(&state_file_url, &state_file_path);
-        }
+        let filter = expand_filter(filter, || into_transaction_iter(io::load_from_local_whirlpool_transaction_file(&transaction_file_path)))?;
+        let state = load_local_state(&state_file_path, account_data_store_config, filter.as_ref())?;
+        let transaction_iter =
+            io::load_from_local_whirlpool_transaction_file(&transaction_file_path)?;
 
-        let transaction_file_path =
-            format!("{}/{}", cache_dir_path, transaction_file_relative_path);
-        if refresh || !std::path::Path::new(&transaction_file_path).exists() {
-            io::download_from_remote_storage(&transaction_file_url, &transaction_file_path);
-        }
+        WhirlpoolReplayer::build(state, Box::new(transaction_iter), filter)
+    }
 
-        let state = io::load_from_local_whirlpool_state_file(&state_file_path, account_data_store_config);
-        let transaction_iter =
-            io::load_from_local_whirlpool_transaction_file(&transaction_file_path);
+    pub fn build_with_local_file_storage_with_date_range(
+        base_path: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_days_with_local_file_storage(base_path, from_yyyymmdd, to_yyyymmdd, account_data_store_config, None)
+    }
+
+    pub fn build_with_remote_file_storage_with_date_range(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_days_with_remote_file_storage(base_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config, None)
+    }
+
+    pub fn build_with_remote_file_storage_with_local_cache_with_date_range(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        cache_dir_path: &String,
+        refresh: bool,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_days_with_remote_file_storage_with_local_cache(base_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config, cache_dir_path, refresh, None)
+    }
+
+    pub fn build_with_local_file_storage_with_date_range_with_filter(
+        base_path: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: WhirlpoolFilter,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_days_with_local_file_storage(base_path, from_yyyymmdd, to_yyyymmdd, account_data_store_config, Some(filter))
+    }
+
+    pub fn build_with_remote_file_storage_with_date_range_with_filter(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: WhirlpoolFilter,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_days_with_remote_file_storage(base_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config, Some(filter))
+    }
+
+    pub fn build_with_remote_file_storage_with_local_cache_with_date_range_with_filter(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        cache_dir_path: &String,
+        refresh: bool,
+        filter: WhirlpoolFilter,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        WhirlpoolReplayer::build_days_with_remote_file_storage_with_local_cache(base_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config, cache_dir_path, refresh, Some(filter))
+    }
+
+    fn build_days_with_local_file_storage(
+        base_path: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: Option<WhirlpoolFilter>,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let (from, to) = parse_date_range(from_yyyymmdd, to_yyyymmdd)?;
+        let previous = previous_date(&from)?;
+
+        // snapshot of the day before the first day
+        let state_file_path = io::local_file_path(base_path, &io::get_whirlpool_state_file_relative_paths(&previous));
+
+        let filter = expand_filter(filter, || local_transaction_iter(base_path, from, to))?;
+        let state = load_local_state(&state_file_path, account_data_store_config, filter.as_ref())?;
+
+        // transactions of the days
+        let transaction_iter = local_transaction_iter(base_path, from, to);
+
+        WhirlpoolReplayer::build(state, transaction_iter, filter)
+    }
+
+    fn build_days_with_remote_file_storage(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        filter: Option<WhirlpoolFilter>,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let (from, to) = parse_date_range(from_yyyymmdd, to_yyyymmdd)?;
+        let previous = previous_date(&from)?;
+
+        // snapshot of the day before the first day
+        let state_file_url = io::remote_file_url(base_url, &io::get_whirlpool_state_file_relative_paths(&previous))?;
+
+        let filter = expand_filter(filter, || remote_transaction_iter(base_url, from, to))?;
+        let state = load_remote_state(&state_file_url, account_data_store_config, filter.as_ref())?;
+
+        // transactions of the days
+        let transaction_iter = remote_transaction_iter(base_url, from, to);
+
+        WhirlpoolReplayer::build(state, transaction_iter, filter)
+    }
+
+    fn build_days_with_remote_file_storage_with_local_cache(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        cache_dir_path: &String,
+        refresh: bool,
+        filter: Option<WhirlpoolFilter>,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let (from, to) = parse_date_range(from_yyyymmdd, to_yyyymmdd)?;
+        let previous = previous_date(&from)?;
+
+        // snapshot of the day before the first day
+        let state_file_path = io::cached_file_path(
+            base_url,
+            cache_dir_path,
+            &io::get_whirlpool_state_file_relative_paths(&previous),
+            refresh,
+        )?;
+
+        // the transaction files refreshed by the scan are not downloaded again
+        let refresh_transactions = refresh && filter.is_none();
+        let filter = expand_filter(filter, || cached_transaction_iter(base_url, cache_dir_path, refresh, from, to))?;
+        let state = load_local_state(&state_file_path, account_data_store_config, filter.as_ref())?;
+
+        // transactions of the days
+        let transaction_iter = cached_transaction_iter(base_url, cache_dir_path, refresh_transactions, from, to);
 
+        WhirlpoolReplayer::build(state, transaction_iter, filter)
+    }
+
+    fn build(state: WhirlpoolState, transaction_iter: TransactionIter, filter: Option<WhirlpoolFilter>) -> Result<WhirlpoolReplayer, ReplayError> {
         let replay_engine = ReplayEngine::new(
             Slot::new(state.slot, state.block_height, state.block_time),
             state.program_data,
             state.accounts,
         );
 
-        return WhirlpoolReplayer {
-            replay_engine,
-            transaction_iter: Box::new(transaction_iter),
-        };
+        let mut replayer = WhirlpoolReplayer::new(replay_engine, transaction_iter);
+        if let Some(filter) = filter {
+            // no account is pruned because the state has been loaded with the filter
+            replayer.set_whirlpool_filter(filter)?;
+        }
+        Ok(replayer)
+    }
+
+    // resume the replay from yyyymmdd to to_yyyymmdd from the checkpoint saved in the middle of it
+    pub fn build_with_local_file_storage_from_checkpoint(
+        base_path: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        checkpoint_file_path: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let (from, to) = parse_date_range(from_yyyymmdd, to_yyyymmdd)?;
+        let checkpoint = io::load_from_local_whirlpool_checkpoint_file(checkpoint_file_path, account_data_store_config)?;
+        let from = checkpoint_date(&checkpoint, from)?;
+
+        // transactions of the days (from the day of the checkpoint)
+        let transaction_iter = local_transaction_iter(base_path, from, to);
+
+        return WhirlpoolReplayer::resume(checkpoint, transaction_iter);
+    }
+
+    pub fn build_with_remote_file_storage_from_checkpoint(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        checkpoint_file_path: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let (from, to) = parse_date_range(from_yyyymmdd, to_yyyymmdd)?;
+        let checkpoint = io::load_from_local_whirlpool_checkpoint_file(checkpoint_file_path, account_data_store_config)?;
+        let from = checkpoint_date(&checkpoint, from)?;
+
+        // transactions of the days (from the day of the checkpoint)
+        let transaction_iter = remote_transaction_iter(base_url, from, to);
+
+        return WhirlpoolReplayer::resume(checkpoint, transaction_iter);
+    }
+
+    pub fn build_with_remote_file_storage_with_local_cache_from_checkpoint(
+        base_url: &String,
+        from_yyyymmdd: &String,
+        to_yyyymmdd: &String,
+        checkpoint_file_path: &String,
+        account_data_store_config: &AccountDataStoreConfig,
+        cache_dir_path: &String,
+        refresh: bool,
+    ) -> Result<WhirlpoolReplayer, ReplayError> {
+        let (from, to) = parse_date_range(from_yyyymmdd, to_yyyymmdd)?;
+        let checkpoint = io::load_from_local_whirlpool_checkpoint_file(checkpoint_file_path, account_data_store_config)?;
+        let from = checkpoint_date(&checkpoint, from)?;
+
+        // transactions of the days (from the day of the checkpoint)
+        let transaction_iter = cached_transaction_iter(base_url, cache_dir_path, refresh, from, to);
+
+        return WhirlpoolReplayer::resume(checkpoint, transaction_iter);
+    }
+
+    fn resume(checkpoint: schema::WhirlpoolCheckpoint, transaction_iter: TransactionIter) -> Result<WhirlpoolReplayer, ReplayError> {
+        let replay_engine = ReplayEngine::new(
+            Slot::new(checkpoint.slot, checkpoint.block_height, checkpoint.block_time),
+            checkpoint.program_data,
+            checkpoint.accounts,
+        );
+
+        let mut replayer = WhirlpoolReplayer::new(replay_engine, transaction_iter);
+        replayer.skip_to_position(&checkpoint.position)?;
+        return Ok(replayer);
     }
 
     pub fn get_slot(&self) -> &Slot {
@@ -191,76 +595,176 @@ impl WhirlpoolReplayer {
         return self.replay_engine.get_accounts();
     }
 
+    // the position of the replay in the transaction stream
+    pub fn get_position(&self) -> ReplayPosition {
+        let slot = self.replay_engine.get_slot().slot;
+        let next_instruction = match self.cursor.as_ref() {
+            Some(cursor) if cursor.slot_started => cursor.next_position().map(|(transaction_index, instruction_index)| {
+                InstructionPosition { transaction_index, instruction_index }
+            }),
+            _ => None,
+        };
+        ReplayPosition { slot, next_instruction }
+    }
+
+    pub fn save_checkpoint(&self, file_path: &String) -> Result<(), ReplayError> {
+        io::save_to_whirlpool_checkpoint_file(
+            file_path,
+            self.replay_engine.get_slot(),
+            &self.get_position(),
+            self.replay_engine.get_program_data(),
+            self.replay_engine.get_accounts(),
+        )
+    }
+
+    // save a checkpoint every interval_slots replayed slots (overwriting the previous one, 0 disables it)
+    pub fn set_checkpoint_autosave(&mut self, file_path: &String, interval_slots: u64) {
+        self.checkpoint_autosave = if interval_slots > 0 {
+            Some(CheckpointAutosave {
+                file_path: file_path.clone(),
+                interval_slots,
+                replayed_slots: 0,
+            })
+        } else {
+            None
+        };
+    }
+
+    // replay and replay_async stop before the next slot once the flag is set (e.g. by Ctrl-C handler)
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt_flag = Some(flag);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupt_flag.as_ref().map_or(false, |flag| flag.load(Ordering::SeqCst))
+    }
+
+    // replay only the instructions that touch the whirlpools in the filter
+    // the accounts not needed are removed (the *_with_filter builders load only the needed accounts to reduce memory usage and loading time)
+    // the filter must have been expanded by two-hop swaps (the *_with_filter builders do it), see WhirlpoolFilter::expand_by_two_hop_swaps
+    pub fn set_whirlpool_filter(&mut self, filter: WhirlpoolFilter) -> Result<(), ReplayError> {
+        filter.prune(self.replay_engine.get_accounts_mut())?;
+        let positions = filter.collect_positions(self.replay_engine.get_accounts())?;
+        self.filter = Some(filter::ReplayFilter::new(filter, positions));
+        Ok(())
+    }
+
+    pub fn get_whirlpool_filter(&self) -> Option<&WhirlpoolFilter> {
+        self.filter.as_ref().map(|filter| filter.get_filter())
+    }
+
+    // strict mode: check that the replayed instruction moved exactly the recorded token amounts
+    // the mismatches are reported per instruction (see take_token_transfer_mismatches) and the replay goes on
+    pub fn set_token_transfer_verification(&mut self, enabled: bool) {
+        self.replay_engine.set_token_transfer_verification(enabled);
+    }
+
+    // the mismatches found since the last call (in replay order)
+    pub fn take_token_transfer_mismatches(&mut self) -> Vec<TokenTransferMismatchReport> {
+        std::mem::take(&mut self.token_transfer_mismatches)
+    }
+
+    // pass logs, compute units, inner instructions, return data and program events of each instruction to the instruction callback
+    // (disabled by default because the recording slows down the replay, program events are not decoded without it)
+    pub fn set_execution_recording(&mut self, enabled: bool) {
+        self.replay_engine.set_execution_recording(enabled);
+    }
+
+    // write a repro bundle (see repro.rs) into the directory when an instruction fails to be replayed
+    pub fn set_repro_bundle_dir(&mut self, dir_path: &String) {
+        self.repro_bundle_dir = Some(dir_path.clone());
+    }
+
+    // the file path of the repro bundle written for the last failure (None if failed to write)
+    pub fn get_last_repro_bundle(&self) -> Option<&String> {
+        self.last_repro_bundle.as_ref()
+    }
+
+    // the record of the last executed instruction (including the failed one)
+    pub fn get_last_execution_record(&self) -> Option<&ExecutionRecord> {
+        self.replay_engine.get_last_execution_record()
+    }
+
     pub fn replay(
         &mut self,
         cond: ReplayUntil,
         instruction_callback: Option<SyncInstructionCallback>,
         slot_pre_callback: Option<SyncSlotCallback>,
         slot_post_callback: Option<SyncSlotCallback>,
-    ) {
-        let mut next_whirlpool_transaction = self.transaction_iter.next();
-        while next_whirlpool_transaction.is_some() {
-            let whirlpool_transaction = next_whirlpool_transaction.unwrap();
-
-            let slot = Slot {
-                slot: whirlpool_transaction.slot,
-                block_height: whirlpool_transaction.block_height,
-                block_time: whirlpool_transaction.block_time,
-            };
-
-            if has_reached_until_condition(&cond, slot) {
+    ) -> Result<(), ReplayError> {
+        while self.load_next_slot()? {
+            // the slot that reached the until condition is kept for the next call
+            if !self.is_slot_started() && has_reached_until_condition(&cond, self.next_slot())? {
+                break;
+            }
+            if self.is_interrupted() {
                 break;
             }
 
-            self.replay_engine
-                .update_slot(slot.slot, slot.block_height, slot.block_time);
+            self.replay_remaining_slot(
+                instruction_callback.as_ref(),
+                slot_pre_callback.as_ref(),
+                slot_post_callback.as_ref(),
+            )?;
+        }
 
-            if let Some(callback) = slot_pre_callback.as_ref() {
-                callback(self.replay_engine.get_slot(), self.replay_engine.get_accounts());
-            }
+        Ok(())
+    }
 
-            for transaction in whirlpool_transaction.transactions {
-                for instruction in transaction.clone().instructions {
-                    let name = instruction.name;
-                    let payload = instruction.payload.to_string();
-                    let decoded = decoded_instructions::from_json(&name, &payload).unwrap();
-
-                    match decoded {
-                        decoded_instructions::DecodedInstruction::ProgramDeployInstruction(
-                            deploy_instruction,
-                        ) => {
-                            self.replay_engine
-                                .update_program_data(deploy_instruction.program_data);
-                        }
-                        decoded_instructions::DecodedInstruction::WhirlpoolInstruction(
-                            whirlpool_instruction,
-                        ) => {
-                            let result = self
-                                .replay_engine
-                                .replay_instruction(&whirlpool_instruction)
-                                .unwrap();
-
-                            if let Some(callback) = instruction_callback.as_ref() {
-                                callback(
-                                    &slot,
-                                    &transaction,
-                                    &name,
-                                    &whirlpool_instruction,
-                                    self.replay_engine.get_accounts(),
-                                    &result.snapshot,
-                                );
-                            }
-                        }
-                    }
+    // replay the next slot (or the rest of the current slot if step_instruction stopped in the middle of it)
+    // returns None if there is no more slot
+    pub fn step_slot(
+        &mut self,
+        instruction_callback: Option<SyncInstructionCallback>,
+        slot_pre_callback: Option<SyncSlotCallback>,
+        slot_post_callback: Option<SyncSlotCallback>,
+    ) -> Result<Option<Slot>, ReplayError> {
+        if !self.load_next_slot()? {
+            return Ok(None);
+        }
+
+        let slot = self.replay_remaining_slot(
+            instruction_callback.as_ref(),
+            slot_pre_callback.as_ref(),
+            slot_post_callback.as_ref(),
+        )?;
+        Ok(Some(slot))
+    }
+
+    // replay the next instruction (slots without instruction are processed on the way)
+    // returns the slot of the replayed instruction, or None if there is no more instruction
+    pub fn step_instruction(
+        &mut self,
+        instruction_callback: Option<SyncInstructionCallback>,
+        slot_pre_callback: Option<SyncSlotCallback>,
+        slot_post_callback: Option<SyncSlotCallback>,
+    ) -> Result<Option<Slot>, ReplayError> {
+        while self.load_next_slot()? {
+            if !self.is_slot_started() {
+                self.start_slot();
+                if let Some(callback) = slot_pre_callback.as_ref() {
+                    callback(self.replay_engine.get_slot(), self.replay_engine.get_accounts());
                 }
             }
 
-            if let Some(callback) = slot_post_callback.as_ref() {
-                callback(self.replay_engine.get_slot(), self.replay_engine.get_accounts());
+            let replayed = self.replay_next_instruction()?;
+            if let Some(replayed) = replayed.as_ref() {
+                self.call_instruction_callback(instruction_callback.as_ref(), replayed);
             }
 
-            next_whirlpool_transaction = self.transaction_iter.next();
+            if self.is_end_of_slot() {
+                self.finish_slot()?;
+                if let Some(callback) = slot_post_callback.as_ref() {
+                    callback(self.replay_engine.get_slot(), self.replay_engine.get_accounts());
+                }
+            }
+
+            if replayed.is_some() {
+                return Ok(Some(*self.replay_engine.get_slot()));
+            }
         }
+
+        Ok(None)
     }
 
     pub async fn replay_async(
@@ -269,84 +773,749 @@ impl WhirlpoolReplayer {
         instruction_callback: Option<AsyncInstructionCallback>,
         slot_pre_callback: Option<AsyncSlotCallback>,
         slot_post_callback: Option<AsyncSlotCallback>,
-    ) {
-        let mut next_whirlpool_transaction = self.transaction_iter.next();
-        while next_whirlpool_transaction.is_some() {
-            let whirlpool_transaction = next_whirlpool_transaction.unwrap();
-
-            let slot = Slot {
-                slot: whirlpool_transaction.slot,
-                block_height: whirlpool_transaction.block_height,
-                block_time: whirlpool_transaction.block_time,
-            };
-
-            if has_reached_until_condition(&cond, slot) {
+    ) -> Result<(), ReplayError> {
+        while self.load_next_slot()? {
+            // the slot that reached the until condition is kept for the next call
+            if !self.is_slot_started() && has_reached_until_condition(&cond, self.next_slot())? {
+                break;
+            }
+            if self.is_interrupted() {
                 break;
             }
 
-            self.replay_engine
-                .update_slot(slot.slot, slot.block_height, slot.block_time);
-
-            if let Some(callback) = slot_pre_callback.as_ref() {
-                let callback_guard = callback.lock().await;
-                callback_guard(self.replay_engine.get_slot(), self.replay_engine.get_accounts()).await;
+            if !self.is_slot_started() {
+                self.start_slot();
+                if let Some(callback) = slot_pre_callback.as_ref() {
+                    let callback_guard = callback.lock().await;
+                    callback_guard(self.replay_engine.get_slot(), self.replay_engine.get_accounts()).await;
+                }
             }
 
-            for transaction in whirlpool_transaction.transactions {
-                for instruction in transaction.clone().instructions {
-                    let name = instruction.name.clone();
-                    let payload = instruction.payload.to_string().clone();
-                    let decoded = decoded_instructions::from_json(&name, &payload).unwrap();
-
-                    match decoded {
-                        decoded_instructions::DecodedInstruction::ProgramDeployInstruction(
-                            deploy_instruction,
-                        ) => {
-                            self.replay_engine
-                                .update_program_data(deploy_instruction.program_data);
-                        }
-                        decoded_instructions::DecodedInstruction::WhirlpoolInstruction(
-                            whirlpool_instruction,
-                        ) => {
-                            let result = self
-                                .replay_engine
-                                .replay_instruction(&whirlpool_instruction)
-                                .unwrap();
-
-                            let accounts = self.replay_engine.get_accounts();
-
-                            if let Some(callback) = instruction_callback.as_ref() {
-                                let callback_guard = callback.lock().await;
-                                callback_guard(
-                                    &slot,
-                                    &transaction,
-                                    &name,
-                                    &whirlpool_instruction,
-                                    &accounts,
-                                    &result.snapshot,
-                                ).await;
-                            }
-                        }
+            while let Some(replayed) = self.replay_next_instruction()? {
+                if let ReplayedInstruction::Whirlpool { transaction_index, name, instruction, snapshot, execution_record } = replayed {
+                    if let Some(callback) = instruction_callback.as_ref() {
+                        let callback_guard = callback.lock().await;
+                        callback_guard(
+                            self.replay_engine.get_slot(),
+                            self.transaction_at(transaction_index),
+                            &name,
+                            &instruction,
+                            self.replay_engine.get_accounts(),
+                            &snapshot,
+                            execution_record.as_ref(),
+                        ).await;
                     }
                 }
             }
 
+            self.finish_slot()?;
             if let Some(callback) = slot_post_callback.as_ref() {
                 let callback_guard = callback.lock().await;
                 callback_guard(self.replay_engine.get_slot(), self.replay_engine.get_accounts()).await;
             }
+        }
+
+        Ok(())
+    }
+
+    fn replay_remaining_slot(
+        &mut self,
+        instruction_callback: Option<&SyncInstructionCallback>,
+        slot_pre_callback: Option<&SyncSlotCallback>,
+        slot_post_callback: Option<&SyncSlotCallback>,
+    ) -> Result<Slot, ReplayError> {
+        if !self.is_slot_started() {
+            self.start_slot();
+            if let Some(callback) = slot_pre_callback {
+                callback(self.replay_engine.get_slot(), self.replay_engine.get_accounts());
+            }
+        }
+
+        while let Some(replayed) = self.replay_next_instruction()? {
+            self.call_instruction_callback(instruction_callback, &replayed);
+        }
+
+        self.finish_slot()?;
+        if let Some(callback) = slot_post_callback {
+            callback(self.replay_engine.get_slot(), self.replay_engine.get_accounts());
+        }
+
+        Ok(*self.replay_engine.get_slot())
+    }
+
+    fn call_instruction_callback(&self, instruction_callback: Option<&SyncInstructionCallback>, replayed: &ReplayedInstruction) {
+        if let (Some(callback), ReplayedInstruction::Whirlpool { transaction_index, name, instruction, snapshot, execution_record }) = (instruction_callback, replayed) {
+            callback(
+                self.replay_engine.get_slot(),
+                self.transaction_at(*transaction_index),
+                name,
+                instruction,
+                self.replay_engine.get_accounts(),
+                snapshot,
+                execution_record.as_ref(),
+            );
+        }
+    }
+
+    // make sure that the cursor points a slot (returns false if no more slot)
+    fn load_next_slot(&mut self) -> Result<bool, ReplayError> {
+        if self.cursor.is_none() {
+            match self.transaction_iter.next() {
+                Some(whirlpool_transaction) => {
+                    self.cursor = Some(ReplayCursor::new(whirlpool_transaction?));
+                }
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
 
-            next_whirlpool_transaction = self.transaction_iter.next();
+    fn next_slot(&self) -> Slot {
+        let whirlpool_transaction = &self.cursor.as_ref().unwrap().whirlpool_transaction;
+        Slot {
+            slot: whirlpool_transaction.slot,
+            block_height: whirlpool_transaction.block_height,
+            block_time: whirlpool_transaction.block_time,
         }
     }
 
+    fn is_slot_started(&self) -> bool {
+        self.cursor.as_ref().map_or(false, |cursor| cursor.slot_started)
+    }
+
+    fn is_end_of_slot(&self) -> bool {
+        self.cursor.as_ref().map_or(true, |cursor| cursor.next_position().is_none())
+    }
+
+    fn start_slot(&mut self) {
+        let slot = self.next_slot();
+        self.replay_engine
+            .update_slot(slot.slot, slot.block_height, slot.block_time);
+        self.cursor.as_mut().unwrap().slot_started = true;
+    }
+
+    fn finish_slot(&mut self) -> Result<(), ReplayError> {
+        self.cursor = None;
+
+        let save = match self.checkpoint_autosave.as_mut() {
+            Some(autosave) => {
+                autosave.replayed_slots += 1;
+                autosave.replayed_slots % autosave.interval_slots == 0
+            }
+            None => false,
+        };
+        if save {
+            let file_path = self.checkpoint_autosave.as_ref().unwrap().file_path.clone();
+            self.save_checkpoint(&file_path)?;
+        }
+
+        Ok(())
+    }
+
+    // skip the slots (and instructions) that had been replayed before the checkpoint
+    fn skip_to_position(&mut self, position: &ReplayPosition) -> Result<(), ReplayError> {
+        while self.load_next_slot()? {
+            let slot = self.next_slot().slot;
+            if slot > position.slot {
+                break;
+            }
+
+            if slot == position.slot {
+                if let Some(next_instruction) = position.next_instruction {
+                    let cursor = self.cursor.as_mut().unwrap();
+                    cursor.slot_started = true;
+                    cursor.transaction_index = next_instruction.transaction_index;
+                    cursor.instruction_index = next_instruction.instruction_index;
+                    break;
+                }
+            }
+
+            self.cursor = None;
+        }
+        Ok(())
+    }
+
+    fn transaction_at(&self, transaction_index: usize) -> &Transaction {
+        &self.cursor.as_ref().unwrap().whirlpool_transaction.transactions[transaction_index]
+    }
+
+    fn replay_next_instruction(&mut self) -> Result<Option<ReplayedInstruction>, ReplayError> {
+        let cursor = match self.cursor.as_ref() {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+        let (transaction_index, instruction_index) = match cursor.next_position() {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+
+        let slot = cursor.whirlpool_transaction.slot;
+        let transaction = &cursor.whirlpool_transaction.transactions[transaction_index];
+        let instruction = &transaction.instructions[instruction_index];
+        let name = instruction.name.clone();
+        let payload = instruction.payload.to_string();
+        let decoded = decoded_instructions::from_json(&name, &payload)
+            .map_err(|e| ReplayError::instruction(slot, &transaction.signature, instruction_index, &name, e))?;
+
+        let replayed = match decoded {
+            decoded_instructions::DecodedInstruction::ProgramDeployInstruction(
+                deploy_instruction,
+            ) => {
+                self.replay_engine
+                    .update_program_data(deploy_instruction.program_data);
+                ReplayedInstruction::ProgramDeploy
+            }
+            decoded_instructions::DecodedInstruction::WhirlpoolInstruction(
+                whirlpool_instruction,
+            ) if !self.filter.as_mut().map_or(true, |filter| filter.should_replay(&whirlpool_instruction)) => {
+                ReplayedInstruction::Skipped
+            }
+            decoded_instructions::DecodedInstruction::WhirlpoolInstruction(
+                whirlpool_instruction,
+            ) => {
+                let result = match self.replay_engine.replay_instruction(&whirlpool_instruction) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        if let (Some(dir_path), ErrorCode::InstructionReplayFailed(_)) = (self.repro_bundle_dir.as_ref(), &err) {
+                            self.last_repro_bundle = repro::build_repro_bundle(
+                                self.replay_engine.get_slot(),
+                                transaction,
+                                instruction_index,
+                                &err,
+                                self.replay_engine.get_accounts(),
+                                self.replay_engine.get_program_data(),
+                                self.replay_engine.get_last_execution_record(),
+                            )
+                            .and_then(|bundle| repro::save_repro_bundle(dir_path, &bundle))
+                            .ok();
+                        }
+                        return Err(ReplayError::instruction(slot, &transaction.signature, instruction_index, &name, err));
+                    }
+                };
+
+                if !result.token_transfer_mismatches.is_empty() {
+                    self.token_transfer_mismatches.push(TokenTransferMismatchReport {
+                        slot,
+                        signature: transaction.signature.clone(),
+                        instruction_index,
+                        name: name.clone(),
+                        mismatches: result.token_transfer_mismatches,
+                    });
+                }
+
+                ReplayedInstruction::Whirlpool {
+                    transaction_index,
+                    name,
+                    instruction: whirlpool_instruction,
+                    snapshot: result.snapshot,
+                    execution_record: result.execution_record,
+                }
+            }
+        };
+
+        // move forward only if the instruction has been replayed successfully
+        let cursor = self.cursor.as_mut().unwrap();
+        cursor.transaction_index = transaction_index;
+        cursor.instruction_index = instruction_index + 1;
+
+        Ok(Some(replayed))
+    }
+
 }
 
-fn has_reached_until_condition(cond: &ReplayUntil, slot: Slot) -> bool {
-    match cond {
+fn has_reached_until_condition(cond: &ReplayUntil, slot: Slot) -> Result<bool, ReplayError> {
+    let reached = match cond {
         ReplayUntil::End => false,
         ReplayUntil::Slot(until_slot) => slot.slot > *until_slot,
         ReplayUntil::BlockHeight(until_block_height) => slot.block_height > *until_block_height,
         ReplayUntil::BlockTime(until_block_time) => slot.block_time > *until_block_time,
+        ReplayUntil::Date(until_date) => {
+            let date = chrono::DateTime::from_timestamp(slot.block_time, 0)
+                .ok_or_else(|| ReplayError::InvalidDate(format!("invalid blockTime: {}", slot.block_time)))?
+                .date_naive();
+            date > *until_date
+        }
+    };
+    Ok(reached)
+}
+
+// yyyymmdd (e.g. 20240101)
+pub fn parse_date(yyyymmdd: &String) -> Result<chrono::NaiveDate, ReplayError> {
+    chrono::NaiveDate::parse_from_str(yyyymmdd, "%Y%m%d")
+        .map_err(|e| ReplayError::InvalidDate(format!("{}: {}", yyyymmdd, e)))
+}
+
+fn previous_date(date: &chrono::NaiveDate) -> Result<chrono::NaiveDate, ReplayError> {
+    date.pred_opt()
+        .ok_or_else(|| ReplayError::InvalidDate(format!("no previous day: {}", date)))
+}
+
+pub(crate) fn parse_date_range(from_yyyymmdd: &String, to_yyyymmdd: &String) -> Result<(chrono::NaiveDate, chrono::NaiveDate), ReplayError> {
+    let from = parse_date(from_yyyymmdd)?;
+    let to = parse_date(to_yyyymmdd)?;
+    if from > to {
+        return Err(ReplayError::InvalidDate(format!("invalid date range: {} > {}", from_yyyymmdd, to_yyyymmdd)));
     }
-}
\ No newline at end of file
+    Ok((from, to))
+}
+
+pub(crate) fn iter_days(from: chrono::NaiveDate, to: chrono::NaiveDate) -> impl Iterator<Item = chrono::NaiveDate> + Send {
+    from.iter_days().take_while(move |date| *date <= to)
+}
+
+// the next file is opened when the previous one is exhausted
+fn local_transaction_iter(base_path: &String, from: chrono::NaiveDate, to: chrono::NaiveDate) -> TransactionIter {
+    let base_path = base_path.clone();
+    Box::new(iter_days(from, to).flat_map(move |date| {
+        let transaction_file_path = io::local_file_path(&base_path, &io::get_whirlpool_transaction_file_relative_paths(&date));
+        into_transaction_iter(io::load_from_local_whirlpool_transaction_file(&transaction_file_path))
+    }))
+}
+
+// the next file is fetched when the previous one is exhausted
+fn remote_transaction_iter(base_url: &String, from: chrono::NaiveDate, to: chrono::NaiveDate) -> TransactionIter {
+    let base_url = base_url.clone();
+    Box::new(iter_days(from, to).flat_map(move |date| {
+        let transaction_file_url = match io::remote_file_url(&base_url, &io::get_whirlpool_transaction_file_relative_paths(&date)) {
+            Ok(url) => url,
+            Err(err) => return failed_transaction_iter(err),
+        };
+        into_transaction_iter(io::load_from_remote_whirlpool_transaction_file(&transaction_file_url))
+    }))
+}
+
+// the next file is downloaded when the previous one is exhausted
+fn cached_transaction_iter(
+    base_url: &String,
+    cache_dir_path: &String,
+    refresh: bool,
+    from: chrono::NaiveDate,
+    to: chrono::NaiveDate,
+) -> TransactionIter {
+    let base_url = base_url.clone();
+    let cache_dir_path = cache_dir_path.clone();
+    Box::new(iter_days(from, to).flat_map(move |date| {
+        let transaction_file_path = match io::cached_file_path(
+            &base_url,
+            &cache_dir_path,
+            &io::get_whirlpool_transaction_file_relative_paths(&date),
+            refresh,
+        ) {
+            Ok(path) => path,
+            Err(err) => return failed_transaction_iter(err),
+        };
+
+        into_transaction_iter(io::load_from_local_whirlpool_transaction_file(&transaction_file_path))
+    }))
+}
+
+// add the pools connected with the whirlpools in the filter through the two-hop swaps in the transactions to be replayed
+// (the transactions are scanned before loading the state, so remote files without cache are read twice)
+fn expand_filter<F>(filter: Option<WhirlpoolFilter>, transaction_iter: F) -> Result<Option<WhirlpoolFilter>, ReplayError>
+where
+    F: FnOnce() -> TransactionIter,
+{
+    match filter {
+        Some(mut filter) => {
+            filter.expand_by_two_hop_swaps(transaction_iter())?;
+            Ok(Some(filter))
+        }
+        None => Ok(None),
+    }
+}
+
+fn load_local_state(
+    file_path: &String,
+    account_data_store_config: &AccountDataStoreConfig,
+    filter: Option<&WhirlpoolFilter>,
+) -> Result<WhirlpoolState, ReplayError> {
+    match filter {
+        Some(filter) => io::load_from_local_whirlpool_state_file_with_filter(file_path, account_data_store_config, filter),
+        None => io::load_from_local_whirlpool_state_file(file_path, account_data_store_config),
+    }
+}
+
+fn load_remote_state(
+    url: &String,
+    account_data_store_config: &AccountDataStoreConfig,
+    filter: Option<&WhirlpoolFilter>,
+) -> Result<WhirlpoolState, ReplayError> {
+    match filter {
+        Some(filter) => io::load_from_remote_whirlpool_state_file_with_filter(url, account_data_store_config, filter),
+        None => io::load_from_remote_whirlpool_state_file(url, account_data_store_config),
+    }
+}
+
+// the first day of transactions to be read after the checkpoint
+// (a checkpoint taken before the first slot has the blockTime of the previous day)
+fn checkpoint_date(checkpoint: &schema::WhirlpoolCheckpoint, from: chrono::NaiveDate) -> Result<chrono::NaiveDate, ReplayError> {
+    let date = chrono::DateTime::from_timestamp(checkpoint.block_time, 0)
+        .ok_or_else(|| ReplayError::InvalidDate(format!("invalid blockTime: {}", checkpoint.block_time)))?
+        .date_naive();
+    Ok(std::cmp::max(date, from))
+}
+
+fn into_transaction_iter<I>(loaded: Result<I, ReplayError>) -> TransactionIter
+where
+    I: Iterator<Item = Result<WhirlpoolTransaction, ReplayError>> + Send + 'static,
+{
+    match loaded {
+        Ok(iter) => Box::new(iter),
+        Err(err) => failed_transaction_iter(err),
+    }
+}
+
+// a file that cannot be opened is reported when the replay reaches its day
+fn failed_transaction_iter(err: ReplayError) -> TransactionIter {
+    Box::new(std::iter::once(Err(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, HashSet};
+    use std::io::Write;
+
+    use super::*;
+    use crate::schema::TransactionInstruction;
+    use crate::source::VecTransactionSource;
+    use crate::test_utils::{
+        pubkey, whirlpool_data, WhirlpoolParams, INITIALIZE_CONFIG, INITIALIZE_FEE_TIER, INITIALIZE_POOL_V2,
+        INITIALIZE_TICK_ARRAY_439296, INITIALIZE_TICK_ARRAY_NEG_444928, WHIRLPOOL_PROGRAM_FOR_TESTING,
+    };
+
+    // config, fee tier, whirlpool and tick arrays
+    const CREATED_ACCOUNTS: [&str; 5] = [
+        "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D",
+        "C7HVbbKnAnuXfhZ87mefqWPXDo2cjrj35yYmq3HhV1D6",
+        "BsGwEuUqbfeUSDN4mmxhcGFhNYKypKH8NZjoQ7DQrFfC",
+        "CPWekMYLLoEggpQCV4ddND6pGCo4LcGb13uvSmsBHfpc",
+        "ESzF37B5Z3JzjU47sMAymWXfrbD2RoezWEnnPiATtvt2",
+    ];
+
+    // each instruction creates an account (replaying an instruction twice fails because the account already exists)
+    // slot 10: [initializeConfig, initializeFeeTier], [initializePoolV2]
+    // slot 11: no transaction
+    // slot 12: [initializeDynamicTickArray], [initializeDynamicTickArray]
+    fn whirlpool_transactions() -> Vec<WhirlpoolTransaction> {
+        vec![
+            whirlpool_transaction(10, vec![
+                transaction(0, vec![("initializeConfig", INITIALIZE_CONFIG), ("initializeFeeTier", INITIALIZE_FEE_TIER)]),
+                transaction(1, vec![("initializePoolV2", INITIALIZE_POOL_V2)]),
+            ]),
+            whirlpool_transaction(11, vec![]),
+            whirlpool_transaction(12, vec![
+                transaction(0, vec![("initializeDynamicTickArray", INITIALIZE_TICK_ARRAY_NEG_444928)]),
+                transaction(1, vec![("initializeDynamicTickArray", INITIALIZE_TICK_ARRAY_439296)]),
+            ]),
+        ]
+    }
+
+    fn whirlpool_transaction(slot: u64, transactions: Vec<Transaction>) -> WhirlpoolTransaction {
+        WhirlpoolTransaction {
+            slot,
+            block_height: slot,
+            block_time: 1700000000 + slot as i64,
+            transactions,
+        }
+    }
+
+    fn transaction(index: u32, instructions: Vec<(&str, &str)>) -> Transaction {
+        Transaction {
+            index,
+            signature: format!("signature-{}", index),
+            payer: "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6".to_string(),
+            balances: vec![],
+            instructions: instructions
+                .into_iter()
+                .map(|(name, payload)| TransactionInstruction {
+                    name: name.to_string(),
+                    payload: serde_json::from_str(payload).unwrap(),
+                })
+                .collect(),
+        }
+    }
+
+    fn build_replayer() -> WhirlpoolReplayer {
+        let state = WhirlpoolState {
+            slot: 1,
+            block_height: 1,
+            block_time: 1700000000,
+            accounts: AccountDataStore::new_on_memory(),
+            program_data: WHIRLPOOL_PROGRAM_FOR_TESTING.to_vec(),
+        };
+        WhirlpoolReplayer::build_with_transaction_source(state, VecTransactionSource::new(whirlpool_transactions()))
+    }
+
+    fn accounts(replayer: &WhirlpoolReplayer) -> BTreeMap<String, Vec<u8>> {
+        let mut accounts = BTreeMap::new();
+        replayer
+            .get_accounts()
+            .traverse(|pubkey, data| {
+                accounts.insert(pubkey.clone(), data.clone());
+                Ok(())
+            })
+            .unwrap();
+        accounts
+    }
+
+    // records (slot, signature, instruction name) of the replayed instructions
+    fn recording_callback() -> (SyncInstructionCallback, Rc<RefCell<Vec<(u64, String, String)>>>) {
+        let replayed = Rc::new(RefCell::new(vec![]));
+        let replayed_clone = Rc::clone(&replayed);
+        let callback: SyncInstructionCallback = Rc::new(move |slot, transaction, name, _instruction, _accounts, _snapshot, _execution_record| {
+            replayed_clone.borrow_mut().push((slot.slot, transaction.signature.clone(), name.clone()));
+        });
+        (callback, replayed)
+    }
+
+    fn replay_all() -> WhirlpoolReplayer {
+        let mut replayer = build_replayer();
+        replayer.replay(ReplayUntil::End, None, None, None).unwrap();
+        replayer
+    }
+
+    #[test]
+    fn test_replay() {
+        let (callback, replayed) = recording_callback();
+        let mut replayer = build_replayer();
+        replayer.replay(ReplayUntil::End, Some(callback), None, None).unwrap();
+
+        assert_eq!(replayer.get_slot().slot, 12);
+        assert_eq!(replayed.borrow().len(), 5);
+        let accounts = accounts(&replayer);
+        for pubkey in CREATED_ACCOUNTS {
+            assert!(accounts.contains_key(pubkey), "{}", pubkey);
+        }
+    }
+
+    #[test]
+    fn test_replay_until_slot_keeps_next_slot() {
+        let mut replayer = build_replayer();
+        replayer.replay(ReplayUntil::Slot(11), None, None, None).unwrap();
+        assert_eq!(replayer.get_slot().slot, 11);
+        assert_eq!(replayer.get_position(), ReplayPosition { slot: 11, next_instruction: None });
+
+        // the slot that reached the condition is replayed by the next call
+        replayer.replay(ReplayUntil::End, None, None, None).unwrap();
+        assert_eq!(accounts(&replayer), accounts(&replay_all()));
+    }
+
+    #[test]
+    fn test_step_instruction() {
+        let mut replayer = build_replayer();
+        let mut slots = vec![];
+        while let Some(slot) = replayer.step_instruction(None, None, None).unwrap() {
+            slots.push(slot.slot);
+        }
+        // slot 11 (no instruction) is processed on the way
+        assert_eq!(slots, vec![10, 10, 10, 12, 12]);
+        assert_eq!(replayer.get_slot().slot, 12);
+        assert_eq!(accounts(&replayer), accounts(&replay_all()));
+    }
+
+    #[test]
+    fn test_step_instruction_then_replay() {
+        let expected = replay_all();
+
+        for steps in 0..=5 {
+            let (callback, replayed) = recording_callback();
+            let mut replayer = build_replayer();
+            for _ in 0..steps {
+                assert!(replayer.step_instruction(Some(Rc::clone(&callback)), None, None).unwrap().is_some());
+            }
+            replayer.replay(ReplayUntil::End, Some(callback), None, None).unwrap();
+
+            // every instruction is replayed exactly once
+            assert_eq!(replayed.borrow().len(), 5, "steps={}", steps);
+            assert_eq!(replayer.get_slot(), expected.get_slot(), "steps={}", steps);
+            assert_eq!(accounts(&replayer), accounts(&expected), "steps={}", steps);
+        }
+    }
+
+    #[test]
+    fn test_step_slot() {
+        let mut replayer = build_replayer();
+
+        // the rest of the slot after step_instruction
+        assert_eq!(replayer.step_instruction(None, None, None).unwrap().map(|slot| slot.slot), Some(10));
+        assert_eq!(
+            replayer.get_position(),
+            ReplayPosition { slot: 10, next_instruction: Some(InstructionPosition { transaction_index: 0, instruction_index: 1 }) },
+        );
+        assert_eq!(replayer.step_slot(None, None, None).unwrap().map(|slot| slot.slot), Some(10));
+        assert_eq!(replayer.get_position(), ReplayPosition { slot: 10, next_instruction: None });
+
+        assert_eq!(replayer.step_slot(None, None, None).unwrap().map(|slot| slot.slot), Some(11));
+        assert_eq!(replayer.step_slot(None, None, None).unwrap().map(|slot| slot.slot), Some(12));
+        assert_eq!(replayer.step_slot(None, None, None).unwrap(), None);
+        assert_eq!(accounts(&replayer), accounts(&replay_all()));
+    }
+
+    // the state of the replayer is copied into a checkpoint (without file)
+    fn resume_from(replayer: &WhirlpoolReplayer) -> WhirlpoolReplayer {
+        let mut accounts = AccountDataStore::new_on_memory();
+        replayer
+            .get_accounts()
+            .traverse(|pubkey, data| accounts.upsert(pubkey, data))
+            .unwrap();
+        let slot = replayer.get_slot();
+        let checkpoint = schema::WhirlpoolCheckpoint {
+            slot: slot.slot,
+            block_height: slot.block_height,
+            block_time: slot.block_time,
+            position: replayer.get_position(),
+            accounts,
+            program_data: replayer.get_program_data().clone(),
+        };
+        let transaction_iter = Box::new(source::TransactionSourceIter::new(VecTransactionSource::new(whirlpool_transactions())));
+        WhirlpoolReplayer::resume(checkpoint, transaction_iter).unwrap()
+    }
+
+    #[test]
+    fn test_resume_in_the_middle_of_slot() {
+        let mut interrupted = build_replayer();
+        interrupted.step_instruction(None, None, None).unwrap();
+        interrupted.step_instruction(None, None, None).unwrap();
+        assert_eq!(
+            interrupted.get_position(),
+            ReplayPosition { slot: 10, next_instruction: Some(InstructionPosition { transaction_index: 1, instruction_index: 0 }) },
+        );
+
+        let (callback, replayed) = recording_callback();
+        let mut resumed = resume_from(&interrupted);
+        // the slot is not started again
+        resumed.replay(ReplayUntil::End, Some(callback), None, None).unwrap();
+
+        let replayed_instructions: Vec<(u64, String)> = replayed.borrow().iter().map(|(slot, _signature, name)| (*slot, name.clone())).collect();
+        assert_eq!(replayed_instructions, vec![
+            (10, "initializePoolV2".to_string()),
+            (12, "initializeDynamicTickArray".to_string()),
+            (12, "initializeDynamicTickArray".to_string()),
+        ]);
+        assert_eq!(accounts(&resumed), accounts(&replay_all()));
+    }
+
+    #[test]
+    fn test_resume_at_the_end_of_slot() {
+        let mut interrupted = build_replayer();
+        interrupted.step_slot(None, None, None).unwrap();
+        assert_eq!(interrupted.get_position(), ReplayPosition { slot: 10, next_instruction: None });
+
+        let (callback, replayed) = recording_callback();
+        let mut resumed = resume_from(&interrupted);
+        resumed.replay(ReplayUntil::End, Some(callback), None, None).unwrap();
+
+        assert!(replayed.borrow().iter().all(|(slot, _signature, _name)| *slot == 12));
+        assert_eq!(replayed.borrow().len(), 2);
+        assert_eq!(accounts(&resumed), accounts(&replay_all()));
+    }
+
+    fn resume_from_checkpoint_file(file_path: &String) -> WhirlpoolReplayer {
+        let checkpoint = io::load_from_local_whirlpool_checkpoint_file(file_path, &AccountDataStoreConfig::OnMemory).unwrap();
+        let transaction_iter = Box::new(source::TransactionSourceIter::new(VecTransactionSource::new(whirlpool_transactions())));
+        WhirlpoolReplayer::resume(checkpoint, transaction_iter).unwrap()
+    }
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let expected = replay_all();
+        let dir = tempfile::tempdir().unwrap();
+
+        // in the middle of slot 10, at the end of slot 10, in the middle of slot 12
+        for steps in [1, 3, 4] {
+            let mut interrupted = build_replayer();
+            for _ in 0..steps {
+                interrupted.step_instruction(None, None, None).unwrap();
+            }
+            let file_path = dir.path().join(format!("checkpoint-{}.json.zst", steps)).to_str().unwrap().to_string();
+            interrupted.save_checkpoint(&file_path).unwrap();
+
+            let checkpoint = io::load_from_local_whirlpool_checkpoint_file(&file_path, &AccountDataStoreConfig::OnMemory).unwrap();
+            assert_eq!(checkpoint.position, interrupted.get_position(), "steps={}", steps);
+            assert_eq!(Slot::new(checkpoint.slot, checkpoint.block_height, checkpoint.block_time), *interrupted.get_slot());
+
+            let (callback, replayed) = recording_callback();
+            let mut resumed = resume_from_checkpoint_file(&file_path);
+            resumed.replay(ReplayUntil::End, Some(callback), None, None).unwrap();
+
+            assert_eq!(replayed.borrow().len(), 5 - steps, "steps={}", steps);
+            assert_eq!(resumed.get_slot(), expected.get_slot(), "steps={}", steps);
+            assert_eq!(resumed.get_program_data(), expected.get_program_data(), "steps={}", steps);
+            assert_eq!(accounts(&resumed), accounts(&expected), "steps={}", steps);
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_autosave_at_slot_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("checkpoint.json.gz").to_str().unwrap().to_string();
+
+        // saved at the end of slot 10 and slot 11 (every slot)
+        let mut interrupted = build_replayer();
+        interrupted.set_checkpoint_autosave(&file_path, 1);
+        interrupted.replay(ReplayUntil::Slot(11), None, None, None).unwrap();
+
+        let checkpoint = io::load_from_local_whirlpool_checkpoint_file(&file_path, &AccountDataStoreConfig::OnMemory).unwrap();
+        assert_eq!(checkpoint.position, ReplayPosition { slot: 11, next_instruction: None });
+
+        let (callback, replayed) = recording_callback();
+        let mut resumed = resume_from_checkpoint_file(&file_path);
+        resumed.replay(ReplayUntil::End, Some(callback), None, None).unwrap();
+
+        assert_eq!(replayed.borrow().len(), 2);
+        assert_eq!(accounts(&resumed), accounts(&replay_all()));
+    }
+
+    // whirlpools 1, 2 and 3 are in the state of 20240101, and 20240102 has a two-hop swap on 2 and 1
+    fn save_two_hop_swap_day(base_path: &String) {
+        let previous = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let current = chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+
+        let mut accounts = AccountDataStore::new_on_memory();
+        for n in [1, 2, 3] {
+            let params = WhirlpoolParams {
+                token_mint_a: 10 + n,
+                token_mint_b: 20 + n,
+                fee_rate: 3000,
+                sqrt_price: 1 << 64,
+                tick_current_index: 0,
+                liquidity: 0,
+            };
+            accounts.upsert(&pubkey(n), &whirlpool_data(&params)).unwrap();
+        }
+        let state_file_path = format!("{}/{}", base_path, io::get_whirlpool_state_file_relative_path(&previous));
+        std::fs::create_dir_all(std::path::Path::new(&state_file_path).parent().unwrap()).unwrap();
+        io::save_to_whirlpool_state_file(&state_file_path, &Slot::new(1, 1, 1700000000), &WHIRLPOOL_PROGRAM_FOR_TESTING.to_vec(), &accounts).unwrap();
+
+        let two_hop_swap = serde_json::json!({ "keyWhirlpoolOne": pubkey(2), "keyWhirlpoolTwo": pubkey(1) }).to_string();
+        let whirlpool_transaction = whirlpool_transaction(10, vec![transaction(0, vec![("twoHopSwap", two_hop_swap.as_str())])]);
+        let transaction_file_path = format!("{}/{}", base_path, io::get_whirlpool_transaction_file_relative_path(&current));
+        io::save_to_compressed_file(&transaction_file_path, |writer| {
+            writeln!(writer, "{}", serde_json::to_string(&whirlpool_transaction)?)?;
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_build_with_filter_expands_by_two_hop_swaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().to_str().unwrap().to_string();
+        save_two_hop_swap_day(&base_path);
+
+        let day = "20240102".to_string();
+        let replayers = [
+            WhirlpoolReplayer::build_with_local_file_storage_with_filter(&base_path, &day, &AccountDataStoreConfig::OnMemory, WhirlpoolFilter::new([pubkey(1)])),
+            WhirlpoolReplayer::build_with_local_file_storage_with_date_range_with_filter(&base_path, &day, &day, &AccountDataStoreConfig::OnMemory, WhirlpoolFilter::new([pubkey(1)])),
+        ];
+
+        // only whirlpool 1 is in the filter, but the two-hop swap needs whirlpool 2, too
+        let expected: HashSet<String> = [pubkey(1), pubkey(2)].into_iter().collect();
+        for replayer in replayers {
+            let replayer = replayer.unwrap();
+            assert_eq!(replayer.get_whirlpool_filter().unwrap().get_whirlpools(), &expected);
+            assert_eq!(accounts(&replayer).into_keys().collect::<HashSet<String>>(), expected);
+        }
+    }
+}
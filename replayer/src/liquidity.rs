@@ -0,0 +1,487 @@
+use anchor_lang::AccountDeserialize;
+use replay_engine::decoded_instructions::{deserialize_u128, serialize_u128, DecodedWhirlpoolInstruction};
+use replay_engine::types::{AccountSnapshot, WritableAccountSnapshot};
+use ::serde::de;
+use serde_derive::{Deserialize, Serialize};
+use whirlpool_base::state::Position;
+
+use crate::errors::ReplayError;
+use crate::trade::get_whirlpool;
+
+/*
+
+Liquidity event
+
+A normalized view of the instructions changing positions and liquidity:
+- OpenPosition: openPosition, openPositionWithMetadata, openPositionWithTokenExtensions, openBundledPosition
+- IncreaseLiquidity: increaseLiquidity, increaseLiquidityV2, adminIncreaseLiquidity (no position)
+- DecreaseLiquidity: decreaseLiquidity, decreaseLiquidityV2
+- ResetPositionRange: resetPositionRange
+- ClosePosition: closePosition, closePositionWithTokenExtensions, closeBundledPosition
+
+- liquidity_delta: the change of the position liquidity (the change of the whirlpool liquidity for adminIncreaseLiquidity)
+- amount_a, amount_b: token amounts deposited into (or withdrawn from) the vaults
+- sqrt_price: sqrt_price of the whirlpool after the instruction (None if the whirlpool is not writable in the instruction)
+
+*/
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum LiquidityEventKind {
+    OpenPosition,
+    IncreaseLiquidity,
+    DecreaseLiquidity,
+    ResetPositionRange,
+    ClosePosition,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LiquidityEvent {
+    pub kind: LiquidityEventKind,
+    pub whirlpool: String,
+    // None for adminIncreaseLiquidity
+    pub position: Option<LiquidityPosition>,
+    #[serde(deserialize_with = "deserialize_i128", serialize_with = "serialize_i128")]
+    pub liquidity_delta: i128,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    #[serde(deserialize_with = "deserialize_option_u128", serialize_with = "serialize_option_u128")]
+    pub sqrt_price: Option<u128>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LiquidityPosition {
+    pub position: String,
+    pub position_mint: String,
+    // bundled position only
+    pub position_bundle: Option<String>,
+    pub bundle_index: Option<u16>,
+    pub tick_lower_index: i32,
+    pub tick_upper_index: i32,
+    // resetPositionRange only
+    pub previous_tick_lower_index: Option<i32>,
+    pub previous_tick_upper_index: Option<i32>,
+    #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+    pub pre_liquidity: u128,
+    #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+    pub post_liquidity: u128,
+}
+
+struct PositionChange<'a> {
+    kind: LiquidityEventKind,
+    position: &'a String,
+    whirlpool: Option<&'a String>,
+    bundle: Option<(&'a String, u16)>,
+    amount_a: u64,
+    amount_b: u64,
+}
+
+impl LiquidityEvent {
+    // liquidity event of the instruction (None for other instructions)
+    pub fn from_instruction(
+        instruction: &DecodedWhirlpoolInstruction,
+        snapshot: &WritableAccountSnapshot,
+    ) -> Result<Option<LiquidityEvent>, ReplayError> {
+        let change = match instruction {
+            DecodedWhirlpoolInstruction::OpenPosition(ix) => PositionChange::new(LiquidityEventKind::OpenPosition, &ix.key_position, Some(&ix.key_whirlpool)),
+            DecodedWhirlpoolInstruction::OpenPositionWithMetadata(ix) => PositionChange::new(LiquidityEventKind::OpenPosition, &ix.key_position, Some(&ix.key_whirlpool)),
+            DecodedWhirlpoolInstruction::OpenPositionWithTokenExtensions(ix) => PositionChange::new(LiquidityEventKind::OpenPosition, &ix.key_position, Some(&ix.key_whirlpool)),
+            DecodedWhirlpoolInstruction::OpenBundledPosition(ix) => PositionChange {
+                bundle: Some((&ix.key_position_bundle, ix.data_bundle_index)),
+                ..PositionChange::new(LiquidityEventKind::OpenPosition, &ix.key_bundled_position, Some(&ix.key_whirlpool))
+            },
+            DecodedWhirlpoolInstruction::IncreaseLiquidity(ix) => PositionChange {
+                amount_a: ix.transfer_amount_0,
+                amount_b: ix.transfer_amount_1,
+                ..PositionChange::new(LiquidityEventKind::IncreaseLiquidity, &ix.key_position, Some(&ix.key_whirlpool))
+            },
+            DecodedWhirlpoolInstruction::IncreaseLiquidityV2(ix) => PositionChange {
+                amount_a: ix.transfer_0.amount,
+                amount_b: ix.transfer_1.amount,
+                ..PositionChange::new(LiquidityEventKind::IncreaseLiquidity, &ix.key_position, Some(&ix.key_whirlpool))
+            },
+            DecodedWhirlpoolInstruction::DecreaseLiquidity(ix) => PositionChange {
+                amount_a: ix.transfer_amount_0,
+                amount_b: ix.transfer_amount_1,
+                ..PositionChange::new(LiquidityEventKind::DecreaseLiquidity, &ix.key_position, Some(&ix.key_whirlpool))
+            },
+            DecodedWhirlpoolInstruction::DecreaseLiquidityV2(ix) => PositionChange {
+                amount_a: ix.transfer_0.amount,
+                amount_b: ix.transfer_1.amount,
+                ..PositionChange::new(LiquidityEventKind::DecreaseLiquidity, &ix.key_position, Some(&ix.key_whirlpool))
+            },
+            DecodedWhirlpoolInstruction::ResetPositionRange(ix) => PositionChange::new(LiquidityEventKind::ResetPositionRange, &ix.key_position, Some(&ix.key_whirlpool)),
+            // the whirlpool is taken from the closed position
+            DecodedWhirlpoolInstruction::ClosePosition(ix) => PositionChange::new(LiquidityEventKind::ClosePosition, &ix.key_position, None),
+            DecodedWhirlpoolInstruction::ClosePositionWithTokenExtensions(ix) => PositionChange::new(LiquidityEventKind::ClosePosition, &ix.key_position, None),
+            DecodedWhirlpoolInstruction::CloseBundledPosition(ix) => PositionChange {
+                bundle: Some((&ix.key_position_bundle, ix.data_bundle_index)),
+                ..PositionChange::new(LiquidityEventKind::ClosePosition, &ix.key_bundled_position, None)
+            },
+            // the whirlpool liquidity is increased without position
+            DecodedWhirlpoolInstruction::AdminIncreaseLiquidity(ix) => {
+                return Ok(Some(LiquidityEvent {
+                    kind: LiquidityEventKind::IncreaseLiquidity,
+                    whirlpool: ix.key_whirlpool.clone(),
+                    position: None,
+                    liquidity_delta: ix.data_liquidity as i128,
+                    amount_a: 0,
+                    amount_b: 0,
+                    sqrt_price: post_sqrt_price(snapshot, &ix.key_whirlpool)?,
+                }));
+            }
+            _ => return Ok(None),
+        };
+
+        change.build(snapshot).map(Some)
+    }
+}
+
+impl<'a> PositionChange<'a> {
+    fn new(kind: LiquidityEventKind, position: &'a String, whirlpool: Option<&'a String>) -> PositionChange<'a> {
+        PositionChange {
+            kind,
+            position,
+            whirlpool,
+            bundle: None,
+            amount_a: 0,
+            amount_b: 0,
+        }
+    }
+
+    fn build(self, snapshot: &WritableAccountSnapshot) -> Result<LiquidityEvent, ReplayError> {
+        // pre is None for open, post is None for close
+        let pre = get_position(&snapshot.pre_snapshot, self.position)?;
+        let post = get_position(&snapshot.post_snapshot, self.position)?;
+        let current = post.as_ref().or(pre.as_ref()).ok_or_else(|| {
+            ReplayError::AccountDeserialize(format!("position not found in snapshot: {}", self.position))
+        })?;
+
+        let pre_liquidity = pre.as_ref().map_or(0, |position| position.liquidity);
+        let post_liquidity = post.as_ref().map_or(0, |position| position.liquidity);
+
+        let (previous_tick_lower_index, previous_tick_upper_index) = match (self.kind, pre.as_ref()) {
+            (LiquidityEventKind::ResetPositionRange, Some(pre)) => (Some(pre.tick_lower_index), Some(pre.tick_upper_index)),
+            _ => (None, None),
+        };
+
+        let whirlpool = self.whirlpool.cloned().unwrap_or_else(|| current.whirlpool.to_string());
+        let sqrt_price = post_sqrt_price(snapshot, &whirlpool)?;
+
+        Ok(LiquidityEvent {
+            kind: self.kind,
+            whirlpool,
+            position: Some(LiquidityPosition {
+                position: self.position.clone(),
+                position_mint: current.position_mint.to_string(),
+                position_bundle: self.bundle.map(|(position_bundle, _)| position_bundle.clone()),
+                bundle_index: self.bundle.map(|(_, bundle_index)| bundle_index),
+                tick_lower_index: current.tick_lower_index,
+                tick_upper_index: current.tick_upper_index,
+                previous_tick_lower_index,
+                previous_tick_upper_index,
+                pre_liquidity,
+                post_liquidity,
+            }),
+            liquidity_delta: post_liquidity as i128 - pre_liquidity as i128,
+            amount_a: self.amount_a,
+            amount_b: self.amount_b,
+            sqrt_price,
+        })
+    }
+}
+
+fn get_position(snapshot: &AccountSnapshot, pubkey: &String) -> Result<Option<Position>, ReplayError> {
+    match snapshot.get(pubkey) {
+        // not initialized yet (or already closed)
+        None => Ok(None),
+        Some(data) if data.is_empty() => Ok(None),
+        Some(data) => Position::try_deserialize(&mut data.as_slice())
+            .map(Some)
+            .map_err(|e| ReplayError::AccountDeserialize(format!("failed to deserialize position {}: {}", pubkey, e))),
+    }
+}
+
+fn post_sqrt_price(snapshot: &WritableAccountSnapshot, whirlpool: &String) -> Result<Option<u128>, ReplayError> {
+    if !snapshot.post_snapshot.contains_key(whirlpool) {
+        return Ok(None);
+    }
+    Ok(Some(get_whirlpool(&snapshot.post_snapshot, whirlpool)?.sqrt_price))
+}
+
+// i128 to string
+fn serialize_i128<S>(n: &i128, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: ::serde::Serializer,
+{
+    serializer.serialize_str(&n.to_string())
+}
+
+// string to i128
+fn deserialize_i128<'de, D>(deserializer: D) -> Result<i128, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let n: String = de::Deserialize::deserialize(deserializer)?;
+    n.parse::<i128>().map_err(|_| de::Error::custom("expected i128"))
+}
+
+// Option<u128> to string (or null)
+fn serialize_option_u128<S>(n: &Option<u128>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: ::serde::Serializer,
+{
+    match n {
+        Some(n) => serializer.serialize_some(&n.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+// string (or null) to Option<u128>
+fn deserialize_option_u128<'de, D>(deserializer: D) -> Result<Option<u128>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let n: Option<String> = de::Deserialize::deserialize(deserializer)?;
+    n.map(|n| n.parse::<u128>().map_err(|_| de::Error::custom("expected u128"))).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::prelude::Pubkey;
+    use anchor_lang::AccountSerialize;
+    use replay_engine::decoded_instructions::{
+        DecodedAdminIncreaseLiquidity, DecodedClosePosition, DecodedIncreaseLiquidity, DecodedOpenPosition, DecodedResetPositionRange,
+    };
+    use crate::test_utils::{pubkey, whirlpool_data, writable_account_snapshot, WhirlpoolParams};
+    use crate::decoder::account_discriminator;
+
+    const WHIRLPOOL: u8 = 10;
+    const POSITION: u8 = 20;
+    const POSITION_MINT: u8 = 21;
+
+    // discriminator(8) + whirlpool(32) + position_mint(32) + liquidity(16) + ticks(8) + fees(48) + rewards(72)
+    const POSITION_LEN: usize = 216;
+
+    fn position(liquidity: u128, tick_lower_index: i32, tick_upper_index: i32) -> (String, Vec<u8>) {
+        let mut zero = vec![0u8; POSITION_LEN];
+        zero[0..8].copy_from_slice(&account_discriminator("Position"));
+
+        let mut position = Position::try_deserialize(&mut zero.as_slice()).unwrap();
+        position.whirlpool = Pubkey::new_from_array([WHIRLPOOL; 32]);
+        position.position_mint = Pubkey::new_from_array([POSITION_MINT; 32]);
+        position.liquidity = liquidity;
+        position.tick_lower_index = tick_lower_index;
+        position.tick_upper_index = tick_upper_index;
+
+        let mut data = vec![];
+        position.try_serialize(&mut data).unwrap();
+        (pubkey(POSITION), data)
+    }
+
+    fn whirlpool(sqrt_price: u128) -> (String, Vec<u8>) {
+        let data = whirlpool_data(&WhirlpoolParams {
+            token_mint_a: 1,
+            token_mint_b: 2,
+            fee_rate: 3000,
+            sqrt_price,
+            tick_current_index: 0,
+            liquidity: 0,
+        });
+        (pubkey(WHIRLPOOL), data)
+    }
+
+    fn liquidity_position(tick_lower_index: i32, tick_upper_index: i32, pre_liquidity: u128, post_liquidity: u128) -> LiquidityPosition {
+        LiquidityPosition {
+            position: pubkey(POSITION),
+            position_mint: pubkey(POSITION_MINT),
+            position_bundle: None,
+            bundle_index: None,
+            tick_lower_index,
+            tick_upper_index,
+            previous_tick_lower_index: None,
+            previous_tick_upper_index: None,
+            pre_liquidity,
+            post_liquidity,
+        }
+    }
+
+    fn open_position() -> DecodedWhirlpoolInstruction {
+        DecodedWhirlpoolInstruction::OpenPosition(DecodedOpenPosition {
+            data_tick_lower_index: -128,
+            data_tick_upper_index: 128,
+            key_funder: pubkey(0),
+            key_owner: pubkey(0),
+            key_position: pubkey(POSITION),
+            key_position_mint: pubkey(POSITION_MINT),
+            key_position_token_account: pubkey(0),
+            key_whirlpool: pubkey(WHIRLPOOL),
+            key_token_program: pubkey(0),
+            key_system_program: pubkey(0),
+            key_rent: pubkey(0),
+            key_associated_token_program: pubkey(0),
+        })
+    }
+
+    fn increase_liquidity(liquidity_amount: u128, amount_a: u64, amount_b: u64) -> DecodedWhirlpoolInstruction {
+        DecodedWhirlpoolInstruction::IncreaseLiquidity(DecodedIncreaseLiquidity {
+            data_liquidity_amount: liquidity_amount,
+            data_token_amount_max_a: amount_a,
+            data_token_amount_max_b: amount_b,
+            key_whirlpool: pubkey(WHIRLPOOL),
+            key_token_program: pubkey(0),
+            key_position_authority: pubkey(0),
+            key_position: pubkey(POSITION),
+            key_position_token_account: pubkey(0),
+            key_token_owner_account_a: pubkey(0),
+            key_token_owner_account_b: pubkey(0),
+            key_token_vault_a: pubkey(0),
+            key_token_vault_b: pubkey(0),
+            key_tick_array_lower: pubkey(0),
+            key_tick_array_upper: pubkey(0),
+            transfer_amount_0: amount_a,
+            transfer_amount_1: amount_b,
+        })
+    }
+
+    fn close_position() -> DecodedWhirlpoolInstruction {
+        DecodedWhirlpoolInstruction::ClosePosition(DecodedClosePosition {
+            key_position_authority: pubkey(0),
+            key_receiver: pubkey(0),
+            key_position: pubkey(POSITION),
+            key_position_mint: pubkey(POSITION_MINT),
+            key_position_token_account: pubkey(0),
+            key_token_program: pubkey(0),
+        })
+    }
+
+    fn reset_position_range(new_tick_lower_index: i32, new_tick_upper_index: i32) -> DecodedWhirlpoolInstruction {
+        DecodedWhirlpoolInstruction::ResetPositionRange(DecodedResetPositionRange {
+            data_new_tick_lower_index: new_tick_lower_index,
+            data_new_tick_upper_index: new_tick_upper_index,
+            key_funder: pubkey(0),
+            key_position_authority: pubkey(0),
+            key_whirlpool: pubkey(WHIRLPOOL),
+            key_position: pubkey(POSITION),
+            key_position_token_account: pubkey(0),
+            key_system_program: pubkey(0),
+        })
+    }
+
+    #[test]
+    fn test_open_position() {
+        // the position is not initialized before open (pre is missing), and the whirlpool is not writable
+        let snapshot = writable_account_snapshot(&[], &[position(0, -128, 128)]);
+        let event = LiquidityEvent::from_instruction(&open_position(), &snapshot).unwrap().unwrap();
+        assert_eq!(
+            event,
+            LiquidityEvent {
+                kind: LiquidityEventKind::OpenPosition,
+                whirlpool: pubkey(WHIRLPOOL),
+                position: Some(liquidity_position(-128, 128, 0, 0)),
+                liquidity_delta: 0,
+                amount_a: 0,
+                amount_b: 0,
+                sqrt_price: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_increase_liquidity() {
+        let snapshot = writable_account_snapshot(
+            &[position(1000, -128, 128), whirlpool(100)],
+            &[position(1500, -128, 128), whirlpool(100)],
+        );
+        let event = LiquidityEvent::from_instruction(&increase_liquidity(500, 10, 20), &snapshot).unwrap().unwrap();
+        assert_eq!(event.kind, LiquidityEventKind::IncreaseLiquidity);
+        assert_eq!(event.position, Some(liquidity_position(-128, 128, 1000, 1500)));
+        assert_eq!((event.liquidity_delta, event.amount_a, event.amount_b), (500, 10, 20));
+        assert_eq!(event.sqrt_price, Some(100));
+    }
+
+    #[test]
+    fn test_close_position() {
+        // the closed position account is empty in post, and the whirlpool is taken from pre
+        let snapshot = writable_account_snapshot(&[position(0, -128, 128)], &[(pubkey(POSITION), vec![])]);
+        let event = LiquidityEvent::from_instruction(&close_position(), &snapshot).unwrap().unwrap();
+        assert_eq!(
+            event,
+            LiquidityEvent {
+                kind: LiquidityEventKind::ClosePosition,
+                whirlpool: pubkey(WHIRLPOOL),
+                position: Some(liquidity_position(-128, 128, 0, 0)),
+                liquidity_delta: 0,
+                amount_a: 0,
+                amount_b: 0,
+                sqrt_price: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_reset_position_range() {
+        let snapshot = writable_account_snapshot(
+            &[position(0, -128, 128), whirlpool(100)],
+            &[position(0, -256, 64), whirlpool(100)],
+        );
+        let event = LiquidityEvent::from_instruction(&reset_position_range(-256, 64), &snapshot).unwrap().unwrap();
+        assert_eq!(event.kind, LiquidityEventKind::ResetPositionRange);
+        assert_eq!(
+            event.position,
+            Some(LiquidityPosition {
+                previous_tick_lower_index: Some(-128),
+                previous_tick_upper_index: Some(128),
+                ..liquidity_position(-256, 64, 0, 0)
+            })
+        );
+        assert_eq!(event.sqrt_price, Some(100));
+    }
+
+    #[test]
+    fn test_admin_increase_liquidity() {
+        let instruction = DecodedWhirlpoolInstruction::AdminIncreaseLiquidity(DecodedAdminIncreaseLiquidity {
+            data_liquidity: 1_000_000,
+            key_whirlpools_config: pubkey(0),
+            key_whirlpool: pubkey(WHIRLPOOL),
+            key_authority: pubkey(0),
+        });
+        let snapshot = writable_account_snapshot(&[whirlpool(100)], &[whirlpool(100)]);
+        let event = LiquidityEvent::from_instruction(&instruction, &snapshot).unwrap().unwrap();
+        assert_eq!(
+            event,
+            LiquidityEvent {
+                kind: LiquidityEventKind::IncreaseLiquidity,
+                whirlpool: pubkey(WHIRLPOOL),
+                position: None,
+                liquidity_delta: 1_000_000,
+                amount_a: 0,
+                amount_b: 0,
+                sqrt_price: Some(100),
+            }
+        );
+    }
+
+    #[test]
+    fn test_position_not_found() {
+        let snapshot = writable_account_snapshot(&[], &[]);
+        assert!(LiquidityEvent::from_instruction(&open_position(), &snapshot).is_err());
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let snapshot = writable_account_snapshot(
+            &[position(0, -128, 128), whirlpool(u128::MAX)],
+            &[position(0, -256, 64), whirlpool(u128::MAX)],
+        );
+        let event = LiquidityEvent::from_instruction(&reset_position_range(-256, 64), &snapshot).unwrap().unwrap();
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["kind"], "resetPositionRange");
+        assert_eq!(json["sqrtPrice"], u128::MAX.to_string());
+        assert_eq!(json["liquidityDelta"], "0");
+        assert_eq!(serde_json::from_value::<LiquidityEvent>(json).unwrap(), event);
+    }
+}
@@ -0,0 +1,480 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use replay_engine::decoded_instructions::DecodedWhirlpoolInstruction;
+use replay_engine::types::{Slot, WritableAccountSnapshot};
+
+use crate::errors::ReplayError;
+use crate::io;
+use crate::schema::{Candle, DecimalPriceOhlc, SqrtPriceOhlc, Volume, WhirlpoolOhlcv, WhirlpoolOhlcvData, WhirlpoolToken};
+use crate::trade::TradeEvent;
+use crate::SyncInstructionCallback;
+
+const SECONDS_PER_MINUTE: i64 = 60;
+
+// build OHLCV candles (daily and minutely) of each whirlpool from swap instructions
+// (swap, swapV2, twoHopSwap and twoHopSwapV2)
+pub struct OhlcvBuilder {
+    decimals: HashMap<String, u8>,
+    // date (UTC) -> whirlpool -> candles
+    days: BTreeMap<chrono::NaiveDate, BTreeMap<String, PoolCandles>>,
+    // the first error in the instruction callback (callback cannot return error)
+    error: Option<ReplayError>,
+}
+
+struct PoolCandles {
+    token_mint_a: String,
+    token_mint_b: String,
+    daily: CandleBuilder,
+    minutely: BTreeMap<i64, CandleBuilder>,
+}
+
+#[derive(Clone, Copy)]
+struct CandleBuilder {
+    timestamp: i64,
+    open: u128,
+    high: u128,
+    low: u128,
+    close: u128,
+    volume_a: u128,
+    volume_b: u128,
+    swaps: u64,
+}
+
+impl OhlcvBuilder {
+    // decimals are taken from the token file (decimal price is not available for unknown tokens)
+    pub fn new(tokens: &WhirlpoolToken) -> OhlcvBuilder {
+        let decimals = tokens.tokens.iter().map(|token| (token.mint.clone(), token.decimals)).collect();
+        OhlcvBuilder::new_with_decimals(decimals)
+    }
+
+    pub fn new_with_decimals(decimals: HashMap<String, u8>) -> OhlcvBuilder {
+        OhlcvBuilder {
+            decimals,
+            days: BTreeMap::new(),
+            error: None,
+        }
+    }
+
+    // instruction callback to feed the replayed instructions into the builder
+    pub fn instruction_callback(builder: &Rc<RefCell<OhlcvBuilder>>) -> SyncInstructionCallback {
+        let builder = Rc::clone(builder);
+        Rc::new(move |slot, _transaction, _name, instruction, _accounts, snapshot, _execution_record| {
+            let mut builder = builder.borrow_mut();
+            if builder.error.is_some() {
+                return;
+            }
+            if let Err(err) = builder.process_instruction(slot, instruction, snapshot) {
+                builder.error = Some(err);
+            }
+        })
+    }
+
+    pub fn process_instruction(
+        &mut self,
+        slot: &Slot,
+        instruction: &DecodedWhirlpoolInstruction,
+        snapshot: &WritableAccountSnapshot,
+    ) -> Result<(), ReplayError> {
+        for trade in TradeEvent::from_instruction(instruction, snapshot)? {
+            let (volume_a, volume_b) = trade.amounts_ab();
+
+            let date = chrono::DateTime::from_timestamp(slot.block_time, 0)
+                .ok_or_else(|| ReplayError::InvalidDate(format!("invalid blockTime: {}", slot.block_time)))?
+                .date_naive();
+            let day_timestamp = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+            let minute_timestamp = slot.block_time - slot.block_time.rem_euclid(SECONDS_PER_MINUTE);
+
+            let pool = self.days
+                .entry(date)
+                .or_default()
+                .entry(trade.whirlpool.clone())
+                .or_insert_with(|| PoolCandles {
+                    token_mint_a: trade.token_mint_a.clone(),
+                    token_mint_b: trade.token_mint_b.clone(),
+                    daily: CandleBuilder::new(day_timestamp, trade.pre_sqrt_price),
+                    minutely: BTreeMap::new(),
+                });
+
+            pool.daily.update(trade.pre_sqrt_price, trade.post_sqrt_price, volume_a, volume_b);
+            pool.minutely
+                .entry(minute_timestamp)
+                .or_insert_with(|| CandleBuilder::new(minute_timestamp, trade.pre_sqrt_price))
+                .update(trade.pre_sqrt_price, trade.post_sqrt_price, volume_a, volume_b);
+        }
+        Ok(())
+    }
+
+    // OHLCV of each day (in date order)
+    pub fn build(&self) -> Result<Vec<WhirlpoolOhlcv>, ReplayError> {
+        if let Some(err) = self.error.as_ref() {
+            return Err(ReplayError::Ohlcv(err.to_string()));
+        }
+
+        Ok(self.days
+            .iter()
+            .map(|(date, pools)| WhirlpoolOhlcv {
+                date: date.format("%Y%m%d").to_string(),
+                ohlcv: pools
+                    .iter()
+                    .map(|(whirlpool, pool)| self.build_pool(whirlpool, pool))
+                    .collect(),
+            })
+            .collect())
+    }
+
+    // save JSON and CSV files in the archive layout (<base_path>/<YYYY>/<MMDD>/whirlpool-ohlcv-*-<YYYYMMDD>.*.gz)
+    pub fn save(&self, base_path: &String) -> Result<(), ReplayError> {
+        // build returns the days in the same order as self.days
+        for (date, ohlcv) in self.days.keys().zip(self.build()?) {
+            // daily file doesn't have minutely candles
+            let daily = WhirlpoolOhlcv {
+                date: ohlcv.date.clone(),
+                ohlcv: ohlcv.ohlcv.iter().map(|data| WhirlpoolOhlcvData { minutely: vec![], ..data.clone() }).collect(),
+            };
+
+            let file_path = |relative_path: String| format!("{}/{}", base_path, relative_path);
+            io::save_to_compressed_file(&file_path(io::get_whirlpool_ohlcv_daily_file_relative_path(date)), |writer| {
+                Ok(serde_json::to_writer(writer, &daily)?)
+            })?;
+            io::save_to_compressed_file(&file_path(io::get_whirlpool_ohlcv_minutely_file_relative_path(date)), |writer| {
+                Ok(serde_json::to_writer(writer, &ohlcv)?)
+            })?;
+            io::save_to_compressed_file(&file_path(io::get_whirlpool_ohlcv_daily_csv_file_relative_path(date)), |writer| {
+                write_csv(writer, &ohlcv, false)
+            })?;
+            io::save_to_compressed_file(&file_path(io::get_whirlpool_ohlcv_minutely_csv_file_relative_path(date)), |writer| {
+                write_csv(writer, &ohlcv, true)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn build_pool(&self, whirlpool: &String, pool: &PoolCandles) -> WhirlpoolOhlcvData {
+        let decimals_a = self.decimals.get(&pool.token_mint_a).copied();
+        let decimals_b = self.decimals.get(&pool.token_mint_b).copied();
+        let decimals = decimals_a.zip(decimals_b);
+
+        WhirlpoolOhlcvData {
+            whirlpool: whirlpool.clone(),
+            token_mint_a: pool.token_mint_a.clone(),
+            token_mint_b: pool.token_mint_b.clone(),
+            decimals_a,
+            decimals_b,
+            daily: pool.daily.build(decimals),
+            minutely: pool.minutely.values().map(|candle| candle.build(decimals)).collect(),
+        }
+    }
+}
+
+impl CandleBuilder {
+    fn new(timestamp: i64, open: u128) -> CandleBuilder {
+        CandleBuilder {
+            timestamp,
+            open,
+            high: open,
+            low: open,
+            close: open,
+            volume_a: 0,
+            volume_b: 0,
+            swaps: 0,
+        }
+    }
+
+    // price moves from pre to post in a swap
+    fn update(&mut self, pre_sqrt_price: u128, post_sqrt_price: u128, volume_a: u64, volume_b: u64) {
+        self.high = self.high.max(pre_sqrt_price).max(post_sqrt_price);
+        self.low = self.low.min(pre_sqrt_price).min(post_sqrt_price);
+        self.close = post_sqrt_price;
+        self.volume_a += volume_a as u128;
+        self.volume_b += volume_b as u128;
+        self.swaps += 1;
+    }
+
+    fn build(&self, decimals: Option<(u8, u8)>) -> Candle {
+        Candle {
+            timestamp: self.timestamp,
+            sqrt_price: SqrtPriceOhlc {
+                open: self.open,
+                high: self.high,
+                low: self.low,
+                close: self.close,
+            },
+            decimal_price: decimals.map(|(decimals_a, decimals_b)| DecimalPriceOhlc {
+                open: sqrt_price_to_decimal_price(self.open, decimals_a, decimals_b),
+                high: sqrt_price_to_decimal_price(self.high, decimals_a, decimals_b),
+                low: sqrt_price_to_decimal_price(self.low, decimals_a, decimals_b),
+                close: sqrt_price_to_decimal_price(self.close, decimals_a, decimals_b),
+            }),
+            volume: Volume {
+                a: self.volume_a,
+                b: self.volume_b,
+            },
+            swaps: self.swaps,
+        }
+    }
+}
+
+// price of token A in token B (sqrt_price is Q64.64)
+pub fn sqrt_price_to_decimal_price(sqrt_price: u128, decimals_a: u8, decimals_b: u8) -> f64 {
+    let sqrt_price = sqrt_price as f64 / 2f64.powi(64);
+    sqrt_price * sqrt_price * 10f64.powi(decimals_a as i32 - decimals_b as i32)
+}
+
+fn write_csv(writer: &mut dyn std::io::Write, ohlcv: &WhirlpoolOhlcv, minutely: bool) -> Result<(), ReplayError> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record([
+        "whirlpool",
+        "timestamp",
+        "sqrt_price_open",
+        "sqrt_price_high",
+        "sqrt_price_low",
+        "sqrt_price_close",
+        "decimal_price_open",
+        "decimal_price_high",
+        "decimal_price_low",
+        "decimal_price_close",
+        "volume_a",
+        "volume_b",
+        "swaps",
+    ])?;
+
+    for data in ohlcv.ohlcv.iter() {
+        let candles = if minutely {
+            data.minutely.iter().collect::<Vec<&Candle>>()
+        } else {
+            vec![&data.daily]
+        };
+        for candle in candles {
+            let decimal_price = |f: fn(&DecimalPriceOhlc) -> f64| {
+                candle.decimal_price.as_ref().map_or(String::new(), |price| f(price).to_string())
+            };
+            csv_writer.write_record([
+                data.whirlpool.clone(),
+                candle.timestamp.to_string(),
+                candle.sqrt_price.open.to_string(),
+                candle.sqrt_price.high.to_string(),
+                candle.sqrt_price.low.to_string(),
+                candle.sqrt_price.close.to_string(),
+                decimal_price(|price| price.open),
+                decimal_price(|price| price.high),
+                decimal_price(|price| price.low),
+                decimal_price(|price| price.close),
+                candle.volume.a.to_string(),
+                candle.volume.b.to_string(),
+                candle.swaps.to_string(),
+            ])?;
+        }
+    }
+
+    csv_writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{pubkey, swap, whirlpool_data, writable_account_snapshot, WhirlpoolParams};
+
+    // 2024-01-01 00:00:00 UTC
+    const DAY_1: i64 = 1704067200;
+    const DAY_2: i64 = DAY_1 + 86400;
+
+    const WHIRLPOOL: u8 = 10;
+    const ORACLE: u8 = 11;
+    const UNKNOWN_TOKEN_WHIRLPOOL: u8 = 20;
+    const MINT_A: u8 = 1;
+    const MINT_B: u8 = 2;
+
+    struct SwapParams {
+        block_time: i64,
+        whirlpool: u8,
+        a_to_b: bool,
+        amount_in: u64,
+        amount_out: u64,
+        pre_sqrt_price: u128,
+        post_sqrt_price: u128,
+    }
+
+    fn process_swap(builder: &mut OhlcvBuilder, params: SwapParams) {
+        // the token mints of UNKNOWN_TOKEN_WHIRLPOOL are not in the decimals
+        let (token_mint_a, token_mint_b) = if params.whirlpool == UNKNOWN_TOKEN_WHIRLPOOL { (3, 4) } else { (MINT_A, MINT_B) };
+        let whirlpool = |sqrt_price| whirlpool_data(&WhirlpoolParams {
+            token_mint_a,
+            token_mint_b,
+            fee_rate: 3000,
+            sqrt_price,
+            tick_current_index: 0,
+            liquidity: 1_000_000,
+        });
+
+        let snapshot = writable_account_snapshot(
+            &[(pubkey(params.whirlpool), whirlpool(params.pre_sqrt_price))],
+            &[(pubkey(params.whirlpool), whirlpool(params.post_sqrt_price))],
+        );
+        let instruction = swap(&pubkey(params.whirlpool), &pubkey(ORACLE), params.a_to_b, params.amount_in, params.amount_out);
+        let slot = Slot::new(params.block_time as u64, params.block_time as u64, params.block_time);
+        builder.process_instruction(&slot, &instruction, &snapshot).unwrap();
+    }
+
+    fn candle(timestamp: i64, (open, high, low, close): (u128, u128, u128, u128), (a, b): (u128, u128), swaps: u64) -> Candle {
+        Candle {
+            timestamp,
+            sqrt_price: SqrtPriceOhlc { open, high, low, close },
+            decimal_price: None,
+            volume: Volume { a, b },
+            swaps,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() <= expected.abs() * 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_candles_across_minute_and_day_boundary() {
+        let mut builder = OhlcvBuilder::new_with_decimals(HashMap::new());
+        let swaps = [
+            // 2024-01-01 23:58:30
+            SwapParams { block_time: DAY_2 - 90, whirlpool: WHIRLPOOL, a_to_b: true, amount_in: 1000, amount_out: 900, pre_sqrt_price: 100, post_sqrt_price: 90 },
+            // 2024-01-01 23:58:50
+            SwapParams { block_time: DAY_2 - 70, whirlpool: WHIRLPOOL, a_to_b: false, amount_in: 500, amount_out: 450, pre_sqrt_price: 90, post_sqrt_price: 120 },
+            // 2024-01-01 23:59:10
+            SwapParams { block_time: DAY_2 - 50, whirlpool: WHIRLPOOL, a_to_b: true, amount_in: 300, amount_out: 250, pre_sqrt_price: 120, post_sqrt_price: 110 },
+            // 2024-01-02 00:00:05
+            SwapParams { block_time: DAY_2 + 5, whirlpool: WHIRLPOOL, a_to_b: true, amount_in: 200, amount_out: 150, pre_sqrt_price: 110, post_sqrt_price: 105 },
+        ];
+        for params in swaps {
+            process_swap(&mut builder, params);
+        }
+
+        let ohlcv = builder.build().unwrap();
+        assert_eq!(ohlcv.len(), 2);
+
+        assert_eq!(ohlcv[0].date, "20240101");
+        assert_eq!(ohlcv[0].ohlcv.len(), 1);
+        let day_1 = &ohlcv[0].ohlcv[0];
+        assert_eq!(day_1.whirlpool, pubkey(WHIRLPOOL));
+        assert_eq!(day_1.token_mint_a, pubkey(MINT_A));
+        assert_eq!(day_1.token_mint_b, pubkey(MINT_B));
+        assert_eq!(day_1.decimals_a, None);
+        assert_eq!(day_1.daily, candle(DAY_1, (100, 120, 90, 110), (1000 + 450 + 300, 900 + 500 + 250), 3));
+        assert_eq!(
+            day_1.minutely,
+            vec![
+                candle(DAY_2 - 120, (100, 120, 90, 120), (1000 + 450, 900 + 500), 2),
+                candle(DAY_2 - 60, (120, 120, 110, 110), (300, 250), 1),
+            ]
+        );
+
+        // the next day starts with the price of the first swap in the day (not the close of the previous day)
+        assert_eq!(ohlcv[1].date, "20240102");
+        let day_2 = &ohlcv[1].ohlcv[0];
+        assert_eq!(day_2.daily, candle(DAY_2, (110, 110, 105, 105), (200, 150), 1));
+        assert_eq!(day_2.minutely, vec![candle(DAY_2, (110, 110, 105, 105), (200, 150), 1)]);
+    }
+
+    #[test]
+    fn test_sqrt_price_to_decimal_price() {
+        let q64 = 2f64.powi(64);
+
+        // price 1.0 (raw) is 0.001 token B per token A with decimals (6, 9)
+        assert_close(sqrt_price_to_decimal_price(1u128 << 64, 6, 9), 0.001);
+        assert_close(sqrt_price_to_decimal_price(2u128 << 64, 6, 9), 0.004);
+        assert_close(sqrt_price_to_decimal_price(1u128 << 63, 6, 9), 0.00025);
+        assert_close(sqrt_price_to_decimal_price(1u128 << 64, 9, 6), 1000.0);
+        assert_close(sqrt_price_to_decimal_price(1u128 << 64, 6, 6), 1.0);
+
+        // 0.01 token B (9 decimals) per token A (6 decimals) is 10 (raw)
+        let sqrt_price = (10f64.sqrt() * q64) as u128;
+        assert_close(sqrt_price_to_decimal_price(sqrt_price, 6, 9), 0.01);
+        // 100 token B (6 decimals) per token A (9 decimals) is 0.1 (raw)
+        let sqrt_price = (0.1f64.sqrt() * q64) as u128;
+        assert_close(sqrt_price_to_decimal_price(sqrt_price, 9, 6), 100.0);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let decimals = HashMap::from([(pubkey(MINT_A), 6), (pubkey(MINT_B), 9)]);
+        let mut builder = OhlcvBuilder::new_with_decimals(decimals);
+        let (p1, p2, p3) = (1u128 << 64, 2u128 << 64, 3u128 << 64);
+        let swaps = [
+            SwapParams { block_time: DAY_1 + 10, whirlpool: WHIRLPOOL, a_to_b: false, amount_in: 1000, amount_out: 900, pre_sqrt_price: p1, post_sqrt_price: p2 },
+            SwapParams { block_time: DAY_1 + 70, whirlpool: WHIRLPOOL, a_to_b: false, amount_in: 500, amount_out: 400, pre_sqrt_price: p2, post_sqrt_price: p3 },
+            SwapParams { block_time: DAY_1 + 20, whirlpool: UNKNOWN_TOKEN_WHIRLPOOL, a_to_b: true, amount_in: 100, amount_out: 50, pre_sqrt_price: p2, post_sqrt_price: p1 },
+        ];
+        for params in swaps {
+            process_swap(&mut builder, params);
+        }
+        let ohlcv = builder.build().unwrap();
+        assert_eq!(ohlcv.len(), 1);
+
+        let write = |minutely| {
+            let mut buffer = vec![];
+            write_csv(&mut buffer, &ohlcv[0], minutely).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        let rows = |csv: &String, whirlpool: u8| -> Vec<Vec<String>> {
+            csv.lines()
+                .skip(1)
+                .map(|line| line.split(',').map(|column| column.to_string()).collect::<Vec<String>>())
+                .filter(|columns| columns[0] == pubkey(whirlpool))
+                .collect()
+        };
+        let decimal_price = |sqrt_price| sqrt_price_to_decimal_price(sqrt_price, 6, 9).to_string();
+        let header = "whirlpool,timestamp,sqrt_price_open,sqrt_price_high,sqrt_price_low,sqrt_price_close,decimal_price_open,decimal_price_high,decimal_price_low,decimal_price_close,volume_a,volume_b,swaps";
+
+        let daily = write(false);
+        assert_eq!(daily.lines().next(), Some(header));
+        assert_eq!(daily.lines().count(), 1 + 2);
+        assert_eq!(
+            rows(&daily, WHIRLPOOL),
+            vec![vec![
+                pubkey(WHIRLPOOL),
+                DAY_1.to_string(),
+                p1.to_string(),
+                p3.to_string(),
+                p1.to_string(),
+                p3.to_string(),
+                decimal_price(p1),
+                decimal_price(p3),
+                decimal_price(p1),
+                decimal_price(p3),
+                "1300".to_string(),
+                "1500".to_string(),
+                "2".to_string(),
+            ]]
+        );
+        // decimal price is empty for unknown tokens
+        assert_eq!(
+            rows(&daily, UNKNOWN_TOKEN_WHIRLPOOL),
+            vec![vec![
+                pubkey(UNKNOWN_TOKEN_WHIRLPOOL),
+                DAY_1.to_string(),
+                p2.to_string(),
+                p2.to_string(),
+                p1.to_string(),
+                p1.to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                "100".to_string(),
+                "50".to_string(),
+                "1".to_string(),
+            ]]
+        );
+
+        let minutely = write(true);
+        assert_eq!(minutely.lines().next(), Some(header));
+        assert_eq!(minutely.lines().count(), 1 + 3);
+        let minutely_rows = rows(&minutely, WHIRLPOOL);
+        assert_eq!(minutely_rows.len(), 2);
+        assert!(minutely_rows.iter().all(|columns| columns.len() == 13));
+        assert_eq!(minutely_rows[0][1..6], [DAY_1.to_string(), p1.to_string(), p2.to_string(), p1.to_string(), p2.to_string()]);
+        assert_eq!(minutely_rows[0][10..], ["900".to_string(), "1000".to_string(), "1".to_string()]);
+        assert_eq!(minutely_rows[1][1..6], [(DAY_1 + 60).to_string(), p2.to_string(), p3.to_string(), p2.to_string(), p3.to_string()]);
+        assert_eq!(minutely_rows[1][10..], ["400".to_string(), "500".to_string(), "1".to_string()]);
+    }
+}
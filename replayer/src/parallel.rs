@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use crate::errors::ReplayError;
+use crate::{iter_days, parse_date_range};
+
+// replay days from from_yyyymmdd to to_yyyymmdd concurrently (each day is self-contained: the previous day's state + the day's transactions)
+//
+// replay_day is called on the worker threads with yyyymmdd, and it should build its own replayer (ReplayEngine and AccountDataStore) for the day.
+// on_day_result is called on the caller thread in date order (as soon as all previous days have been finished).
+pub fn replay_days_in_parallel<T, R, C>(
+    from_yyyymmdd: &String,
+    to_yyyymmdd: &String,
+    concurrency: usize,
+    replay_day: R,
+    on_day_result: C,
+) -> Result<(), ReplayError>
+where
+    T: Send,
+    R: Fn(&String) -> Result<T, ReplayError> + Sync,
+    C: FnMut(&String, Result<T, ReplayError>),
+{
+    let (from, to) = parse_date_range(from_yyyymmdd, to_yyyymmdd)?;
+
+    let days: Vec<String> = iter_days(from, to)
+        .map(|date| date.format("%Y%m%d").to_string())
+        .collect();
+    replay_in_order(&days, concurrency, replay_day, on_day_result);
+
+    Ok(())
+}
+
+// run replay_day for each day on min(concurrency, days) threads and pass the results to on_day_result in the order of days
+// (a failed day is passed as Err in its place, and the other days go on)
+fn replay_in_order<T, R, C>(days: &Vec<String>, concurrency: usize, replay_day: R, mut on_day_result: C)
+where
+    T: Send,
+    R: Fn(&String) -> Result<T, ReplayError> + Sync,
+    C: FnMut(&String, Result<T, ReplayError>),
+{
+    let workers = concurrency.max(1).min(days.len());
+
+    // workers take the next day in date order
+    let next_day = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel::<(usize, Result<T, ReplayError>)>();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let next_day = &next_day;
+            let replay_day = &replay_day;
+            scope.spawn(move || loop {
+                let index = next_day.fetch_add(1, Ordering::SeqCst);
+                if index >= days.len() {
+                    break;
+                }
+                let result = replay_day(&days[index]);
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // reorder the results finished out of order
+        let mut pending = BTreeMap::<usize, Result<T, ReplayError>>::new();
+        let mut next_result = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next_result) {
+                on_day_result(&days[next_result], result);
+                next_result += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    const DAYS: [&str; 4] = ["20240101", "20240102", "20240103", "20240104"];
+
+    fn days() -> Vec<String> {
+        DAYS.iter().map(|day| day.to_string()).collect()
+    }
+
+    // (day, result) in the order on_day_result is called
+    fn run<R>(concurrency: usize, replay_day: R) -> Vec<(String, Result<String, String>)>
+    where
+        R: Fn(&String) -> Result<String, ReplayError> + Sync,
+    {
+        let mut results = vec![];
+        replay_days_in_parallel(&DAYS[0].to_string(), &DAYS[3].to_string(), concurrency, replay_day, |day, result| {
+            results.push((day.clone(), result.map_err(|e| e.to_string())));
+        })
+        .unwrap();
+        results
+    }
+
+    fn ok_results() -> Vec<(String, Result<String, String>)> {
+        days().into_iter().map(|day| (day.clone(), Ok(day))).collect()
+    }
+
+    #[test]
+    fn test_results_in_date_order() {
+        let finished = Mutex::new(vec![]);
+        // the earlier day takes longer, so the days finish in reverse order
+        let results = run(4, |day| {
+            let index = DAYS.iter().position(|d| d == day).unwrap();
+            std::thread::sleep(Duration::from_millis(100 * (DAYS.len() - index) as u64));
+            finished.lock().unwrap().push(day.clone());
+            Ok(day.clone())
+        });
+
+        assert_eq!(results, ok_results());
+        let mut reversed = days();
+        reversed.reverse();
+        assert_eq!(*finished.lock().unwrap(), reversed);
+    }
+
+    #[test]
+    fn test_failed_day_in_its_place() {
+        let called = Mutex::new(vec![]);
+        let results = run(2, |day| {
+            called.lock().unwrap().push(day.clone());
+            if day == DAYS[1] {
+                return Err(ReplayError::InvalidDate(day.clone()));
+            }
+            Ok(day.clone())
+        });
+
+        let mut expected = ok_results();
+        expected[1].1 = Err(ReplayError::InvalidDate(DAYS[1].to_string()).to_string());
+        assert_eq!(results, expected);
+        // the days after the failed day are replayed, too
+        let mut called = called.lock().unwrap().clone();
+        called.sort();
+        assert_eq!(called, days());
+    }
+
+    #[test]
+    fn test_more_threads_than_days() {
+        let results = run(16, |day| Ok(day.clone()));
+        assert_eq!(results, ok_results());
+    }
+
+    #[test]
+    fn test_single_thread() {
+        for concurrency in [0, 1] {
+            let called = Mutex::new(vec![]);
+            let results = run(concurrency, |day| {
+                called.lock().unwrap().push(day.clone());
+                Ok(day.clone())
+            });
+
+            assert_eq!(results, ok_results(), "concurrency={}", concurrency);
+            // one day after another in date order
+            assert_eq!(*called.lock().unwrap(), days(), "concurrency={}", concurrency);
+        }
+    }
+
+    #[test]
+    fn test_replay_in_order_without_days() {
+        let mut results: Vec<Result<String, ReplayError>> = vec![];
+        replay_in_order(&vec![], 4, |day| Ok(day.clone()), |_day, result| results.push(result));
+        assert!(results.is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+
+use replay_engine::account_data_store::AccountDataStore;
+use replay_engine::types::Slot;
+
+use crate::errors::ReplayError;
+use crate::io;
+use crate::serde::AccountDataStoreConfig;
+use crate::state_diff::{self, StateDiffReport};
+
+/*
+
+Reference snapshot verification
+
+The snapshot CSV files in data/reference_snapshot (whirlpool-snapshot-<slot>.csv.gz) are the whirlpool accounts
+dumped from validator snapshots (Jito and Solana Labs), so they are ground truth independent of the state files of the archive.
+
+1. find the day containing the snapshot slot (the state file of the day D is the state at the end of D)
+2. replay the day with ReplayUntil::Slot(snapshot slot)
+3. compare the replayed accounts with the snapshot (left: snapshot, right: replayed)
+
+The replay stops at the last slot with whirlpool transactions before (or at) the snapshot slot,
+so the replayed slot can be smaller than the snapshot slot.
+
+*/
+
+// the state file of 2022-04-06 (used as the starting point of the day search)
+const ANCHOR_DATE: (i32, u32, u32) = (2022, 4, 6);
+const ANCHOR_SLOT: u64 = 128556169;
+// upper bound of the slots in a day (400ms per slot), so the day search never overshoots
+const MAX_SLOTS_PER_DAY: u64 = 216_000;
+const MAX_DAY_SEARCH_PROBES: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceSnapshotReport {
+    pub snapshot_file: String,
+    pub snapshot_slot: u64,
+    pub date: chrono::NaiveDate,
+    pub replayed_slot: Slot,
+    pub diff: StateDiffReport,
+}
+
+impl ReferenceSnapshotReport {
+    pub fn is_ok(&self) -> bool {
+        self.replayed_slot.slot <= self.snapshot_slot && self.diff.summary.is_identical()
+    }
+}
+
+impl fmt::Display for ReferenceSnapshotReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "reference snapshot verification: {}", if self.is_ok() { "PASS" } else { "FAIL" })?;
+        writeln!(f, "  snapshot: {} (slot={})", self.snapshot_file, self.snapshot_slot)?;
+        writeln!(
+            f,
+            "  replayed: {} until slot={} (block_height={} block_time={})",
+            self.date.format("%Y%m%d"), self.replayed_slot.slot, self.replayed_slot.block_height, self.replayed_slot.block_time,
+        )?;
+        writeln!(f, "  accounts (left: snapshot, right: replayed):")?;
+        for line in self.diff.summary.to_string().lines() {
+            writeln!(f, "    {}", line)?;
+        }
+        for account_diff in self.diff.accounts.iter() {
+            for line in account_diff.to_string().lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// whirlpool-snapshot-<slot>.csv.gz (or .csv.zst)
+pub fn parse_snapshot_slot(file_path: &String) -> Option<u64> {
+    let file_name = std::path::Path::new(file_path).file_name()?.to_str()?;
+    file_name
+        .strip_prefix("whirlpool-snapshot-")?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+// the day D such that (slot of the state file of D - 1) < slot <= (slot of the state file of D)
+// load_state_slot returns the slot of the state file of the day (see io::load_slot_from_local_whirlpool_state_file)
+pub fn find_day_containing_slot<F>(slot: u64, mut load_state_slot: F) -> Result<chrono::NaiveDate, ReplayError>
+where
+    F: FnMut(&chrono::NaiveDate) -> Result<Slot, ReplayError>,
+{
+    let (year, month, day) = ANCHOR_DATE;
+    let anchor_date = chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    let mut date = add_days(&anchor_date, slot.saturating_sub(ANCHOR_SLOT) / MAX_SLOTS_PER_DAY)?;
+
+    for _ in 0..MAX_DAY_SEARCH_PROBES {
+        let state_slot = load_state_slot(&date)?.slot;
+        if state_slot < slot {
+            date = add_days(&date, ((slot - state_slot) / MAX_SLOTS_PER_DAY).max(1))?;
+            continue;
+        }
+
+        let previous_date = sub_days(&date, 1)?;
+        let previous_state_slot = load_state_slot(&previous_date)?.slot;
+        if previous_state_slot < slot {
+            return Ok(date);
+        }
+        date = sub_days(&previous_date, (previous_state_slot - slot) / MAX_SLOTS_PER_DAY)?;
+    }
+
+    Err(ReplayError::InvalidDate(format!("no day containing slot {} found", slot)))
+}
+
+// accounts is the replayed state at the snapshot slot
+pub fn verify_with_reference_snapshot_file(
+    file_path: &String,
+    snapshot_slot: u64,
+    date: &chrono::NaiveDate,
+    replayed_slot: &Slot,
+    accounts: &AccountDataStore,
+    account_data_store_config: &AccountDataStoreConfig,
+) -> Result<ReferenceSnapshotReport, ReplayError> {
+    let snapshot = io::load_from_local_whirlpool_snapshot_csv_file(file_path, account_data_store_config)?;
+    let diff = state_diff::build_state_diff_report(&snapshot, accounts)?;
+    Ok(ReferenceSnapshotReport {
+        snapshot_file: file_path.clone(),
+        snapshot_slot,
+        date: *date,
+        replayed_slot: *replayed_slot,
+        diff,
+    })
+}
+
+// summary line of the report (e.g. for the list of snapshots)
+pub fn format_report_line(report: &ReferenceSnapshotReport) -> String {
+    let total = report.diff.summary.total();
+    format!(
+        "{} slot={} day={} identical={} different={} snapshot only={} replayed only={}",
+        if report.is_ok() { "PASS" } else { "FAIL" },
+        report.snapshot_slot,
+        report.date.format("%Y%m%d"),
+        total.identical,
+        total.different,
+        total.left_only,
+        total.right_only,
+    )
+}
+
+fn add_days(date: &chrono::NaiveDate, days: u64) -> Result<chrono::NaiveDate, ReplayError> {
+    date.checked_add_days(chrono::Days::new(days))
+        .ok_or_else(|| ReplayError::InvalidDate(format!("{} + {} days", date, days)))
+}
+
+fn sub_days(date: &chrono::NaiveDate, days: u64) -> Result<chrono::NaiveDate, ReplayError> {
+    date.checked_sub_days(chrono::Days::new(days))
+        .ok_or_else(|| ReplayError::InvalidDate(format!("{} - {} days", date, days)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // slots of the state files from 20220406 to 20220408 (only the slot is used by the day search)
+    fn load_sample_state_slot(date: &chrono::NaiveDate) -> Result<Slot, ReplayError> {
+        match date.format("%Y%m%d").to_string().as_str() {
+            "20220406" => Ok(Slot::new(128556169, 0, 0)),
+            "20220407" => Ok(Slot::new(128703662, 0, 0)),
+            "20220408" => Ok(Slot::new(128863644, 0, 0)),
+            _ => Err(ReplayError::InvalidDate(format!("no state file: {}", date))),
+        }
+    }
+
+    fn find_day(slot: u64) -> Result<String, ReplayError> {
+        find_day_containing_slot(slot, load_sample_state_slot).map(|date| date.format("%Y%m%d").to_string())
+    }
+
+    #[test]
+    fn test_find_day_containing_slot() {
+        // the first slot after the state of 20220406
+        assert_eq!(find_day(128556170).unwrap(), "20220407");
+        assert_eq!(find_day(128600000).unwrap(), "20220407");
+        // the state file of the day is the state at the end of the day
+        assert_eq!(find_day(128703662).unwrap(), "20220407");
+        assert_eq!(find_day(128703663).unwrap(), "20220408");
+        assert_eq!(find_day(128863644).unwrap(), "20220408");
+    }
+
+    #[test]
+    fn test_find_day_containing_slot_without_state_file() {
+        // 20220409 and 20220405 are not available
+        assert!(find_day(128863645).is_err());
+        assert!(find_day(128556169).is_err());
+    }
+
+    #[test]
+    fn test_parse_snapshot_slot() {
+        assert_eq!(parse_snapshot_slot(&"data/reference_snapshot/whirlpool-snapshot-215135999.csv.gz".to_string()), Some(215135999));
+        assert_eq!(parse_snapshot_slot(&"whirlpool-snapshot-215135999.csv.zst".to_string()), Some(215135999));
+        assert_eq!(parse_snapshot_slot(&"whirlpool-state-20220406.json.gz".to_string()), None);
+        assert_eq!(parse_snapshot_slot(&"whirlpool-snapshot-latest.csv.gz".to_string()), None);
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::BTreeSet;
+
+use replay_engine::account_data_store::AccountDataStore;
+use replay_engine::decoded_instructions::{self, DecodedInstruction};
+use replay_engine::errors::ErrorCode;
+use replay_engine::replay_engine::ReplayEngine;
+use replay_engine::types::{ProgramData, Slot};
+use serde_json::Value;
+
+use crate::errors::ReplayError;
+use crate::io;
+use crate::schema::{Transaction, WhirlpoolReproBundle, WhirlpoolStateAccount};
+use crate::{ExecutionRecord, ReplayInstructionResult};
+
+/*
+
+Single instruction replay and repro bundle
+
+replay_single_instruction executes one instruction on a fresh ReplayEngine built from the given state
+(a full state or a subset of its accounts) with the execution recording enabled.
+
+A repro bundle is a self-contained snapshot of a failed instruction (see schema.rs for the file format).
+The instruction in the bundle is re-executed by replay_single_instruction, so the program logs are available
+even if the original replay ran without them.
+
+*/
+
+pub struct InstructionOutcome {
+    pub result: Result<ReplayInstructionResult, ErrorCode>,
+    // the record of the execution (available even if it failed)
+    pub execution_record: Option<ExecutionRecord>,
+}
+
+// the instruction is given in the same format as the transaction file (name and JSON payload)
+pub fn replay_single_instruction(
+    slot: Slot,
+    program_data: ProgramData,
+    accounts: AccountDataStore,
+    instruction_name: &String,
+    instruction_payload: &String,
+) -> Result<InstructionOutcome, ReplayError> {
+    let decoded = decoded_instructions::from_json(instruction_name, instruction_payload)
+        .map_err(|e| ReplayError::instruction(slot.slot, &String::new(), 0, instruction_name, e))?;
+    let instruction = match decoded {
+        DecodedInstruction::WhirlpoolInstruction(instruction) => instruction,
+        DecodedInstruction::ProgramDeployInstruction(_) => {
+            return Err(ReplayError::instruction(
+                slot.slot,
+                &String::new(),
+                0,
+                instruction_name,
+                ErrorCode::UnknownWhirlpoolInstruction(format!("not a whirlpool instruction: {}", instruction_name)),
+            ));
+        }
+    };
+
+    let mut replay_engine = ReplayEngine::new(slot, program_data, accounts);
+    replay_engine.set_execution_recording(true);
+
+    let result = replay_engine.replay_instruction(&instruction);
+    Ok(InstructionOutcome {
+        result,
+        execution_record: replay_engine.get_last_execution_record().cloned(),
+    })
+}
+
+// accounts is the state before the instruction (the failed instruction is not written back)
+pub fn build_repro_bundle(
+    slot: &Slot,
+    transaction: &Transaction,
+    instruction_index: usize,
+    error: &ErrorCode,
+    accounts: &AccountDataStore,
+    program_data: &ProgramData,
+    execution_record: Option<&ExecutionRecord>,
+) -> Result<WhirlpoolReproBundle, ReplayError> {
+    let instruction = &transaction.instructions[instruction_index];
+
+    // every pubkey in the payload is looked up (non-pubkey strings are simply not found)
+    let mut pubkeys = BTreeSet::new();
+    collect_strings(&instruction.payload, &mut pubkeys);
+    let mut bundle_accounts = vec![];
+    for pubkey in pubkeys {
+        let data = accounts.get(&pubkey).map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+        if let Some(data) = data {
+            bundle_accounts.push(WhirlpoolStateAccount { pubkey, data });
+        }
+    }
+
+    Ok(WhirlpoolReproBundle {
+        slot: slot.slot,
+        block_height: slot.block_height,
+        block_time: slot.block_time,
+        signature: transaction.signature.clone(),
+        instruction_index,
+        instruction: instruction.clone(),
+        error: error.to_string(),
+        log_messages: execution_record.map(|execution_record| execution_record.log_messages.clone()),
+        accounts_hash: accounts_hash(&bundle_accounts),
+        accounts: bundle_accounts,
+        program_data_hash: program_data_hash(program_data),
+        program_data: program_data.clone(),
+    })
+}
+
+pub fn get_repro_bundle_file_name(bundle: &WhirlpoolReproBundle) -> String {
+    format!("repro-{}-{}-{}.json.gz", bundle.slot, bundle.signature, bundle.instruction_index)
+}
+
+// write the bundle into the directory and return the file path
+pub fn save_repro_bundle(dir_path: &String, bundle: &WhirlpoolReproBundle) -> Result<String, ReplayError> {
+    let file_path = format!("{}/{}", dir_path, get_repro_bundle_file_name(bundle));
+    io::save_to_whirlpool_repro_bundle_file(&file_path, bundle)?;
+    Ok(file_path)
+}
+
+// re-execute the instruction in the bundle with the execution recording enabled
+pub fn replay_repro_bundle(bundle: &WhirlpoolReproBundle) -> Result<InstructionOutcome, ReplayError> {
+    if program_data_hash(&bundle.program_data) != bundle.program_data_hash {
+        return Err(ReplayError::ReproBundle(format!("program data hash mismatch (expected {})", bundle.program_data_hash)));
+    }
+    if accounts_hash(&bundle.accounts) != bundle.accounts_hash {
+        return Err(ReplayError::ReproBundle(format!("accounts hash mismatch (expected {})", bundle.accounts_hash)));
+    }
+
+    let mut accounts = AccountDataStore::new_on_memory();
+    for account in bundle.accounts.iter() {
+        accounts.upsert(&account.pubkey, &account.data)
+            .map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+    }
+
+    replay_single_instruction(
+        Slot::new(bundle.slot, bundle.block_height, bundle.block_time),
+        bundle.program_data.clone(),
+        accounts,
+        &bundle.instruction.name,
+        &bundle.instruction.payload.to_string(),
+    )
+    .map_err(|e| match e {
+        // the position in the transaction is known for the bundle
+        ReplayError::Instruction { instruction_name, source, .. } => {
+            ReplayError::instruction(bundle.slot, &bundle.signature, bundle.instruction_index, &instruction_name, source)
+        }
+        e => e,
+    })
+}
+
+// sha256 of the program data (base58 encoding)
+pub fn program_data_hash(program_data: &ProgramData) -> String {
+    anchor_lang::solana_program::hash::hash(program_data).to_string()
+}
+
+// sha256 of pubkey, data length and data of the accounts in order (base58 encoding)
+pub fn accounts_hash(accounts: &[WhirlpoolStateAccount]) -> String {
+    let mut hasher = anchor_lang::solana_program::hash::Hasher::default();
+    for account in accounts.iter() {
+        hasher.hash(account.pubkey.as_bytes());
+        hasher.hash(&(account.data.len() as u64).to_le_bytes());
+        hasher.hash(&account.data);
+    }
+    hasher.result().to_string()
+}
+
+fn collect_strings(value: &Value, strings: &mut BTreeSet<String>) {
+    match value {
+        Value::String(s) => {
+            strings.insert(s.clone());
+        }
+        Value::Array(values) => values.iter().for_each(|value| collect_strings(value, strings)),
+        Value::Object(map) => map.values().for_each(|value| collect_strings(value, strings)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::TransactionInstruction;
+    use crate::test_utils::{INITIALIZE_CONFIG, INITIALIZE_FEE_TIER, INITIALIZE_POOL_V2, WHIRLPOOL_PROGRAM_FOR_TESTING};
+
+    const CONFIG: &str = "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D";
+    const FEE_TIER: &str = "C7HVbbKnAnuXfhZ87mefqWPXDo2cjrj35yYmq3HhV1D6";
+    const WHIRLPOOL: &str = "BsGwEuUqbfeUSDN4mmxhcGFhNYKypKH8NZjoQ7DQrFfC";
+
+    // the state after initializeConfig and initializeFeeTier (initializePoolV2 can be replayed on it)
+    fn replay_engine_with_fee_tier() -> ReplayEngine {
+        let mut replay_engine = ReplayEngine::new(
+            Slot::new(10, 10, 1700000010),
+            WHIRLPOOL_PROGRAM_FOR_TESTING.to_vec(),
+            AccountDataStore::new_on_memory(),
+        );
+        for (name, payload) in [("initializeConfig", INITIALIZE_CONFIG), ("initializeFeeTier", INITIALIZE_FEE_TIER)] {
+            match decoded_instructions::from_json(&name.to_string(), &payload.to_string()).unwrap() {
+                DecodedInstruction::WhirlpoolInstruction(instruction) => {
+                    replay_engine.replay_instruction(&instruction).unwrap();
+                }
+                DecodedInstruction::ProgramDeployInstruction(_) => unreachable!(),
+            }
+        }
+        replay_engine
+    }
+
+    fn initialize_pool_transaction() -> Transaction {
+        Transaction {
+            index: 1,
+            signature: "signature-1".to_string(),
+            payer: "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6".to_string(),
+            balances: vec![],
+            instructions: vec![TransactionInstruction {
+                name: "initializePoolV2".to_string(),
+                payload: serde_json::from_str(INITIALIZE_POOL_V2).unwrap(),
+            }],
+        }
+    }
+
+    fn build_bundle() -> WhirlpoolReproBundle {
+        let replay_engine = replay_engine_with_fee_tier();
+        build_repro_bundle(
+            replay_engine.get_slot(),
+            &initialize_pool_transaction(),
+            0,
+            &ErrorCode::AccountNotFound(WHIRLPOOL.to_string()),
+            replay_engine.get_accounts(),
+            replay_engine.get_program_data(),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_repro_bundle_collects_accounts_in_state() {
+        let bundle = build_bundle();
+
+        // the payload has many pubkeys, but only the config and the fee tier exist in the state (sorted by pubkey)
+        let pubkeys: Vec<String> = bundle.accounts.iter().map(|account| account.pubkey.clone()).collect();
+        let mut expected = vec![CONFIG.to_string(), FEE_TIER.to_string()];
+        expected.sort();
+        assert_eq!(pubkeys, expected);
+
+        let replay_engine = replay_engine_with_fee_tier();
+        for account in bundle.accounts.iter() {
+            assert_eq!(Some(account.data.clone()), replay_engine.get_accounts().get(&account.pubkey).unwrap());
+        }
+        assert_eq!(bundle.slot, 10);
+        assert_eq!(bundle.instruction_index, 0);
+        assert_eq!(bundle.log_messages, None);
+    }
+
+    #[test]
+    fn test_repro_bundle_roundtrip() {
+        let bundle = build_bundle();
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap().to_string();
+
+        let file_path = save_repro_bundle(&dir_path, &bundle).unwrap();
+        assert_eq!(file_path, format!("{}/repro-10-signature-1-0.json.gz", dir_path));
+        let loaded = io::load_from_local_whirlpool_repro_bundle_file(&file_path).unwrap();
+        assert_eq!(loaded, bundle);
+
+        // the instruction succeeds on the accounts in the bundle (the whirlpool is created)
+        let outcome = replay_repro_bundle(&loaded).unwrap();
+        let result = outcome.result.unwrap();
+        assert!(result.snapshot.pre_snapshot.get(WHIRLPOOL).map_or(true, |data| data.is_empty()));
+        assert!(!result.snapshot.post_snapshot[WHIRLPOOL].is_empty());
+        // the execution is always recorded
+        assert!(!outcome.execution_record.unwrap().log_messages.is_empty());
+    }
+
+    #[test]
+    fn test_replay_tampered_repro_bundle() {
+        let mut tampered_program = build_bundle();
+        tampered_program.program_data[0] ^= 1;
+        assert!(matches!(replay_repro_bundle(&tampered_program), Err(ReplayError::ReproBundle(_))));
+
+        let mut tampered_program_hash = build_bundle();
+        tampered_program_hash.program_data_hash = program_data_hash(&vec![0u8]);
+        assert!(matches!(replay_repro_bundle(&tampered_program_hash), Err(ReplayError::ReproBundle(_))));
+
+        let mut tampered_account = build_bundle();
+        tampered_account.accounts[0].data[8] ^= 1;
+        assert!(matches!(replay_repro_bundle(&tampered_account), Err(ReplayError::ReproBundle(_))));
+
+        let mut removed_account = build_bundle();
+        removed_account.accounts.pop();
+        assert!(matches!(replay_repro_bundle(&removed_account), Err(ReplayError::ReproBundle(_))));
+    }
+
+    #[test]
+    fn test_replay_single_instruction_rejects_program_deploy() {
+        let result = replay_single_instruction(
+            Slot::new(10, 10, 1700000010),
+            WHIRLPOOL_PROGRAM_FOR_TESTING.to_vec(),
+            AccountDataStore::new_on_memory(),
+            &"programDeploy".to_string(),
+            &r#"{"programData": "AQID"}"#.to_string(),
+        );
+        match result {
+            Err(ReplayError::Instruction { instruction_name, source: ErrorCode::UnknownWhirlpoolInstruction(_), .. }) => {
+                assert_eq!(instruction_name, "programDeploy");
+            }
+            _ => panic!("programDeploy must be rejected"),
+        }
+    }
+}
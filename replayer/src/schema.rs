@@ -1,7 +1,7 @@
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
 use replay_engine::account_data_store::AccountDataStore;
-use replay_engine::decoded_instructions::{deserialize_u64, deserialize_base64, serialize_base64};
+use replay_engine::decoded_instructions::{deserialize_u64, deserialize_u128, deserialize_base64, deserialize_option_base64, serialize_base64, serialize_option_base64, serialize_u128};
 
 pub use replay_engine::decoded_instructions::{DecodedInstruction, DecodedProgramDeployInstruction, DecodedWhirlpoolInstruction};
 
@@ -9,7 +9,7 @@ pub use replay_engine::decoded_instructions::{DecodedInstruction, DecodedProgram
 
 Whirlpool State File JSON Schema
 
-A whirlpool state file (whirlpool-state-yyyymmdd.json.gz) is GZIP compressed JSON file with the following schema:
+A whirlpool state file (whirlpool-state-yyyymmdd.json.gz or .json.zst) is GZIP (or Zstandard) compressed JSON file with the following schema:
  
 {
   slot: u64,
@@ -23,6 +23,9 @@ A whirlpool state file (whirlpool-state-yyyymmdd.json.gz) is GZIP compressed JSO
   programData: String(base64 encoding)
 }
 
+The order of accounts is not defined, except for canonical state files (io::save_to_whirlpool_state_file_canonical)
+that have accounts sorted by pubkey and no whitespace, so the same state is always saved into the same bytes.
+
 */
 
 pub struct WhirlpoolState {
@@ -33,7 +36,7 @@ pub struct WhirlpoolState {
   pub program_data: Vec<u8>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct WhirlpoolStateAccount {
   pub pubkey: String,
@@ -43,9 +46,75 @@ pub struct WhirlpoolStateAccount {
 
 /*
 
+Whirlpool Snapshot CSV File Format
+
+A whirlpool snapshot CSV file (whirlpool-snapshot-<slot>.csv.gz or .csv.zst) is GZIP (or Zstandard) compressed CSV file without header.
+It is a dump of the whirlpool program accounts taken from a validator snapshot (see data/reference_snapshot).
+Each line is an account (lines are not sorted by pubkey):
+
+pubkey(base58 encoding),data(base64 encoding)
+
+A full-columns file has additional columns between pubkey and data (e.g. owner, data length, lamports, write version).
+The data is always the last column.
+
+*/
+
+/*
+
+Whirlpool Checkpoint File JSON Schema
+
+A whirlpool checkpoint file is a whirlpool state file taken in the middle of the replay.
+It has the position in the transaction stream in addition to the state, so the replay can be resumed from it.
+The compression is determined by the extension (.zst is Zstandard, others are GZIP).
+
+{
+  slot: u64,
+  blockHeight: u64,
+  blockTime: i64,
+  position: {
+    slot: u64,
+    nextInstruction: { transactionIndex: usize, instructionIndex: usize } | null
+  },
+  accounts: [
+    { pubkey: String(base58 encoding), data: String(base64 encoding) },
+    ...
+  ],
+  programData: String(base64 encoding)
+}
+
+position.slot is the last slot started (equal to slot).
+nextInstruction is null if all instructions in the slot have been replayed.
+
+*/
+
+pub struct WhirlpoolCheckpoint {
+  pub slot: u64,
+  pub block_height: u64,
+  pub block_time: i64,
+  pub position: ReplayPosition,
+  pub accounts: AccountDataStore,
+  pub program_data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayPosition {
+  pub slot: u64,
+  pub next_instruction: Option<InstructionPosition>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionPosition {
+  pub transaction_index: usize,
+  pub instruction_index: usize,
+}
+
+/*
+
 Whirlpool Transaction File JSON Lines Format
 
-A whirlpool transaction file (whirlpool-transaction-yyyymmdd.json.gz) is GZIP compressed text file.
+A whirlpool transaction file (whirlpool-transaction-yyyymmdd.jsonl.gz or .jsonl.zst) is GZIP (or Zstandard) compressed text file.
 Each line is a JSON object with the following schema:
 
 {
@@ -114,7 +183,7 @@ pub struct TransactionInstruction {
 
 Whirlpool Token File JSON Schema
 
-A whirlpool token file (whirlpool-token-yyyymmdd.json.gz) is GZIP compressed JSON file with the following schema:
+A whirlpool token file (whirlpool-token-yyyymmdd.json.gz or .json.zst) is GZIP (or Zstandard) compressed JSON file with the following schema:
  
 {
   slot: u64,
@@ -144,3 +213,236 @@ pub struct TokenInfo {
   pub mint: String,
   pub decimals: u8,
 }
+
+/*
+
+Whirlpool Repro Bundle JSON Schema
+
+A whirlpool repro bundle (repro-<slot>-<signature>-<instructionIndex>.json.gz or .json.zst) is GZIP (or Zstandard) compressed JSON file
+written when a whirlpool instruction fails to be replayed.
+It contains everything needed to re-execute the instruction on a fresh replay engine (no state file is needed).
+
+{
+  slot: u64,
+  blockHeight: u64,
+  blockTime: i64,
+  signature: String(base58 encoding),
+  instructionIndex: usize,
+  instruction: { name: String, payload: Value(same as transaction file) },
+  error: String,
+  logMessages: [String] | null,
+  accounts: [
+    { pubkey: String(base58 encoding), data: String(base64 encoding) },
+    ...
+  ],
+  accountsHash: String(sha256, base58 encoding),
+  programDataHash: String(sha256, base58 encoding),
+  programData: String(base64 encoding)
+}
+
+accounts are the pre-state of the accounts referenced by the instruction that exist in the state (sorted by pubkey).
+accountsHash is the hash of pubkey, data length and data of the accounts in order (a tampered bundle is rejected on replay).
+The replay handler loads whirlpool program owned accounts only from them, and other accounts (token accounts, mints) are
+built from the instruction itself.
+logMessages is null if the execution recording was disabled.
+
+*/
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WhirlpoolReproBundle {
+  pub slot: u64,
+  pub block_height: u64,
+  pub block_time: i64,
+  pub signature: String,
+  pub instruction_index: usize,
+  pub instruction: TransactionInstruction,
+  pub error: String,
+  pub log_messages: Option<Vec<String>>,
+  pub accounts: Vec<WhirlpoolStateAccount>,
+  pub accounts_hash: String,
+  pub program_data_hash: String,
+  #[serde(deserialize_with = "deserialize_base64", serialize_with = "serialize_base64")]
+  pub program_data: Vec<u8>,
+}
+
+/*
+
+Whirlpool Event File JSON Lines Format
+
+A whirlpool event file (whirlpool-event-yyyymmdd.jsonl.gz or .jsonl.zst) is GZIP (or Zstandard) compressed text file.
+It is a whirlpool transaction file converted by the replay: each replayed instruction has the old (pre) and new (post) state
+of the writable accounts mentioned in the instruction.
+Each line is a JSON object with the following schema (only slots with at least one replayed instruction are included):
+
+{
+  slot: u64,
+  blockHeight: u64,
+  blockTime: i64,
+  transactions: [
+    {
+      index: u32,
+      signature: String(base58 encoding),
+      payer: String(base58 encoding),
+      events: [
+        {
+          name: String,
+          payload: Value(decoded instruction, numbers are JSON numbers),
+          programEvents: [String(Anchor event name, e.g. Traded), ...] (empty without the execution recording),
+          accounts: [
+            { pubkey: String(base58 encoding), pre: String(base64 encoding) | null, post: String(base64 encoding) | null },
+            ...
+          ]
+        },
+        ...
+      ]
+    },
+    ...
+  ]
+}
+
+pre is null if the account is created by the instruction, and post is null if the account is closed by the instruction.
+accounts are sorted by pubkey.
+
+*/
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WhirlpoolEvent {
+  pub slot: u64,
+  pub block_height: u64,
+  pub block_time: i64,
+  pub transactions: Vec<EventTransaction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EventTransaction {
+  pub index: u32,
+  pub signature: String,
+  pub payer: String,
+  pub events: Vec<InstructionEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InstructionEvent {
+  pub name: String,
+  pub payload: Value,
+  #[serde(default)]
+  pub program_events: Vec<String>,
+  pub accounts: Vec<EventAccount>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EventAccount {
+  pub pubkey: String,
+  #[serde(deserialize_with = "deserialize_option_base64", serialize_with = "serialize_option_base64")]
+  pub pre: Option<Vec<u8>>,
+  #[serde(deserialize_with = "deserialize_option_base64", serialize_with = "serialize_option_base64")]
+  pub post: Option<Vec<u8>>,
+}
+
+/*
+
+Whirlpool OHLCV File JSON Schema
+
+A whirlpool OHLCV file is GZIP (or Zstandard) compressed JSON file with the following schema:
+- daily: whirlpool-ohlcv-daily-yyyymmdd.json.gz (minutely is empty)
+- minutely: whirlpool-ohlcv-minutely-yyyymmdd.json.gz (daily + minutely)
+
+Only whirlpools with at least one swap in the day are included.
+timestamp is the beginning of the period (unix time, UTC).
+decimalPrice is null if the decimals of the tokens are unknown.
+
+{
+  date: String(yyyymmdd),
+  ohlcv: [
+    {
+      whirlpool: String(base58 encoding),
+      tokenMintA: String(base58 encoding),
+      tokenMintB: String(base58 encoding),
+      decimalsA: u8 | null,
+      decimalsB: u8 | null,
+      daily: Candle,
+      minutely: [Candle, Candle, ...]
+    },
+    ...
+  ]
+}
+
+Candle:
+{
+  timestamp: i64,
+  sqrtPrice: { open: String(u128 as string), high: String(u128 as string), low: String(u128 as string), close: String(u128 as string) },
+  decimalPrice: { open: f64, high: f64, low: f64, close: f64 } | null,
+  volume: { a: String(u128 as string), b: String(u128 as string) },
+  swaps: u64
+}
+
+A whirlpool OHLCV CSV file (whirlpool-ohlcv-daily-yyyymmdd.csv.gz, whirlpool-ohlcv-minutely-yyyymmdd.csv.gz) has one candle per line:
+
+whirlpool,timestamp,sqrt_price_open,sqrt_price_high,sqrt_price_low,sqrt_price_close,decimal_price_open,decimal_price_high,decimal_price_low,decimal_price_close,volume_a,volume_b,swaps
+
+*/
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WhirlpoolOhlcv {
+  pub date: String,
+  pub ohlcv: Vec<WhirlpoolOhlcvData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WhirlpoolOhlcvData {
+  pub whirlpool: String,
+  pub token_mint_a: String,
+  pub token_mint_b: String,
+  pub decimals_a: Option<u8>,
+  pub decimals_b: Option<u8>,
+  pub daily: Candle,
+  pub minutely: Vec<Candle>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Candle {
+  pub timestamp: i64,
+  pub sqrt_price: SqrtPriceOhlc,
+  pub decimal_price: Option<DecimalPriceOhlc>,
+  pub volume: Volume,
+  pub swaps: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct SqrtPriceOhlc {
+  #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+  pub open: u128,
+  #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+  pub high: u128,
+  #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+  pub low: u128,
+  #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+  pub close: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct DecimalPriceOhlc {
+  pub open: f64,
+  pub high: f64,
+  pub low: f64,
+  pub close: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Volume {
+  #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+  pub a: u128,
+  #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+  pub b: u128,
+}
@@ -1,8 +1,10 @@
+use crate::errors::ReplayError;
+use crate::filter::WhirlpoolFilter;
 use crate::schema::*;
 use replay_engine::account_data_store::AccountDataStore;
 use serde::{
     de::{self, DeserializeSeed, MapAccess, Visitor},
-    ser::SerializeSeq, Deserializer,
+    ser::{self, SerializeSeq}, Deserializer,
 };
 use std::fmt;
 use serde_derive::Serialize;
@@ -12,10 +14,30 @@ use base64::prelude::{Engine as _, BASE64_STANDARD};
 pub fn deserialize_whirlpool_state_from_reader(
     reader: impl std::io::Read,
     config: AccountDataStoreConfig,
-) -> WhirlpoolState {
+) -> Result<WhirlpoolState, ReplayError> {
     let deserializer = WhirlpoolStateDeserializer::new(config);
     let de = &mut serde_json::Deserializer::from_reader(reader);
-    deserializer.deserialize(de).unwrap()
+    Ok(deserializer.deserialize(de)?)
+}
+
+// only the accounts needed by the filter are stored
+pub fn deserialize_whirlpool_state_from_reader_with_filter(
+    reader: impl std::io::Read,
+    config: AccountDataStoreConfig,
+    filter: WhirlpoolFilter,
+) -> Result<WhirlpoolState, ReplayError> {
+    let deserializer = WhirlpoolStateDeserializer::new_with_filter(config, filter);
+    let de = &mut serde_json::Deserializer::from_reader(reader);
+    Ok(deserializer.deserialize(de)?)
+}
+
+pub fn deserialize_whirlpool_checkpoint_from_reader(
+    reader: impl std::io::Read,
+    config: AccountDataStoreConfig,
+) -> Result<WhirlpoolCheckpoint, ReplayError> {
+    let deserializer = WhirlpoolCheckpointDeserializer::new(config);
+    let de = &mut serde_json::Deserializer::from_reader(reader);
+    Ok(deserializer.deserialize(de)?)
 }
 
 #[derive(Clone)]
@@ -26,18 +48,41 @@ pub enum AccountDataStoreConfig {
 
 pub struct WhirlpoolStateDeserializer{
     config: AccountDataStoreConfig,
+    filter: Option<WhirlpoolFilter>,
+}
+
+pub struct WhirlpoolCheckpointDeserializer {
+    config: AccountDataStoreConfig,
+}
+
+impl WhirlpoolCheckpointDeserializer {
+    pub fn new(config: AccountDataStoreConfig) -> Self {
+        Self { config }
+    }
 }
 
 pub struct AccountsDeserializeConfig {
     config: AccountDataStoreConfig,
+    filter: Option<WhirlpoolFilter>,
 }
 
 impl WhirlpoolStateDeserializer {
     pub fn new(config: AccountDataStoreConfig) -> Self {
-        Self { config }
+        Self { config, filter: None }
+    }
+
+    pub fn new_with_filter(config: AccountDataStoreConfig, filter: WhirlpoolFilter) -> Self {
+        Self { config, filter: Some(filter) }
     }
 }
 
+const FIELD_SLOT: &'static str = "slot";
+const FIELD_BLOCK_HEIGHT: &'static str = "blockHeight";
+const FIELD_BLOCK_TIME: &'static str = "blockTime";
+const FIELD_POSITION: &'static str = "position";
+const FIELD_PROGRAM_DATA: &'static str = "programData";
+const FIELD_ACCOUNTS: &'static str = "accounts";
+
 impl<'de> DeserializeSeed<'de> for WhirlpoolStateDeserializer {
     type Value = WhirlpoolState;
 
@@ -45,96 +90,131 @@ impl<'de> DeserializeSeed<'de> for WhirlpoolStateDeserializer {
     where
         D: Deserializer<'de>,
     {
-        const FIELD_SLOT: &'static str = "slot";
-        const FIELD_BLOCK_HEIGHT: &'static str = "blockHeight";
-        const FIELD_BLOCK_TIME: &'static str = "blockTime";
-        const FIELD_PROGRAM_DATA: &'static str = "programData";
-        const FIELD_ACCOUNTS: &'static str = "accounts";
-
-        struct LocalVisitor {
-            config: AccountDataStoreConfig,
-        }
-
-        impl<'de> Visitor<'de> for LocalVisitor {
-            type Value = WhirlpoolState;
-    
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("struct WhirlpoolState")
-            }
+        const FIELDS: &'static [&'static str] = &[
+            FIELD_SLOT,
+            FIELD_BLOCK_HEIGHT,
+            FIELD_BLOCK_TIME,
+            FIELD_PROGRAM_DATA,
+            FIELD_ACCOUNTS,
+        ];
+        let (state, _position) = deserializer.deserialize_struct("WhirlpoolState", FIELDS, StateVisitor { config: self.config.clone(), filter: self.filter.clone() })?;
+        Ok(state)
+    }
+}
 
-            fn visit_map<V>(self, mut map: V) -> Result<WhirlpoolState, V::Error>
-            where
-                V: MapAccess<'de>,
-            {
-                let mut slot: Option<u64> = None;
-                let mut block_height: Option<u64> = None;
-                let mut block_time: Option<i64> = None;
-                let mut program_data: Option<Vec<u8>> = None;
-                let mut accounts: Option<AccountDataStore> = None;
-                while let Some(key) = map.next_key::<String>()? {
-                    match key.as_str() {
-                        FIELD_SLOT => {
-                            if slot.is_some() {
-                                return Err(de::Error::duplicate_field(FIELD_SLOT));
-                            }
-                            slot = Some(map.next_value()?);
-                        }
-                        FIELD_BLOCK_HEIGHT => {
-                            if block_height.is_some() {
-                                return Err(de::Error::duplicate_field(FIELD_BLOCK_HEIGHT));
-                            }
-                            block_height = Some(map.next_value()?);
-                        }
-                        FIELD_BLOCK_TIME => {
-                            if block_time.is_some() {
-                                return Err(de::Error::duplicate_field(FIELD_BLOCK_TIME));
-                            }
-                            block_time = Some(map.next_value()?);
-                        }
-                        FIELD_PROGRAM_DATA => {
-                            if program_data.is_some() {
-                                return Err(de::Error::duplicate_field(FIELD_PROGRAM_DATA));
-                            }
-                            let program_data_base64: String = map.next_value()?;
-                            program_data = Some(match BASE64_STANDARD.decode(program_data_base64).ok() {
-                                Some(data) => Ok(data),
-                                None => Err(de::Error::custom("expected base64 string")),
-                            }?);
-                        }
-                        FIELD_ACCOUNTS => {
-                            if accounts.is_some() {
-                                return Err(de::Error::duplicate_field(FIELD_ACCOUNTS));
-                            }
-                            accounts = Some(map.next_value_seed(AccountsDeserializeConfig { config: self.config.clone() })?);                        
-                        }
-                        _ => {
-                            map.next_value::<serde::de::IgnoredAny>()?;
-                        }
-                    }
-                }
-                let slot = slot.ok_or_else(|| de::Error::missing_field(FIELD_SLOT))?;
-                let block_height = block_height.ok_or_else(|| de::Error::missing_field(FIELD_BLOCK_HEIGHT))?;
-                let block_time = block_time.ok_or_else(|| de::Error::missing_field(FIELD_BLOCK_TIME))?;
-                let program_data = program_data.ok_or_else(|| de::Error::missing_field(FIELD_PROGRAM_DATA))?;
-                let accounts = accounts.ok_or_else(|| de::Error::missing_field(FIELD_ACCOUNTS))?;
-                Ok(WhirlpoolState {
-                    slot,
-                    block_height,
-                    block_time,
-                    program_data,
-                    accounts,
-                })
-            }
-        }
+impl<'de> DeserializeSeed<'de> for WhirlpoolCheckpointDeserializer {
+    type Value = WhirlpoolCheckpoint;
 
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
         const FIELDS: &'static [&'static str] = &[
             FIELD_SLOT,
             FIELD_BLOCK_HEIGHT,
             FIELD_BLOCK_TIME,
+            FIELD_POSITION,
             FIELD_PROGRAM_DATA,
             FIELD_ACCOUNTS,
         ];
-        deserializer.deserialize_struct("WhirlpoolState", FIELDS, LocalVisitor { config: self.config.clone() })
+        let (state, position) = deserializer.deserialize_struct("WhirlpoolCheckpoint", FIELDS, StateVisitor { config: self.config.clone(), filter: None })?;
+        let position = position.ok_or_else(|| de::Error::missing_field(FIELD_POSITION))?;
+        Ok(WhirlpoolCheckpoint {
+            slot: state.slot,
+            block_height: state.block_height,
+            block_time: state.block_time,
+            position,
+            accounts: state.accounts,
+            program_data: state.program_data,
+        })
+    }
+}
+
+// state file and checkpoint file share the same visitor (position is only in checkpoint file)
+struct StateVisitor {
+    config: AccountDataStoreConfig,
+    filter: Option<WhirlpoolFilter>,
+}
+
+impl<'de> Visitor<'de> for StateVisitor {
+    type Value = (WhirlpoolState, Option<ReplayPosition>);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("struct WhirlpoolState")
+    }
+
+    fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
+    where
+        V: MapAccess<'de>,
+    {
+        let mut slot: Option<u64> = None;
+        let mut block_height: Option<u64> = None;
+        let mut block_time: Option<i64> = None;
+        let mut position: Option<ReplayPosition> = None;
+        let mut program_data: Option<Vec<u8>> = None;
+        let mut accounts: Option<AccountDataStore> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                FIELD_SLOT => {
+                    if slot.is_some() {
+                        return Err(de::Error::duplicate_field(FIELD_SLOT));
+                    }
+                    slot = Some(map.next_value()?);
+                }
+                FIELD_BLOCK_HEIGHT => {
+                    if block_height.is_some() {
+                        return Err(de::Error::duplicate_field(FIELD_BLOCK_HEIGHT));
+                    }
+                    block_height = Some(map.next_value()?);
+                }
+                FIELD_BLOCK_TIME => {
+                    if block_time.is_some() {
+                        return Err(de::Error::duplicate_field(FIELD_BLOCK_TIME));
+                    }
+                    block_time = Some(map.next_value()?);
+                }
+                FIELD_POSITION => {
+                    if position.is_some() {
+                        return Err(de::Error::duplicate_field(FIELD_POSITION));
+                    }
+                    position = Some(map.next_value()?);
+                }
+                FIELD_PROGRAM_DATA => {
+                    if program_data.is_some() {
+                        return Err(de::Error::duplicate_field(FIELD_PROGRAM_DATA));
+                    }
+                    let program_data_base64: String = map.next_value()?;
+                    program_data = Some(match BASE64_STANDARD.decode(program_data_base64).ok() {
+                        Some(data) => Ok(data),
+                        None => Err(de::Error::custom("expected base64 string")),
+                    }?);
+                }
+                FIELD_ACCOUNTS => {
+                    if accounts.is_some() {
+                        return Err(de::Error::duplicate_field(FIELD_ACCOUNTS));
+                    }
+                    accounts = Some(map.next_value_seed(AccountsDeserializeConfig { config: self.config.clone(), filter: self.filter.clone() })?);
+                }
+                _ => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
+        }
+        let slot = slot.ok_or_else(|| de::Error::missing_field(FIELD_SLOT))?;
+        let block_height = block_height.ok_or_else(|| de::Error::missing_field(FIELD_BLOCK_HEIGHT))?;
+        let block_time = block_time.ok_or_else(|| de::Error::missing_field(FIELD_BLOCK_TIME))?;
+        let program_data = program_data.ok_or_else(|| de::Error::missing_field(FIELD_PROGRAM_DATA))?;
+        let accounts = accounts.ok_or_else(|| de::Error::missing_field(FIELD_ACCOUNTS))?;
+        Ok((
+            WhirlpoolState {
+                slot,
+                block_height,
+                block_time,
+                program_data,
+                accounts,
+            },
+            position,
+        ))
     }
 }
 
@@ -147,6 +227,7 @@ impl<'de> DeserializeSeed<'de> for AccountsDeserializeConfig {
     {
         struct LocalVisitor {
             config: AccountDataStoreConfig,
+            filter: Option<WhirlpoolFilter>,
         }
 
         impl<'de> Visitor<'de> for LocalVisitor {
@@ -166,13 +247,18 @@ impl<'de> DeserializeSeed<'de> for AccountsDeserializeConfig {
                 };
                 while let Some(value) = seq.next_element()? {
                     let account: WhirlpoolStateAccount = value;
-                    store.upsert(&account.pubkey, &account.data).unwrap();
+                    if let Some(filter) = self.filter.as_ref() {
+                        if !filter.is_account_needed(&account.pubkey, &account.data) {
+                            continue;
+                        }
+                    }
+                    store.upsert(&account.pubkey, &account.data).map_err(|e| <V::Error as de::Error>::custom(e))?;
                 }
                 Ok(store)
             }
         }
 
-        deserializer.deserialize_seq(LocalVisitor { config: self.config.clone() })
+        deserializer.deserialize_seq(LocalVisitor { config: self.config.clone(), filter: self.filter.clone() })
     }
 }
 
@@ -188,25 +274,87 @@ pub struct WhirlpoolStateSerializer<'a> {
   pub program_data: &'a Vec<u8>,
 }
 
+// accounts are sorted by pubkey, so the same state is always serialized into the same bytes
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhirlpoolCanonicalStateSerializer<'a> {
+  pub slot: u64,
+  pub block_height: u64,
+  pub block_time: i64,
+  #[serde(serialize_with = "serialize_account_data_store_sorted")]
+  pub accounts: &'a AccountDataStore,
+  #[serde(serialize_with = "serialize_base64")]
+  pub program_data: &'a Vec<u8>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhirlpoolCheckpointSerializer<'a> {
+  pub slot: u64,
+  pub block_height: u64,
+  pub block_time: i64,
+  pub position: &'a ReplayPosition,
+  #[serde(serialize_with = "serialize_account_data_store")]
+  pub accounts: &'a AccountDataStore,
+  #[serde(serialize_with = "serialize_base64")]
+  pub program_data: &'a Vec<u8>,
+}
+
 fn serialize_account_data_store<S>(
     accounts: &AccountDataStore,
     serializer: S,
 ) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serialize_accounts(accounts, false, serializer)
+}
+
+fn serialize_account_data_store_sorted<S>(
+    accounts: &AccountDataStore,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serialize_accounts(accounts, true, serializer)
+}
+
+fn serialize_accounts<S>(
+    accounts: &AccountDataStore,
+    sorted: bool,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
 {
     let mut seq = serializer.serialize_seq(None)?;
 
-    accounts
-        .traverse(|pubkey, data| {
-            let account = WhirlpoolStateAccount {
-                pubkey: pubkey.to_string(),
-                data: data.to_vec(),
-            };
-            seq.serialize_element(&account).unwrap();
-            Ok(())
-        })
-        .unwrap();
+    // traverse callback cannot return S::Error, so keep the first error and stop writing
+    let mut serialize_error: Option<S::Error> = None;
+    let callback = |pubkey: &String, data: &Vec<u8>| {
+        if serialize_error.is_some() {
+            return Ok(());
+        }
+        let account = WhirlpoolStateAccount {
+            pubkey: pubkey.to_string(),
+            data: data.to_vec(),
+        };
+        if let Err(err) = seq.serialize_element(&account) {
+            serialize_error = Some(err);
+        }
+        Ok(())
+    };
+    let traversed = if sorted {
+        accounts.traverse_sorted(callback)
+    } else {
+        accounts.traverse(callback)
+    };
+    traversed.map_err(|e| <S::Error as ser::Error>::custom(e))?;
+
+    if let Some(err) = serialize_error {
+        return Err(err);
+    }
 
     seq.end()
 }
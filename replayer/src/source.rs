@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines, Read};
+
+use crate::errors::ReplayError;
+use crate::io::{decompress, parse_whirlpool_transaction_line};
+use crate::schema::WhirlpoolTransaction;
+
+// source of whirlpool transactions (one item per slot, in ascending order of slot)
+pub trait TransactionSource: Send {
+    // returns None if no more slot
+    fn next_transaction(&mut self) -> Option<Result<WhirlpoolTransaction, ReplayError>>;
+}
+
+impl TransactionSource for Box<dyn TransactionSource> {
+    fn next_transaction(&mut self) -> Option<Result<WhirlpoolTransaction, ReplayError>> {
+        self.as_mut().next_transaction()
+    }
+}
+
+// adapter to use TransactionSource as Iterator
+pub(crate) struct TransactionSourceIter<S: TransactionSource> {
+    source: S,
+}
+
+impl<S: TransactionSource> TransactionSourceIter<S> {
+    pub(crate) fn new(source: S) -> Self {
+        Self { source }
+    }
+}
+
+impl<S: TransactionSource> Iterator for TransactionSourceIter<S> {
+    type Item = Result<WhirlpoolTransaction, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.next_transaction()
+    }
+}
+
+// in-memory transactions (e.g. synthetic or filtered ones)
+pub struct VecTransactionSource {
+    transactions: std::vec::IntoIter<WhirlpoolTransaction>,
+}
+
+impl VecTransactionSource {
+    pub fn new(transactions: Vec<WhirlpoolTransaction>) -> Self {
+        Self { transactions: transactions.into_iter() }
+    }
+}
+
+impl From<Vec<WhirlpoolTransaction>> for VecTransactionSource {
+    fn from(transactions: Vec<WhirlpoolTransaction>) -> Self {
+        Self::new(transactions)
+    }
+}
+
+impl TransactionSource for VecTransactionSource {
+    fn next_transaction(&mut self) -> Option<Result<WhirlpoolTransaction, ReplayError>> {
+        self.transactions.next().map(Ok)
+    }
+}
+
+// JSON Lines (same format as whirlpool transaction file) from any reader
+pub struct ReaderTransactionSource<R: BufRead + Send> {
+    lines: Lines<R>,
+    // reported with the line number if a line is broken
+    location: String,
+    line_number: usize,
+}
+
+impl<R: BufRead + Send> ReaderTransactionSource<R> {
+    // reader must provide uncompressed JSON Lines
+    pub fn new(reader: R) -> Self {
+        Self::with_location(reader, &"reader".to_string())
+    }
+
+    // location is the name of the reader (e.g. file path) in the errors
+    pub fn with_location(reader: R, location: &String) -> Self {
+        Self { lines: reader.lines(), location: location.clone(), line_number: 0 }
+    }
+}
+
+impl ReaderTransactionSource<BufReader<Box<dyn Read + Send>>> {
+    // reader provides GZip or Zstd compressed JSON Lines
+    pub fn from_compressed<R: Read + Send + 'static>(reader: R) -> Result<Self, ReplayError> {
+        let decoder = decompress(reader)?;
+        Ok(Self::new(BufReader::new(decoder)))
+    }
+
+    pub fn from_file(file_path: &String) -> Result<Self, ReplayError> {
+        let file = File::open(file_path)?;
+        let decoder = decompress(file)?;
+        Ok(Self::with_location(BufReader::new(decoder), file_path))
+    }
+}
+
+impl<R: BufRead + Send> TransactionSource for ReaderTransactionSource<R> {
+    fn next_transaction(&mut self) -> Option<Result<WhirlpoolTransaction, ReplayError>> {
+        let jsonl = self.lines.next()?;
+        self.line_number += 1;
+        Some(parse_whirlpool_transaction_line(&self.location, self.line_number, jsonl))
+    }
+}
+
+// merge sources into one stream ordered by slot
+// transactions of the same slot from different sources are merged into one slot (ordered by index)
+pub struct MergedTransactionSource {
+    sources: Vec<Box<dyn TransactionSource>>,
+    heads: Vec<Option<WhirlpoolTransaction>>,
+    exhausted: Vec<bool>,
+}
+
+impl MergedTransactionSource {
+    pub fn new(sources: Vec<Box<dyn TransactionSource>>) -> Self {
+        let heads = sources.iter().map(|_| None).collect();
+        let exhausted = sources.iter().map(|_| false).collect();
+        Self { sources, heads, exhausted }
+    }
+
+    // each file is a whirlpool transaction file (GZip or Zstd compressed)
+    pub fn from_files(file_paths: &Vec<String>) -> Result<Self, ReplayError> {
+        let mut sources: Vec<Box<dyn TransactionSource>> = vec![];
+        for file_path in file_paths {
+            sources.push(Box::new(ReaderTransactionSource::from_file(file_path)?));
+        }
+        Ok(Self::new(sources))
+    }
+}
+
+impl TransactionSource for MergedTransactionSource {
+    fn next_transaction(&mut self) -> Option<Result<WhirlpoolTransaction, ReplayError>> {
+        for i in 0..self.sources.len() {
+            if self.heads[i].is_some() || self.exhausted[i] {
+                continue;
+            }
+            match self.sources[i].next_transaction() {
+                Some(Ok(whirlpool_transaction)) => self.heads[i] = Some(whirlpool_transaction),
+                Some(Err(err)) => return Some(Err(err)),
+                None => self.exhausted[i] = true,
+            }
+        }
+
+        let min_slot = self.heads.iter().flatten().map(|head| head.slot).min()?;
+
+        let mut merged: Option<WhirlpoolTransaction> = None;
+        for head in self.heads.iter_mut() {
+            if head.as_ref().map_or(true, |head| head.slot != min_slot) {
+                continue;
+            }
+            let whirlpool_transaction = head.take().unwrap();
+            match merged.as_mut() {
+                Some(merged) => merged.transactions.extend(whirlpool_transaction.transactions),
+                None => merged = Some(whirlpool_transaction),
+            }
+        }
+
+        let mut merged = merged.unwrap();
+        merged.transactions.sort_by_key(|transaction| transaction.index);
+        Some(Ok(merged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Transaction;
+
+    fn whirlpool_transaction(slot: u64, indexes: &[u32]) -> WhirlpoolTransaction {
+        WhirlpoolTransaction {
+            slot,
+            block_height: slot,
+            block_time: slot as i64,
+            transactions: indexes
+                .iter()
+                .map(|index| Transaction {
+                    index: *index,
+                    signature: format!("{}-{}", slot, index),
+                    payer: String::new(),
+                    balances: vec![],
+                    instructions: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    fn collect(source: &mut impl TransactionSource) -> Vec<(u64, Vec<u32>)> {
+        let mut slots = vec![];
+        while let Some(whirlpool_transaction) = source.next_transaction() {
+            let whirlpool_transaction = whirlpool_transaction.unwrap();
+            let indexes = whirlpool_transaction.transactions.iter().map(|transaction| transaction.index).collect();
+            slots.push((whirlpool_transaction.slot, indexes));
+        }
+        slots
+    }
+
+    #[test]
+    fn test_merged_transaction_source_interleaved_slots() {
+        let mut source = MergedTransactionSource::new(vec![
+            Box::new(VecTransactionSource::new(vec![
+                whirlpool_transaction(1, &[0]),
+                whirlpool_transaction(4, &[0]),
+                whirlpool_transaction(5, &[0]),
+            ])),
+            Box::new(VecTransactionSource::new(vec![
+                whirlpool_transaction(2, &[3]),
+                whirlpool_transaction(3, &[1]),
+                whirlpool_transaction(6, &[2]),
+            ])),
+        ]);
+
+        assert_eq!(collect(&mut source), vec![
+            (1, vec![0]),
+            (2, vec![3]),
+            (3, vec![1]),
+            (4, vec![0]),
+            (5, vec![0]),
+            (6, vec![2]),
+        ]);
+        // exhausted
+        assert!(source.next_transaction().is_none());
+    }
+
+    #[test]
+    fn test_merged_transaction_source_shared_slot() {
+        let mut source = MergedTransactionSource::new(vec![
+            Box::new(VecTransactionSource::new(vec![
+                whirlpool_transaction(1, &[0, 4]),
+                whirlpool_transaction(2, &[1]),
+            ])),
+            Box::new(VecTransactionSource::new(vec![
+                whirlpool_transaction(1, &[2, 3]),
+            ])),
+            Box::new(VecTransactionSource::new(vec![
+                whirlpool_transaction(1, &[1]),
+                whirlpool_transaction(2, &[0]),
+                whirlpool_transaction(3, &[5]),
+            ])),
+        ]);
+
+        // transactions of the same slot are merged into one slot (sorted by index)
+        assert_eq!(collect(&mut source), vec![
+            (1, vec![0, 1, 2, 3, 4]),
+            (2, vec![0, 1]),
+            (3, vec![5]),
+        ]);
+    }
+
+    #[test]
+    fn test_merged_transaction_source_empty() {
+        let mut source = MergedTransactionSource::new(vec![
+            Box::new(VecTransactionSource::new(vec![])),
+            Box::new(VecTransactionSource::new(vec![whirlpool_transaction(7, &[0])])),
+        ]);
+        assert_eq!(collect(&mut source), vec![(7, vec![0])]);
+
+        let mut source = MergedTransactionSource::new(vec![]);
+        assert!(source.next_transaction().is_none());
+    }
+
+    #[test]
+    fn test_reader_transaction_source() {
+        let jsonl = "{\"slot\":1,\"blockHeight\":1,\"blockTime\":1,\"transactions\":[]}\n{\"slot\":2,\"blockHeight\":2,\"blockTime\":2,\"transactions\":[]}\n";
+        let mut source = ReaderTransactionSource::new(jsonl.as_bytes());
+        assert_eq!(collect(&mut source), vec![(1, vec![]), (2, vec![])]);
+    }
+}
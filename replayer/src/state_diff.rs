@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use replay_engine::account_data_store::AccountDataStore;
+use serde_derive::Serialize;
+
+use crate::decoder;
+use crate::diff::{self, AccountChangeKind, AccountDiff};
+use crate::errors::ReplayError;
+use crate::io;
+use crate::serde::AccountDataStoreConfig;
+
+/*
+
+State diff
+
+Compares the accounts of two states (left as old, right as new).
+A state is loaded from one of the following sources.
+- state file (whirlpool-state-yyyymmdd.json.gz or .json.zst, checkpoint files are accepted too)
+- snapshot CSV file (whirlpool-snapshot-<slot>.csv.gz or .csv.zst, compact or full-columns)
+- account data store (e.g. the live state of the replayer: WhirlpoolReplayer::get_accounts)
+
+Both sources are fully loaded into account data stores before the comparison (diff_sources),
+so use the on-disk store (AccountDataStoreConfig) for large inputs.
+Both sides are walked in pubkey order (AccountDataStore::traverse_sorted).
+The left side is streamed, and only the pubkeys of the right side are kept on memory to merge them,
+the account data of the right side is read from the store one by one.
+
+Accounts only in the left are reported as Deleted, accounts only in the right as Created.
+An account with empty data (uninitialized account) only in one side is counted as left only or right only in the summary,
+but it is not passed to the callback because it has no data to compare.
+
+*/
+
+pub enum StateSource {
+    StateFile(String),
+    SnapshotCsvFile(String),
+}
+
+// counts of the accounts of an account type
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountTypeSummary {
+    pub identical: u64,
+    pub different: u64,
+    pub left_only: u64,
+    pub right_only: u64,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDiffSummary {
+    pub account_types: BTreeMap<String, AccountTypeSummary>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDiffReport {
+    pub summary: StateDiffSummary,
+    // sorted by pubkey
+    pub accounts: Vec<AccountDiff>,
+}
+
+impl StateSource {
+    // snapshot CSV file is detected by the extension (.csv.gz, .csv.zst)
+    pub fn from_path(path: &String) -> StateSource {
+        if path.ends_with(".csv.gz") || path.ends_with(".csv.zst") {
+            StateSource::SnapshotCsvFile(path.clone())
+        } else {
+            StateSource::StateFile(path.clone())
+        }
+    }
+
+    pub fn load(&self, account_data_store_config: &AccountDataStoreConfig) -> Result<AccountDataStore, ReplayError> {
+        match self {
+            StateSource::StateFile(path) => Ok(io::load_from_local_whirlpool_state_file(path, account_data_store_config)?.accounts),
+            StateSource::SnapshotCsvFile(path) => io::load_from_local_whirlpool_snapshot_csv_file(path, account_data_store_config),
+        }
+    }
+}
+
+impl AccountTypeSummary {
+    pub fn is_identical(&self) -> bool {
+        self.different == 0 && self.left_only == 0 && self.right_only == 0
+    }
+
+    fn add(&mut self, other: &AccountTypeSummary) {
+        self.identical += other.identical;
+        self.different += other.different;
+        self.left_only += other.left_only;
+        self.right_only += other.right_only;
+    }
+}
+
+impl StateDiffSummary {
+    pub fn total(&self) -> AccountTypeSummary {
+        let mut total = AccountTypeSummary::default();
+        self.account_types.values().for_each(|summary| total.add(summary));
+        total
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.total().is_identical()
+    }
+}
+
+pub fn diff_sources<F>(
+    left: &StateSource,
+    right: &StateSource,
+    account_data_store_config: &AccountDataStoreConfig,
+    callback: F,
+) -> Result<StateDiffSummary, ReplayError>
+where
+    F: FnMut(&AccountDiff) -> Result<(), ReplayError>,
+{
+    let left = left.load(account_data_store_config)?;
+    let right = right.load(account_data_store_config)?;
+    diff_account_stores(&left, &right, callback)
+}
+
+// callback receives the different accounts in pubkey order
+pub fn diff_account_stores<F>(left: &AccountDataStore, right: &AccountDataStore, mut callback: F) -> Result<StateDiffSummary, ReplayError>
+where
+    F: FnMut(&AccountDiff) -> Result<(), ReplayError>,
+{
+    let mut right_pubkeys = vec![];
+    right
+        .traverse_sorted(|pubkey, _data| {
+            right_pubkeys.push(pubkey.clone());
+            Ok(())
+        })
+        .map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+
+    // tick arrays are named with the tick spacing of the right (new) whirlpools, or the left ones if deleted
+    let right_tick_spacing = diff::store_tick_spacing(right);
+    let left_tick_spacing = diff::store_tick_spacing(left);
+    let tick_spacing = |whirlpool: &String| right_tick_spacing(whirlpool).or_else(|| left_tick_spacing(whirlpool));
+
+    let mut summary = StateDiffSummary::default();
+    let mut diff = |pubkey: &String, left_data: Option<&[u8]>, right_data: Option<&[u8]>| -> Result<(), ReplayError> {
+        match diff::diff_account(pubkey, left_data, right_data, &tick_spacing) {
+            Some(account_diff) => {
+                let counts = summary.account_types.entry(account_diff.account_type.clone()).or_default();
+                match account_diff.kind {
+                    AccountChangeKind::Updated => counts.different += 1,
+                    AccountChangeKind::Deleted => counts.left_only += 1,
+                    AccountChangeKind::Created => counts.right_only += 1,
+                }
+                callback(&account_diff)
+            }
+            None => {
+                let data = left_data.or(right_data).unwrap_or_default();
+                let account_type = decoder::registry().account_type(data).unwrap_or("Unknown");
+                let counts = summary.account_types.entry(account_type.to_string()).or_default();
+                // the data is identical (or empty), but an empty account only in one side is not identical
+                match (left_data, right_data) {
+                    (Some(_), None) => counts.left_only += 1,
+                    (None, Some(_)) => counts.right_only += 1,
+                    _ => counts.identical += 1,
+                }
+                Ok(())
+            }
+        }
+    };
+
+    // merge join of the sorted pubkeys
+    // the first error in the traverse callback (traverse callback cannot return ReplayError)
+    let mut error = None;
+    let mut right_pubkeys = right_pubkeys.into_iter().peekable();
+    left
+        .traverse_sorted(|pubkey, left_data| {
+            if error.is_some() {
+                return Ok(());
+            }
+            let mut merge = || -> Result<(), ReplayError> {
+                // right only accounts before the pubkey
+                while let Some(right_pubkey) = right_pubkeys.next_if(|right_pubkey| right_pubkey < pubkey) {
+                    diff(&right_pubkey, None, get_account_data(right, &right_pubkey)?.as_deref())?;
+                }
+                let right_data = match right_pubkeys.next_if(|right_pubkey| right_pubkey == pubkey) {
+                    Some(_) => get_account_data(right, pubkey)?,
+                    None => None,
+                };
+                diff(pubkey, Some(left_data.as_slice()), right_data.as_deref())
+            };
+            if let Err(err) = merge() {
+                error = Some(err);
+            }
+            Ok(())
+        })
+        .map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+    if let Some(err) = error {
+        return Err(err);
+    }
+
+    // right only accounts after the last pubkey of the left
+    for right_pubkey in right_pubkeys {
+        diff(&right_pubkey, None, get_account_data(right, &right_pubkey)?.as_deref())?;
+    }
+
+    Ok(summary)
+}
+
+// all different accounts are kept on memory (use diff_account_stores with a callback for large diffs)
+pub fn build_state_diff_report(left: &AccountDataStore, right: &AccountDataStore) -> Result<StateDiffReport, ReplayError> {
+    let mut accounts = vec![];
+    let summary = diff_account_stores(left, right, |account_diff| {
+        accounts.push(account_diff.clone());
+        Ok(())
+    })?;
+    Ok(StateDiffReport { summary, accounts })
+}
+
+impl fmt::Display for StateDiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:24} {:>10} {:>10} {:>10} {:>10}", "account type", "identical", "different", "left only", "right only")?;
+        let total = self.total();
+        for (account_type, counts) in self.account_types.iter().map(|(k, v)| (k.as_str(), v)).chain(std::iter::once(("(total)", &total))) {
+            writeln!(
+                f,
+                "{:24} {:>10} {:>10} {:>10} {:>10}",
+                account_type, counts.identical, counts.different, counts.left_only, counts.right_only,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn get_account_data(store: &AccountDataStore, pubkey: &String) -> Result<Option<Vec<u8>>, ReplayError> {
+    store.get(pubkey).map_err(|e| ReplayError::AccountDataStore(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use base64::prelude::{Engine as _, BASE64_STANDARD};
+    use crate::test_utils::{pubkey, whirlpool_data, WhirlpoolParams};
+
+    fn whirlpool(sqrt_price: u128) -> Vec<u8> {
+        whirlpool_data(&WhirlpoolParams {
+            token_mint_a: 1,
+            token_mint_b: 2,
+            fee_rate: 3000,
+            sqrt_price,
+            tick_current_index: 0,
+            liquidity: 0,
+        })
+    }
+
+    // same layout as the state files (slot, blockHeight and blockTime precede accounts)
+    fn save_state_file(file_path: &String, accounts: &[(String, Vec<u8>)]) {
+        let accounts = accounts
+            .iter()
+            .map(|(pubkey, data)| format!("{{\"pubkey\":\"{}\",\"data\":\"{}\"}}", pubkey, BASE64_STANDARD.encode(data)))
+            .collect::<Vec<String>>()
+            .join(",");
+        let state = format!("{{\"slot\":1,\"blockHeight\":1,\"blockTime\":1,\"accounts\":[{}],\"programData\":\"\"}}", accounts);
+        io::save_to_compressed_file(file_path, |writer| Ok(writer.write_all(state.as_bytes())?)).unwrap();
+    }
+
+    fn store(accounts: &[(String, Vec<u8>)]) -> AccountDataStore {
+        let mut store = AccountDataStore::new_on_memory();
+        for (pubkey, data) in accounts.iter() {
+            store.upsert(pubkey, data).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn test_diff_state_files() {
+        let left = [
+            (pubkey(1), whirlpool(100)),
+            (pubkey(2), whirlpool(300)),
+            (pubkey(3), vec![1, 2, 3]),
+            (pubkey(5), vec![5, 5, 5]),
+        ];
+        let right = [
+            (pubkey(5), vec![5, 5, 6]),
+            (pubkey(4), vec![4, 5, 6]),
+            (pubkey(2), whirlpool(300)),
+            (pubkey(1), whirlpool(200)),
+        ];
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let left_path = tempdir.path().join("left.json.gz").to_str().unwrap().to_string();
+        let right_path = tempdir.path().join("right.json.zst").to_str().unwrap().to_string();
+        save_state_file(&left_path, &left);
+        save_state_file(&right_path, &right);
+
+        for config in [AccountDataStoreConfig::OnMemory, AccountDataStoreConfig::OnDisk(None)] {
+            let mut accounts = vec![];
+            let summary = diff_sources(&StateSource::from_path(&left_path), &StateSource::from_path(&right_path), &config, |account_diff| {
+                accounts.push(account_diff.clone());
+                Ok(())
+            })
+            .unwrap();
+
+            assert_eq!(
+                summary.account_types,
+                BTreeMap::from([
+                    ("Whirlpool".to_string(), AccountTypeSummary { identical: 1, different: 1, left_only: 0, right_only: 0 }),
+                    ("Unknown".to_string(), AccountTypeSummary { identical: 0, different: 1, left_only: 1, right_only: 1 }),
+                ])
+            );
+            assert_eq!(summary.total(), AccountTypeSummary { identical: 1, different: 2, left_only: 1, right_only: 1 });
+            assert!(!summary.is_identical());
+
+            // sorted by pubkey
+            let mut expected_pubkeys = vec![pubkey(1), pubkey(3), pubkey(4), pubkey(5)];
+            expected_pubkeys.sort();
+            assert_eq!(accounts.iter().map(|account_diff| account_diff.pubkey.clone()).collect::<Vec<_>>(), expected_pubkeys);
+
+            let account = |n: u8| accounts.iter().find(|account_diff| account_diff.pubkey == pubkey(n)).unwrap();
+            // changed
+            assert_eq!((account(1).account_type.as_str(), account(1).kind), ("Whirlpool", AccountChangeKind::Updated));
+            assert_eq!(
+                account(1).field_changes,
+                vec![diff::FieldChange { field: "sqrt_price".to_string(), old: Some("100".to_string()), new: Some("200".to_string()) }]
+            );
+            assert_eq!((account(5).account_type.as_str(), account(5).kind), ("Unknown", AccountChangeKind::Updated));
+            assert_eq!(account(5).field_changes[0].field, "data");
+            // removed and added
+            assert_eq!(account(3).kind, AccountChangeKind::Deleted);
+            assert_eq!(account(4).kind, AccountChangeKind::Created);
+        }
+    }
+
+    #[test]
+    fn test_diff_account_stores_one_side_empty() {
+        let accounts = [(pubkey(1), whirlpool(100)), (pubkey(2), vec![1, 2, 3])];
+
+        let report = build_state_diff_report(&store(&[]), &store(&accounts)).unwrap();
+        assert_eq!(report.summary.total(), AccountTypeSummary { identical: 0, different: 0, left_only: 0, right_only: 2 });
+        assert!(report.accounts.iter().all(|account_diff| account_diff.kind == AccountChangeKind::Created));
+
+        let report = build_state_diff_report(&store(&accounts), &store(&[])).unwrap();
+        assert_eq!(report.summary.total(), AccountTypeSummary { identical: 0, different: 0, left_only: 2, right_only: 0 });
+        assert!(report.accounts.iter().all(|account_diff| account_diff.kind == AccountChangeKind::Deleted));
+
+        let report = build_state_diff_report(&store(&accounts), &store(&accounts)).unwrap();
+        assert!(report.summary.is_identical());
+        assert!(report.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_diff_account_stores_empty_data_in_one_side() {
+        let both = (pubkey(1), whirlpool(100));
+        let empty_left = (pubkey(2), vec![]);
+        let empty_right = (pubkey(3), vec![]);
+        let empty_both = (pubkey(4), vec![]);
+
+        let left = store(&[both.clone(), empty_left, empty_both.clone()]);
+        let right = store(&[both, empty_right, empty_both]);
+        let report = build_state_diff_report(&left, &right).unwrap();
+
+        assert_eq!(report.summary.account_types["Whirlpool"], AccountTypeSummary { identical: 1, different: 0, left_only: 0, right_only: 0 });
+        assert_eq!(report.summary.account_types["Unknown"], AccountTypeSummary { identical: 1, different: 0, left_only: 1, right_only: 1 });
+        assert!(!report.summary.is_identical());
+        // no data to compare
+        assert!(report.accounts.is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, AccountSerialize};
+use replay_engine::decoded_instructions::{DecodedSwap, DecodedWhirlpoolInstruction};
+use replay_engine::types::{AccountSnapshot, WritableAccountSnapshot};
+use whirlpool_base::state::Whirlpool;
+
+use crate::decoder::account_discriminator;
+
+// 8 (discriminator) + 261 + 384 (reward infos)
+const WHIRLPOOL_LEN: usize = 653;
+
+pub const WHIRLPOOL_PROGRAM_FOR_TESTING: &[u8] = include_bytes!("../../replay-engine/src/tests/whirlpool-for-testing.so");
+
+// instruction payloads (the same as the transaction file) to build a whirlpool from scratch
+pub const INITIALIZE_CONFIG: &str = r#"{"dataDefaultProtocolFeeRate": 300, "dataFeeAuthority": "3Q4pMMf5e2wcqYMGZhjpDWEmWaAVAVzMxKJtsv2iMu1R", "dataCollectProtocolFeesAuthority": "EXz6jkK22aa6jqgkG4q3GXm5Ankb6gh8Dsk4VytWupf7", "dataRewardEmissionsSuperAuthority": "34H4AgZt4CpS4488bWNcXUZFqpbXrfCnS7ika2BUwY3p", "keyWhirlpoolsConfig": "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D", "keyFunder": "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6", "keySystemProgram": "11111111111111111111111111111111"}"#;
+pub const INITIALIZE_FEE_TIER: &str = r#"{"dataTickSpacing": 64, "dataDefaultFeeRate": 3000, "keyWhirlpoolsConfig": "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D", "keyFeeTier": "C7HVbbKnAnuXfhZ87mefqWPXDo2cjrj35yYmq3HhV1D6", "keyFunder": "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6", "keyFeeAuthority": "3Q4pMMf5e2wcqYMGZhjpDWEmWaAVAVzMxKJtsv2iMu1R", "keySystemProgram": "11111111111111111111111111111111"}"#;
+pub const INITIALIZE_POOL_V2: &str = r#"{"dataTickSpacing": 64, "dataInitialSqrtPrice": "92233720368547758080", "keyWhirlpoolsConfig": "Drks8NktGoBWtV5YPWwKEWR9skUWypjmS6q46ckjPi8D", "keyTokenMintA": "7Xws5FruPQGB3Jq9xj4Cc55rruVjY8mdzmmr6wpUHeqB", "keyTokenMintB": "7j8yxRszXTonjCS7LsnvLtitRSBCggPM2Bx9yzJjcT9y", "keyTokenBadgeA": "FpegWLV3Gj8ye8gP9Jc5VPWXm6q4cWCSHoc6g56KYEyv", "keyTokenBadgeB": "AKC42rsBPMDu2F4DfSjzj3SeB2CGMEdbhVTDVJjrxM9N", "keyFunder": "r21Gamwd9DtyjHeGywsneoQYR39C1VDwrw7tWxHAwh6", "keyWhirlpool": "BsGwEuUqbfeUSDN4mmxhcGFhNYKypKH8NZjoQ7DQrFfC", "keyTokenVaultA": "FNiNQiXYgFhKcKuU16DuNDxZynVAmNG2DVs3ukXe1JeB", "keyTokenVaultB": "6tMEfTsiby8m1jh861Zb23aTQt65c8mjVvL6PgkjXmjh", "keyFeeTier": "C7HVbbKnAnuXfhZ87mefqWPXDo2cjrj35yYmq3HhV1D6", "keyTokenProgramA": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", "keyTokenProgramB": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", "keySystemProgram": "11111111111111111111111111111111", "keyRent": "SysvarRent111111111111111111111111111111111", "decimalsTokenMintA": 0, "decimalsTokenMintB": 0}"#;
+pub const INITIALIZE_TICK_ARRAY_NEG_444928: &str = r#"{"dataStartTickIndex": -444928, "dataIdempotent": 0, "keyWhirlpool": "BsGwEuUqbfeUSDN4mmxhcGFhNYKypKH8NZjoQ7DQrFfC", "keyFunder": "9ar6ByCBqCYyH7VWSNah7h5hR2FcHtig5Dnhcq5UTFR7", "keyTickArray": "CPWekMYLLoEggpQCV4ddND6pGCo4LcGb13uvSmsBHfpc", "keySystemProgram": "11111111111111111111111111111111"}"#;
+pub const INITIALIZE_TICK_ARRAY_439296: &str = r#"{"dataStartTickIndex": 439296, "dataIdempotent": 0, "keyWhirlpool": "BsGwEuUqbfeUSDN4mmxhcGFhNYKypKH8NZjoQ7DQrFfC", "keyFunder": "9ar6ByCBqCYyH7VWSNah7h5hR2FcHtig5Dnhcq5UTFR7", "keyTickArray": "ESzF37B5Z3JzjU47sMAymWXfrbD2RoezWEnnPiATtvt2", "keySystemProgram": "11111111111111111111111111111111"}"#;
+
+// deterministic pubkey for tests (all bytes are n)
+pub fn pubkey(n: u8) -> String {
+    Pubkey::new_from_array([n; 32]).to_string()
+}
+
+// price and liquidity of a whirlpool account at some point
+#[derive(Clone, Copy)]
+pub struct WhirlpoolParams {
+    pub token_mint_a: u8,
+    pub token_mint_b: u8,
+    pub fee_rate: u16,
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub liquidity: u128,
+}
+
+// Whirlpool account data (the fields not in params are zero)
+pub fn whirlpool_data(params: &WhirlpoolParams) -> Vec<u8> {
+    let mut zero = vec![0u8; WHIRLPOOL_LEN];
+    zero[0..8].copy_from_slice(&account_discriminator("Whirlpool"));
+
+    let mut whirlpool = Whirlpool::try_deserialize(&mut zero.as_slice()).unwrap();
+    whirlpool.token_mint_a = Pubkey::new_from_array([params.token_mint_a; 32]);
+    whirlpool.token_mint_b = Pubkey::new_from_array([params.token_mint_b; 32]);
+    whirlpool.fee_rate = params.fee_rate;
+    whirlpool.sqrt_price = params.sqrt_price;
+    whirlpool.tick_current_index = params.tick_current_index;
+    whirlpool.liquidity = params.liquidity;
+
+    let mut data = vec![];
+    whirlpool.try_serialize(&mut data).unwrap();
+    data
+}
+
+pub fn account_snapshot(accounts: &[(String, Vec<u8>)]) -> AccountSnapshot {
+    accounts.iter().cloned().collect()
+}
+
+pub fn writable_account_snapshot(pre: &[(String, Vec<u8>)], post: &[(String, Vec<u8>)]) -> WritableAccountSnapshot {
+    WritableAccountSnapshot {
+        pre_snapshot: account_snapshot(pre),
+        post_snapshot: account_snapshot(post),
+    }
+}
+
+// swap instruction (the accounts other than whirlpool and oracle are dummy)
+pub fn swap(whirlpool: &String, oracle: &String, a_to_b: bool, amount_in: u64, amount_out: u64) -> DecodedWhirlpoolInstruction {
+    DecodedWhirlpoolInstruction::Swap(DecodedSwap {
+        data_amount: amount_in,
+        data_other_amount_threshold: 0,
+        data_sqrt_price_limit: 0,
+        data_amount_specified_is_input: true,
+        data_a_to_b: a_to_b,
+        key_token_program: pubkey(0),
+        key_token_authority: pubkey(0),
+        key_whirlpool: whirlpool.clone(),
+        key_token_owner_account_a: pubkey(0),
+        key_vault_a: pubkey(0),
+        key_token_owner_account_b: pubkey(0),
+        key_vault_b: pubkey(0),
+        key_tick_array_0: pubkey(0),
+        key_tick_array_1: pubkey(0),
+        key_tick_array_2: pubkey(0),
+        key_oracle: oracle.clone(),
+        transfer_amount_0: amount_in,
+        transfer_amount_1: amount_out,
+    })
+}
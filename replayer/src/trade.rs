@@ -0,0 +1,550 @@
+use anchor_lang::AccountDeserialize;
+use replay_engine::decoded_instructions::{deserialize_u128, serialize_u128, DecodedWhirlpoolInstruction, TransferAmountWithTransferFeeConfig};
+use replay_engine::types::{AccountSnapshot, WritableAccountSnapshot};
+use serde_derive::{Deserialize, Serialize};
+use whirlpool_base::state::Whirlpool;
+
+use crate::decoder::{decode_account_data, DecodedAccount};
+use crate::errors::ReplayError;
+
+// fee_rate is in hundredths of a basis point
+const FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+const FEE_RATE_HARD_LIMIT: u32 = 100_000;
+const TRANSFER_FEE_BPS_DENOMINATOR: u128 = 10_000;
+
+// adaptive fee (variable fee rate from the volatility accumulator of the oracle)
+const ADAPTIVE_FEE_CONTROL_FACTOR_DENOMINATOR: u128 = 100_000;
+const VOLATILITY_ACCUMULATOR_SCALE_FACTOR: u128 = 10_000;
+
+/*
+
+Trade event
+
+A normalized view of the swap instructions (swap, swapV2, twoHopSwap, twoHopSwapV2).
+Each swap yields one event, and each two-hop swap yields two leg events linked together.
+
+- amount_in, amount_out: token amounts transferred from the trader to the vault and from the vault to the trader
+- transfer_fee_in, transfer_fee_out: Token-2022 transfer fee included in amount_in and amount_out (0 for V1 instructions)
+- fee_rate: the fee rate of the swap (static fee rate + variable fee rate if the pool uses the adaptive fee)
+- fee_amount: the fee paid in the input token, computed from fee_rate and the input amount received by the vault
+
+The variable fee rate changes along the price move, so fee_rate (and fee_amount) of an adaptive fee pool is
+an estimate based on the volatility accumulator of the oracle after the swap.
+
+*/
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeEvent {
+    pub whirlpool: String,
+    pub token_mint_a: String,
+    pub token_mint_b: String,
+    pub a_to_b: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub transfer_fee_in: u64,
+    pub transfer_fee_out: u64,
+    pub fee_rate: u32,
+    pub fee_amount: u64,
+    #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+    pub pre_sqrt_price: u128,
+    #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+    pub post_sqrt_price: u128,
+    pub pre_tick_current_index: i32,
+    pub post_tick_current_index: i32,
+    #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+    pub pre_liquidity: u128,
+    #[serde(deserialize_with = "deserialize_u128", serialize_with = "serialize_u128")]
+    pub post_liquidity: u128,
+    pub leg: TradeLeg,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum TradeLeg {
+    // swap, swapV2
+    Single,
+    // the first leg of two-hop swap (its output is the input of the next leg)
+    #[serde(rename_all = "camelCase")]
+    TwoHopOne { next_whirlpool: String },
+    // the second leg of two-hop swap
+    #[serde(rename_all = "camelCase")]
+    TwoHopTwo { prev_whirlpool: String },
+}
+
+struct SwapLeg<'a> {
+    whirlpool: &'a String,
+    oracle: &'a String,
+    a_to_b: bool,
+    transfer_in: Transfer,
+    transfer_out: Transfer,
+    leg: TradeLeg,
+}
+
+#[derive(Clone, Copy)]
+struct Transfer {
+    amount: u64,
+    fee: u64,
+}
+
+impl TradeEvent {
+    // trade events of the swap instruction (empty for other instructions)
+    pub fn from_instruction(
+        instruction: &DecodedWhirlpoolInstruction,
+        snapshot: &WritableAccountSnapshot,
+    ) -> Result<Vec<TradeEvent>, ReplayError> {
+        let mut events = vec![];
+        for leg in swap_legs(instruction) {
+            let pre = get_whirlpool(&snapshot.pre_snapshot, leg.whirlpool)?;
+            let post = get_whirlpool(&snapshot.post_snapshot, leg.whirlpool)?;
+
+            // the oracle account (writable) is not initialized if the pool doesn't use the adaptive fee
+            let variable_fee_rate = snapshot.post_snapshot
+                .get(leg.oracle)
+                .and_then(|data| variable_fee_rate(data.as_slice()))
+                .unwrap_or(0);
+            let fee_rate = (pre.fee_rate as u32 + variable_fee_rate).min(FEE_RATE_HARD_LIMIT);
+
+            let net_amount_in = leg.transfer_in.amount - leg.transfer_in.fee;
+            let fee_amount = (net_amount_in as u128 * fee_rate as u128).div_ceil(FEE_RATE_DENOMINATOR) as u64;
+
+            events.push(TradeEvent {
+                whirlpool: leg.whirlpool.clone(),
+                token_mint_a: pre.token_mint_a.to_string(),
+                token_mint_b: pre.token_mint_b.to_string(),
+                a_to_b: leg.a_to_b,
+                amount_in: leg.transfer_in.amount,
+                amount_out: leg.transfer_out.amount,
+                transfer_fee_in: leg.transfer_in.fee,
+                transfer_fee_out: leg.transfer_out.fee,
+                fee_rate,
+                fee_amount,
+                pre_sqrt_price: pre.sqrt_price,
+                post_sqrt_price: post.sqrt_price,
+                pre_tick_current_index: pre.tick_current_index,
+                post_tick_current_index: post.tick_current_index,
+                pre_liquidity: pre.liquidity,
+                post_liquidity: post.liquidity,
+                leg: leg.leg,
+            });
+        }
+        Ok(events)
+    }
+
+    // traded amount of token A and token B
+    pub fn amounts_ab(&self) -> (u64, u64) {
+        if self.a_to_b {
+            (self.amount_in, self.amount_out)
+        } else {
+            (self.amount_out, self.amount_in)
+        }
+    }
+}
+
+fn swap_legs(instruction: &DecodedWhirlpoolInstruction) -> Vec<SwapLeg> {
+    match instruction {
+        DecodedWhirlpoolInstruction::Swap(ix) => vec![
+            SwapLeg {
+                whirlpool: &ix.key_whirlpool,
+                oracle: &ix.key_oracle,
+                a_to_b: ix.data_a_to_b,
+                transfer_in: Transfer::without_fee(ix.transfer_amount_0),
+                transfer_out: Transfer::without_fee(ix.transfer_amount_1),
+                leg: TradeLeg::Single,
+            },
+        ],
+        DecodedWhirlpoolInstruction::SwapV2(ix) => vec![
+            SwapLeg {
+                whirlpool: &ix.key_whirlpool,
+                oracle: &ix.key_oracle,
+                a_to_b: ix.data_a_to_b,
+                transfer_in: Transfer::with_fee(&ix.transfer_0),
+                transfer_out: Transfer::with_fee(&ix.transfer_1),
+                leg: TradeLeg::Single,
+            },
+        ],
+        // transfer_amount_1 (out of one) and transfer_amount_2 (into two) are transferred via the trader's intermediate account
+        DecodedWhirlpoolInstruction::TwoHopSwap(ix) => vec![
+            SwapLeg {
+                whirlpool: &ix.key_whirlpool_one,
+                oracle: &ix.key_oracle_one,
+                a_to_b: ix.data_a_to_b_one,
+                transfer_in: Transfer::without_fee(ix.transfer_amount_0),
+                transfer_out: Transfer::without_fee(ix.transfer_amount_1),
+                leg: TradeLeg::TwoHopOne { next_whirlpool: ix.key_whirlpool_two.clone() },
+            },
+            SwapLeg {
+                whirlpool: &ix.key_whirlpool_two,
+                oracle: &ix.key_oracle_two,
+                a_to_b: ix.data_a_to_b_two,
+                transfer_in: Transfer::without_fee(ix.transfer_amount_2),
+                transfer_out: Transfer::without_fee(ix.transfer_amount_3),
+                leg: TradeLeg::TwoHopTwo { prev_whirlpool: ix.key_whirlpool_one.clone() },
+            },
+        ],
+        // transfer_1 is transferred from the vault of one to the vault of two directly
+        DecodedWhirlpoolInstruction::TwoHopSwapV2(ix) => vec![
+            SwapLeg {
+                whirlpool: &ix.key_whirlpool_one,
+                oracle: &ix.key_oracle_one,
+                a_to_b: ix.data_a_to_b_one,
+                transfer_in: Transfer::with_fee(&ix.transfer_0),
+                transfer_out: Transfer::with_fee(&ix.transfer_1),
+                leg: TradeLeg::TwoHopOne { next_whirlpool: ix.key_whirlpool_two.clone() },
+            },
+            SwapLeg {
+                whirlpool: &ix.key_whirlpool_two,
+                oracle: &ix.key_oracle_two,
+                a_to_b: ix.data_a_to_b_two,
+                transfer_in: Transfer::with_fee(&ix.transfer_1),
+                transfer_out: Transfer::with_fee(&ix.transfer_2),
+                leg: TradeLeg::TwoHopTwo { prev_whirlpool: ix.key_whirlpool_one.clone() },
+            },
+        ],
+        _ => vec![],
+    }
+}
+
+impl Transfer {
+    fn without_fee(amount: u64) -> Transfer {
+        Transfer { amount, fee: 0 }
+    }
+
+    // Token-2022 transfer fee (withheld from the transferred amount)
+    fn with_fee(transfer: &TransferAmountWithTransferFeeConfig) -> Transfer {
+        if !transfer.transfer_fee_config_opt {
+            return Transfer::without_fee(transfer.amount);
+        }
+        let fee = (transfer.amount as u128 * transfer.transfer_fee_config_bps as u128).div_ceil(TRANSFER_FEE_BPS_DENOMINATOR) as u64;
+        Transfer {
+            amount: transfer.amount,
+            fee: fee.min(transfer.transfer_fee_config_max).min(transfer.amount),
+        }
+    }
+}
+
+// None if the oracle account is not initialized
+fn variable_fee_rate(oracle_data: &[u8]) -> Option<u32> {
+    let Ok(Some(DecodedAccount::Oracle(oracle))) = decode_account_data(oracle_data) else {
+        return None;
+    };
+
+    let crossed = oracle.volatility_accumulator as u128 * oracle.tick_group_size as u128;
+    let variable_fee_rate = (oracle.adaptive_fee_control_factor as u128 * crossed * crossed)
+        .div_ceil(ADAPTIVE_FEE_CONTROL_FACTOR_DENOMINATOR * VOLATILITY_ACCUMULATOR_SCALE_FACTOR * VOLATILITY_ACCUMULATOR_SCALE_FACTOR);
+    Some(variable_fee_rate.min(FEE_RATE_HARD_LIMIT as u128) as u32)
+}
+
+pub(crate) fn get_whirlpool(snapshot: &AccountSnapshot, pubkey: &String) -> Result<Whirlpool, ReplayError> {
+    let data = snapshot
+        .get(pubkey)
+        .ok_or_else(|| ReplayError::AccountDeserialize(format!("whirlpool not found in snapshot: {}", pubkey)))?;
+    Whirlpool::try_deserialize(&mut data.as_slice())
+        .map_err(|e| ReplayError::AccountDeserialize(format!("failed to deserialize whirlpool {}: {}", pubkey, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use replay_engine::decoded_instructions::{DecodedSwapV2, DecodedTwoHopSwap, DecodedTwoHopSwapV2};
+    use crate::test_utils::{pubkey, swap, whirlpool_data, writable_account_snapshot, WhirlpoolParams};
+    use crate::decoder::account_discriminator;
+
+    const WHIRLPOOL_ONE: u8 = 10;
+    const ORACLE_ONE: u8 = 11;
+    const WHIRLPOOL_TWO: u8 = 20;
+    const ORACLE_TWO: u8 = 21;
+    const STATIC_FEE_RATE: u16 = 3000;
+
+    // whirlpool one: token 1 / token 2, whirlpool two: token 2 / token 3
+    fn whirlpool(n: u8, sqrt_price: u128) -> (String, Vec<u8>) {
+        let (token_mint_a, token_mint_b) = if n == WHIRLPOOL_ONE { (1, 2) } else { (2, 3) };
+        let data = whirlpool_data(&WhirlpoolParams {
+            token_mint_a,
+            token_mint_b,
+            fee_rate: STATIC_FEE_RATE,
+            sqrt_price,
+            tick_current_index: sqrt_price as i32,
+            liquidity: sqrt_price * 10,
+        });
+        (pubkey(n), data)
+    }
+
+    // initialized oracle of the adaptive fee pool (only the fields used for the variable fee rate)
+    fn oracle(whirlpool: u8, adaptive_fee_control_factor: u32, tick_group_size: u16, volatility_accumulator: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 254];
+        data[0..8].copy_from_slice(&account_discriminator("Oracle"));
+        data[8..40].copy_from_slice(&[whirlpool; 32]);
+        data[54..58].copy_from_slice(&adaptive_fee_control_factor.to_le_bytes());
+        data[62..64].copy_from_slice(&tick_group_size.to_le_bytes());
+        data[106..110].copy_from_slice(&volatility_accumulator.to_le_bytes());
+        data
+    }
+
+    fn transfer(amount: u64, fee_config: Option<(u16, u64)>) -> TransferAmountWithTransferFeeConfig {
+        let (bps, max) = fee_config.unwrap_or_default();
+        TransferAmountWithTransferFeeConfig {
+            amount,
+            transfer_fee_config_opt: fee_config.is_some(),
+            transfer_fee_config_bps: bps,
+            transfer_fee_config_max: max,
+        }
+    }
+
+    fn swap_v2(a_to_b: bool, transfer_0: TransferAmountWithTransferFeeConfig, transfer_1: TransferAmountWithTransferFeeConfig) -> DecodedWhirlpoolInstruction {
+        DecodedWhirlpoolInstruction::SwapV2(DecodedSwapV2 {
+            data_amount: transfer_0.amount,
+            data_other_amount_threshold: 0,
+            data_sqrt_price_limit: 0,
+            data_amount_specified_is_input: true,
+            data_a_to_b: a_to_b,
+            key_token_program_a: pubkey(0),
+            key_token_program_b: pubkey(0),
+            key_memo_program: pubkey(0),
+            key_token_authority: pubkey(0),
+            key_whirlpool: pubkey(WHIRLPOOL_ONE),
+            key_token_mint_a: pubkey(1),
+            key_token_mint_b: pubkey(2),
+            key_token_owner_account_a: pubkey(0),
+            key_vault_a: pubkey(0),
+            key_token_owner_account_b: pubkey(0),
+            key_vault_b: pubkey(0),
+            key_tick_array_0: pubkey(0),
+            key_tick_array_1: pubkey(0),
+            key_tick_array_2: pubkey(0),
+            key_oracle: pubkey(ORACLE_ONE),
+            remaining_accounts_info: vec![],
+            remaining_accounts_keys: vec![],
+            transfer_0,
+            transfer_1,
+        })
+    }
+
+    // token 1 -> token 2 (one: a to b) -> token 3 (two: a to b)
+    fn two_hop_swap(amounts: [u64; 4]) -> DecodedWhirlpoolInstruction {
+        DecodedWhirlpoolInstruction::TwoHopSwap(DecodedTwoHopSwap {
+            data_amount: amounts[0],
+            data_other_amount_threshold: 0,
+            data_amount_specified_is_input: true,
+            data_a_to_b_one: true,
+            data_a_to_b_two: true,
+            data_sqrt_price_limit_one: 0,
+            data_sqrt_price_limit_two: 0,
+            key_token_program: pubkey(0),
+            key_token_authority: pubkey(0),
+            key_whirlpool_one: pubkey(WHIRLPOOL_ONE),
+            key_whirlpool_two: pubkey(WHIRLPOOL_TWO),
+            key_token_owner_account_one_a: pubkey(0),
+            key_vault_one_a: pubkey(0),
+            key_token_owner_account_one_b: pubkey(0),
+            key_vault_one_b: pubkey(0),
+            key_token_owner_account_two_a: pubkey(0),
+            key_vault_two_a: pubkey(0),
+            key_token_owner_account_two_b: pubkey(0),
+            key_vault_two_b: pubkey(0),
+            key_tick_array_one_0: pubkey(0),
+            key_tick_array_one_1: pubkey(0),
+            key_tick_array_one_2: pubkey(0),
+            key_tick_array_two_0: pubkey(0),
+            key_tick_array_two_1: pubkey(0),
+            key_tick_array_two_2: pubkey(0),
+            key_oracle_one: pubkey(ORACLE_ONE),
+            key_oracle_two: pubkey(ORACLE_TWO),
+            transfer_amount_0: amounts[0],
+            transfer_amount_1: amounts[1],
+            transfer_amount_2: amounts[2],
+            transfer_amount_3: amounts[3],
+        })
+    }
+
+    // token 3 -> token 2 (two: b to a) -> token 1 (one: b to a)
+    fn two_hop_swap_v2(
+        transfer_0: TransferAmountWithTransferFeeConfig,
+        transfer_1: TransferAmountWithTransferFeeConfig,
+        transfer_2: TransferAmountWithTransferFeeConfig,
+    ) -> DecodedWhirlpoolInstruction {
+        DecodedWhirlpoolInstruction::TwoHopSwapV2(DecodedTwoHopSwapV2 {
+            data_amount: transfer_0.amount,
+            data_other_amount_threshold: 0,
+            data_amount_specified_is_input: true,
+            data_a_to_b_one: false,
+            data_a_to_b_two: false,
+            data_sqrt_price_limit_one: 0,
+            data_sqrt_price_limit_two: 0,
+            key_whirlpool_one: pubkey(WHIRLPOOL_TWO),
+            key_whirlpool_two: pubkey(WHIRLPOOL_ONE),
+            key_token_mint_input: pubkey(3),
+            key_token_mint_intermediate: pubkey(2),
+            key_token_mint_output: pubkey(1),
+            key_token_program_input: pubkey(0),
+            key_token_program_intermediate: pubkey(0),
+            key_token_program_output: pubkey(0),
+            key_token_owner_account_input: pubkey(0),
+            key_vault_one_input: pubkey(0),
+            key_vault_one_intermediate: pubkey(0),
+            key_vault_two_intermediate: pubkey(0),
+            key_vault_two_output: pubkey(0),
+            key_token_owner_account_output: pubkey(0),
+            key_token_authority: pubkey(0),
+            key_tick_array_one_0: pubkey(0),
+            key_tick_array_one_1: pubkey(0),
+            key_tick_array_one_2: pubkey(0),
+            key_tick_array_two_0: pubkey(0),
+            key_tick_array_two_1: pubkey(0),
+            key_tick_array_two_2: pubkey(0),
+            key_oracle_one: pubkey(ORACLE_TWO),
+            key_oracle_two: pubkey(ORACLE_ONE),
+            key_memo_program: pubkey(0),
+            remaining_accounts_info: vec![],
+            remaining_accounts_keys: vec![],
+            transfer_0,
+            transfer_1,
+            transfer_2,
+        })
+    }
+
+    // whirlpool one: 100 -> 90, whirlpool two: 200 -> 180
+    fn snapshot(oracles: &[(String, Vec<u8>)]) -> WritableAccountSnapshot {
+        let pre = [whirlpool(WHIRLPOOL_ONE, 100), whirlpool(WHIRLPOOL_TWO, 200)];
+        let mut post = vec![whirlpool(WHIRLPOOL_ONE, 90), whirlpool(WHIRLPOOL_TWO, 180)];
+        post.extend_from_slice(oracles);
+        writable_account_snapshot(&pre, &post)
+    }
+
+    #[test]
+    fn test_swap() {
+        let instruction = swap(&pubkey(WHIRLPOOL_ONE), &pubkey(ORACLE_ONE), false, 1_000_000, 900_000);
+        let events = TradeEvent::from_instruction(&instruction, &snapshot(&[])).unwrap();
+        assert_eq!(
+            events,
+            vec![TradeEvent {
+                whirlpool: pubkey(WHIRLPOOL_ONE),
+                token_mint_a: pubkey(1),
+                token_mint_b: pubkey(2),
+                a_to_b: false,
+                amount_in: 1_000_000,
+                amount_out: 900_000,
+                transfer_fee_in: 0,
+                transfer_fee_out: 0,
+                fee_rate: 3000,
+                fee_amount: 3000,
+                pre_sqrt_price: 100,
+                post_sqrt_price: 90,
+                pre_tick_current_index: 100,
+                post_tick_current_index: 90,
+                pre_liquidity: 1000,
+                post_liquidity: 900,
+                leg: TradeLeg::Single,
+            }]
+        );
+        assert_eq!(events[0].amounts_ab(), (900_000, 1_000_000));
+    }
+
+    #[test]
+    fn test_two_hop_swap_legs() {
+        let events = TradeEvent::from_instruction(&two_hop_swap([1000, 900, 900, 800]), &snapshot(&[])).unwrap();
+        assert_eq!(events.len(), 2);
+
+        let (one, two) = (&events[0], &events[1]);
+        assert_eq!(one.whirlpool, pubkey(WHIRLPOOL_ONE));
+        assert_eq!(one.leg, TradeLeg::TwoHopOne { next_whirlpool: pubkey(WHIRLPOOL_TWO) });
+        assert_eq!((one.token_mint_a.clone(), one.token_mint_b.clone()), (pubkey(1), pubkey(2)));
+        assert_eq!((one.amount_in, one.amount_out), (1000, 900));
+        assert_eq!((one.pre_sqrt_price, one.post_sqrt_price), (100, 90));
+
+        assert_eq!(two.whirlpool, pubkey(WHIRLPOOL_TWO));
+        assert_eq!(two.leg, TradeLeg::TwoHopTwo { prev_whirlpool: pubkey(WHIRLPOOL_ONE) });
+        assert_eq!((two.token_mint_a.clone(), two.token_mint_b.clone()), (pubkey(2), pubkey(3)));
+        assert_eq!((two.amount_in, two.amount_out), (900, 800));
+        assert_eq!((two.pre_sqrt_price, two.post_sqrt_price), (200, 180));
+
+        // the output of one is the input of two (token 2)
+        assert_eq!(one.amounts_ab().1, two.amounts_ab().0);
+    }
+
+    #[test]
+    fn test_two_hop_swap_v2_legs() {
+        // the intermediate transfer (vault of two to vault of one) has its own transfer fee
+        let instruction = two_hop_swap_v2(
+            transfer(10_000, None),
+            transfer(9_000, Some((100, 1_000))),
+            transfer(8_000, Some((50, 1_000))),
+        );
+        let events = TradeEvent::from_instruction(&instruction, &snapshot(&[])).unwrap();
+        assert_eq!(events.len(), 2);
+
+        let (one, two) = (&events[0], &events[1]);
+        assert_eq!(one.whirlpool, pubkey(WHIRLPOOL_TWO));
+        assert_eq!(one.leg, TradeLeg::TwoHopOne { next_whirlpool: pubkey(WHIRLPOOL_ONE) });
+        assert_eq!((one.a_to_b, one.amount_in, one.amount_out), (false, 10_000, 9_000));
+        assert_eq!((one.transfer_fee_in, one.transfer_fee_out), (0, 90));
+
+        assert_eq!(two.whirlpool, pubkey(WHIRLPOOL_ONE));
+        assert_eq!(two.leg, TradeLeg::TwoHopTwo { prev_whirlpool: pubkey(WHIRLPOOL_TWO) });
+        assert_eq!((two.a_to_b, two.amount_in, two.amount_out), (false, 9_000, 8_000));
+        assert_eq!((two.transfer_fee_in, two.transfer_fee_out), (90, 40));
+        // fee is computed from the amount received by the vault (9_000 - 90)
+        assert_eq!(two.fee_amount, (8_910u128 * 3000).div_ceil(1_000_000) as u64);
+    }
+
+    #[test]
+    fn test_transfer_with_fee() {
+        let with_fee = |amount, fee_config| {
+            let result = Transfer::with_fee(&transfer(amount, fee_config));
+            (result.amount, result.fee)
+        };
+
+        // no transfer fee config (Token program or Token-2022 without the extension)
+        assert_eq!(with_fee(1000, None), (1000, 0));
+        // 1% (rounded up)
+        assert_eq!(with_fee(1000, Some((100, u64::MAX))), (1000, 10));
+        assert_eq!(with_fee(1001, Some((100, u64::MAX))), (1001, 11));
+        // clamped by the maximum fee
+        assert_eq!(with_fee(1000, Some((100, 5))), (1000, 5));
+        // clamped by the amount
+        assert_eq!(with_fee(1000, Some((10_000, u64::MAX))), (1000, 1000));
+        assert_eq!(with_fee(0, Some((100, u64::MAX))), (0, 0));
+    }
+
+    #[test]
+    fn test_swap_v2_transfer_fee() {
+        let instruction = swap_v2(true, transfer(1000, Some((100, 5))), transfer(1001, Some((100, 1000))));
+        let events = TradeEvent::from_instruction(&instruction, &snapshot(&[])).unwrap();
+        assert_eq!(events.len(), 1);
+
+        let event = &events[0];
+        assert_eq!((event.amount_in, event.amount_out), (1000, 1001));
+        assert_eq!((event.transfer_fee_in, event.transfer_fee_out), (5, 11));
+        // ceil((1000 - 5) * 3000 / 1_000_000)
+        assert_eq!(event.fee_amount, 3);
+        assert_eq!(event.leg, TradeLeg::Single);
+    }
+
+    #[test]
+    fn test_variable_fee_rate() {
+        // ceil(1500 * (10_000 * 64)^2 / (100_000 * 10_000 * 10_000)) = ceil(61.44)
+        assert_eq!(variable_fee_rate(&oracle(WHIRLPOOL_ONE, 1500, 64, 10_000)), Some(62));
+        assert_eq!(variable_fee_rate(&oracle(WHIRLPOOL_ONE, 1500, 64, 0)), Some(0));
+        // clamped by the hard limit
+        assert_eq!(variable_fee_rate(&oracle(WHIRLPOOL_ONE, 100_000, 100, 10_000_000)), Some(FEE_RATE_HARD_LIMIT));
+        // not initialized
+        assert_eq!(variable_fee_rate(&[]), None);
+        assert_eq!(variable_fee_rate(&[0u8; 254]), None);
+    }
+
+    #[test]
+    fn test_swap_with_adaptive_fee() {
+        let instruction = swap(&pubkey(WHIRLPOOL_ONE), &pubkey(ORACLE_ONE), true, 1_000_000, 900_000);
+
+        let adaptive_fee = snapshot(&[(pubkey(ORACLE_ONE), oracle(WHIRLPOOL_ONE, 1500, 64, 10_000))]);
+        let event = &TradeEvent::from_instruction(&instruction, &adaptive_fee).unwrap()[0];
+        assert_eq!(event.fee_rate, 3000 + 62);
+        assert_eq!(event.fee_amount, 3062);
+
+        // static fee rate + variable fee rate is clamped by the hard limit
+        let high_volatility = snapshot(&[(pubkey(ORACLE_ONE), oracle(WHIRLPOOL_ONE, 100_000, 100, 10_000_000))]);
+        let event = &TradeEvent::from_instruction(&instruction, &high_volatility).unwrap()[0];
+        assert_eq!(event.fee_rate, FEE_RATE_HARD_LIMIT);
+        assert_eq!(event.fee_amount, 100_000);
+    }
+}
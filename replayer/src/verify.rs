@@ -0,0 +1,487 @@
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use replay_engine::account_data_store::AccountDataStore;
+use replay_engine::types::Slot;
+use serde::{
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    Deserializer,
+};
+use std::collections::HashSet;
+use std::fmt;
+use std::{fs::File, io::BufReader};
+
+use crate::diff;
+use crate::errors::ReplayError;
+use crate::schema::WhirlpoolStateAccount;
+
+/*
+
+State verification
+
+Compare the replayed state (slot, program data and AccountDataStore) with a reference whirlpool state file.
+The reference state file is streamed, so only the pubkeys of the reference accounts are kept in memory.
+
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateVerificationReport {
+    pub expected_slot: Slot,
+    pub actual_slot: Slot,
+    pub program_data_matched: bool,
+    pub matched_account_count: u64,
+    // exists in the reference state, but not in the replayed state
+    pub missing_pubkeys: Vec<String>,
+    // exists in the replayed state, but not in the reference state
+    pub extra_pubkeys: Vec<String>,
+    pub mismatched_accounts: Vec<AccountMismatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountMismatch {
+    pub pubkey: String,
+    pub account_type: String,
+    pub field_diffs: Vec<FieldDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl StateVerificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.expected_slot == self.actual_slot
+            && self.program_data_matched
+            && self.missing_pubkeys.is_empty()
+            && self.extra_pubkeys.is_empty()
+            && self.mismatched_accounts.is_empty()
+    }
+}
+
+impl fmt::Display for StateVerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "state verification: {}", if self.is_ok() { "OK" } else { "MISMATCH" })?;
+        writeln!(
+            f,
+            "  slot: expected={} (block_height={} block_time={}) actual={} (block_height={} block_time={})",
+            self.expected_slot.slot, self.expected_slot.block_height, self.expected_slot.block_time,
+            self.actual_slot.slot, self.actual_slot.block_height, self.actual_slot.block_time,
+        )?;
+        writeln!(f, "  program data: {}", if self.program_data_matched { "matched" } else { "mismatched" })?;
+        writeln!(f, "  matched accounts: {}", self.matched_account_count)?;
+        writeln!(f, "  missing accounts: {}", self.missing_pubkeys.len())?;
+        for pubkey in self.missing_pubkeys.iter() {
+            writeln!(f, "    {}", pubkey)?;
+        }
+        writeln!(f, "  extra accounts: {}", self.extra_pubkeys.len())?;
+        for pubkey in self.extra_pubkeys.iter() {
+            writeln!(f, "    {}", pubkey)?;
+        }
+        writeln!(f, "  mismatched accounts: {}", self.mismatched_accounts.len())?;
+        for mismatch in self.mismatched_accounts.iter() {
+            writeln!(f, "    {} ({})", mismatch.pubkey, mismatch.account_type)?;
+            for diff in mismatch.field_diffs.iter() {
+                writeln!(f, "      {}: expected={} actual={}", diff.field, diff.expected, diff.actual)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn verify_with_local_whirlpool_state_file(
+    file_path: &String,
+    slot: &Slot,
+    program_data: &Vec<u8>,
+    accounts: &AccountDataStore,
+) -> Result<StateVerificationReport, ReplayError> {
+    let file = File::open(file_path)?;
+    let decoder = crate::io::decompress(file)?;
+    let reader = BufReader::new(decoder);
+    verify_whirlpool_state_from_reader(reader, slot, program_data, accounts)
+}
+
+pub fn verify_with_remote_whirlpool_state_file(
+    url: &String,
+    slot: &Slot,
+    program_data: &Vec<u8>,
+    accounts: &AccountDataStore,
+) -> Result<StateVerificationReport, ReplayError> {
+    let response = reqwest::blocking::get(url)?.error_for_status()?;
+    let decoder = crate::io::decompress(response)?;
+    let reader = BufReader::new(decoder);
+    verify_whirlpool_state_from_reader(reader, slot, program_data, accounts)
+}
+
+pub fn verify_whirlpool_state_from_reader(
+    reader: impl std::io::Read,
+    slot: &Slot,
+    program_data: &Vec<u8>,
+    accounts: &AccountDataStore,
+) -> Result<StateVerificationReport, ReplayError> {
+    let verifier = StateVerifier { program_data, accounts };
+    let de = &mut serde_json::Deserializer::from_reader(reader);
+    let verified = verifier.deserialize(de)?;
+
+    // accounts only in the replayed state
+    let mut extra_pubkeys = vec![];
+    accounts
+        .traverse(|pubkey, _data| {
+            if !verified.accounts.seen_pubkeys.contains(pubkey) {
+                extra_pubkeys.push(pubkey.clone());
+            }
+            Ok(())
+        })
+        .map_err(|e| ReplayError::AccountDataStore(e.to_string()))?;
+
+    let mut missing_pubkeys = verified.accounts.missing_pubkeys;
+    let mut mismatched_accounts = verified.accounts.mismatched_accounts;
+    missing_pubkeys.sort();
+    extra_pubkeys.sort();
+    mismatched_accounts.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+
+    Ok(StateVerificationReport {
+        expected_slot: verified.slot,
+        actual_slot: *slot,
+        program_data_matched: verified.program_data_matched,
+        matched_account_count: verified.accounts.matched_account_count,
+        missing_pubkeys,
+        extra_pubkeys,
+        mismatched_accounts,
+    })
+}
+
+// ticks are named by tick index if the whirlpool is found by tick_spacing (see diff.rs)
+pub fn diff_account_data(pubkey: &String, expected: &[u8], actual: &[u8], tick_spacing: &diff::TickSpacingLookup) -> AccountMismatch {
+    let (account_type, field_changes) = match diff::diff_account(pubkey, Some(expected), Some(actual), tick_spacing) {
+        Some(account_diff) => (account_diff.account_type, account_diff.field_changes),
+        // empty data is treated as non-existent by diff_account, so compare the fields directly
+        // (undecodable accounts are compared by the base64 "data" field)
+        None => {
+            let (account_type, expected_fields) = diff::decode_fields(expected, tick_spacing);
+            let (_, actual_fields) = diff::decode_fields(actual, tick_spacing);
+            (account_type, diff::diff_fields(&expected_fields, &actual_fields))
+        }
+    };
+
+    let none = "(none)".to_string();
+    AccountMismatch {
+        pubkey: pubkey.clone(),
+        account_type,
+        field_diffs: field_changes
+            .into_iter()
+            .map(|change| FieldDiff {
+                field: change.field,
+                expected: change.old.unwrap_or(none.clone()),
+                actual: change.new.unwrap_or(none.clone()),
+            })
+            .collect(),
+    }
+}
+
+struct VerifiedState {
+    slot: Slot,
+    program_data_matched: bool,
+    accounts: VerifiedAccounts,
+}
+
+struct VerifiedAccounts {
+    seen_pubkeys: HashSet<String>,
+    matched_account_count: u64,
+    missing_pubkeys: Vec<String>,
+    mismatched_accounts: Vec<AccountMismatch>,
+}
+
+struct StateVerifier<'a> {
+    program_data: &'a Vec<u8>,
+    accounts: &'a AccountDataStore,
+}
+
+struct AccountsVerifier<'a> {
+    accounts: &'a AccountDataStore,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for StateVerifier<'a> {
+    type Value = VerifiedState;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        const FIELD_SLOT: &'static str = "slot";
+        const FIELD_BLOCK_HEIGHT: &'static str = "blockHeight";
+        const FIELD_BLOCK_TIME: &'static str = "blockTime";
+        const FIELD_PROGRAM_DATA: &'static str = "programData";
+        const FIELD_ACCOUNTS: &'static str = "accounts";
+
+        struct LocalVisitor<'a> {
+            program_data: &'a Vec<u8>,
+            accounts: &'a AccountDataStore,
+        }
+
+        impl<'de, 'a> Visitor<'de> for LocalVisitor<'a> {
+            type Value = VerifiedState;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct WhirlpoolState")
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<VerifiedState, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                let mut slot: Option<u64> = None;
+                let mut block_height: Option<u64> = None;
+                let mut block_time: Option<i64> = None;
+                let mut program_data_matched: Option<bool> = None;
+                let mut accounts: Option<VerifiedAccounts> = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        FIELD_SLOT => {
+                            if slot.is_some() {
+                                return Err(de::Error::duplicate_field(FIELD_SLOT));
+                            }
+                            slot = Some(map.next_value()?);
+                        }
+                        FIELD_BLOCK_HEIGHT => {
+                            if block_height.is_some() {
+                                return Err(de::Error::duplicate_field(FIELD_BLOCK_HEIGHT));
+                            }
+                            block_height = Some(map.next_value()?);
+                        }
+                        FIELD_BLOCK_TIME => {
+                            if block_time.is_some() {
+                                return Err(de::Error::duplicate_field(FIELD_BLOCK_TIME));
+                            }
+                            block_time = Some(map.next_value()?);
+                        }
+                        FIELD_PROGRAM_DATA => {
+                            if program_data_matched.is_some() {
+                                return Err(de::Error::duplicate_field(FIELD_PROGRAM_DATA));
+                            }
+                            let program_data_base64: String = map.next_value()?;
+                            let program_data = match BASE64_STANDARD.decode(program_data_base64).ok() {
+                                Some(data) => Ok(data),
+                                None => Err(de::Error::custom("expected base64 string")),
+                            }?;
+                            program_data_matched = Some(program_data == *self.program_data);
+                        }
+                        FIELD_ACCOUNTS => {
+                            if accounts.is_some() {
+                                return Err(de::Error::duplicate_field(FIELD_ACCOUNTS));
+                            }
+                            accounts = Some(map.next_value_seed(AccountsVerifier { accounts: self.accounts })?);
+                        }
+                        _ => {
+                            map.next_value::<serde::de::IgnoredAny>()?;
+                        }
+                    }
+                }
+                let slot = slot.ok_or_else(|| de::Error::missing_field(FIELD_SLOT))?;
+                let block_height = block_height.ok_or_else(|| de::Error::missing_field(FIELD_BLOCK_HEIGHT))?;
+                let block_time = block_time.ok_or_else(|| de::Error::missing_field(FIELD_BLOCK_TIME))?;
+                let program_data_matched = program_data_matched.ok_or_else(|| de::Error::missing_field(FIELD_PROGRAM_DATA))?;
+                let accounts = accounts.ok_or_else(|| de::Error::missing_field(FIELD_ACCOUNTS))?;
+                Ok(VerifiedState {
+                    slot: Slot::new(slot, block_height, block_time),
+                    program_data_matched,
+                    accounts,
+                })
+            }
+        }
+
+        const FIELDS: &'static [&'static str] = &[
+            FIELD_SLOT,
+            FIELD_BLOCK_HEIGHT,
+            FIELD_BLOCK_TIME,
+            FIELD_PROGRAM_DATA,
+            FIELD_ACCOUNTS,
+        ];
+        deserializer.deserialize_struct("WhirlpoolState", FIELDS, LocalVisitor {
+            program_data: self.program_data,
+            accounts: self.accounts,
+        })
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for AccountsVerifier<'a> {
+    type Value = VerifiedAccounts;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct LocalVisitor<'a> {
+            accounts: &'a AccountDataStore,
+        }
+
+        impl<'de, 'a> Visitor<'de> for LocalVisitor<'a> {
+            type Value = VerifiedAccounts;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct AccountDataStore")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<VerifiedAccounts, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let mut verified = VerifiedAccounts {
+                    seen_pubkeys: HashSet::new(),
+                    matched_account_count: 0,
+                    missing_pubkeys: vec![],
+                    mismatched_accounts: vec![],
+                };
+                // tick arrays are named with the tick spacing of the replayed whirlpools
+                let tick_spacing = diff::store_tick_spacing(self.accounts);
+                while let Some(value) = seq.next_element()? {
+                    let account: WhirlpoolStateAccount = value;
+                    let replayed = self.accounts.get(&account.pubkey).map_err(de::Error::custom)?;
+                    match replayed {
+                        None => verified.missing_pubkeys.push(account.pubkey.clone()),
+                        Some(data) if data == account.data => verified.matched_account_count += 1,
+                        Some(data) => verified.mismatched_accounts.push(diff_account_data(&account.pubkey, &account.data, &data, &tick_spacing)),
+                    }
+                    verified.seen_pubkeys.insert(account.pubkey);
+                }
+                Ok(verified)
+            }
+        }
+
+        deserializer.deserialize_seq(LocalVisitor { accounts: self.accounts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{pubkey, whirlpool_data, WhirlpoolParams};
+
+    const PROGRAM_DATA: [u8; 4] = [1, 2, 3, 4];
+
+    fn whirlpool(liquidity: u128) -> Vec<u8> {
+        whirlpool_data(&WhirlpoolParams {
+            token_mint_a: 10,
+            token_mint_b: 11,
+            fee_rate: 3000,
+            sqrt_price: 1 << 64,
+            tick_current_index: 0,
+            liquidity,
+        })
+    }
+
+    // reference state file (uncompressed JSON)
+    fn reference_state(slot: u64, program_data: &[u8], accounts: &[(String, Vec<u8>)]) -> String {
+        let accounts = accounts
+            .iter()
+            .map(|(pubkey, data)| format!("{{\"pubkey\":\"{}\",\"data\":\"{}\"}}", pubkey, BASE64_STANDARD.encode(data)))
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            "{{\"slot\":{},\"blockHeight\":{},\"blockTime\":1700000000,\"accounts\":[{}],\"programData\":\"{}\"}}",
+            slot,
+            slot - 10,
+            accounts,
+            BASE64_STANDARD.encode(program_data),
+        )
+    }
+
+    fn store(accounts: &[(String, Vec<u8>)]) -> AccountDataStore {
+        let mut store = AccountDataStore::new_on_memory();
+        for (pubkey, data) in accounts.iter() {
+            store.upsert(pubkey, data).unwrap();
+        }
+        store
+    }
+
+    fn verify(reference: &String, slot: u64, accounts: &[(String, Vec<u8>)]) -> StateVerificationReport {
+        verify_whirlpool_state_from_reader(
+            reference.as_bytes(),
+            &Slot::new(slot, slot - 10, 1700000000),
+            &PROGRAM_DATA.to_vec(),
+            &store(accounts),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_all_matched() {
+        let accounts = vec![(pubkey(1), whirlpool(100)), (pubkey(2), vec![1, 2, 3])];
+        let report = verify(&reference_state(100, &PROGRAM_DATA, &accounts), 100, &accounts);
+
+        assert!(report.is_ok());
+        assert_eq!(report.expected_slot, report.actual_slot);
+        assert!(report.program_data_matched);
+        assert_eq!(report.matched_account_count, 2);
+    }
+
+    #[test]
+    fn test_missing_and_extra_accounts() {
+        let reference = reference_state(100, &PROGRAM_DATA, &[(pubkey(1), vec![1]), (pubkey(3), vec![3]), (pubkey(2), vec![2])]);
+        let report = verify(&reference, 100, &[(pubkey(1), vec![1]), (pubkey(5), vec![5]), (pubkey(4), vec![4])]);
+
+        assert!(!report.is_ok());
+        assert_eq!(report.matched_account_count, 1);
+        // sorted by pubkey
+        let mut missing = vec![pubkey(2), pubkey(3)];
+        missing.sort();
+        let mut extra = vec![pubkey(4), pubkey(5)];
+        extra.sort();
+        assert_eq!(report.missing_pubkeys, missing);
+        assert_eq!(report.extra_pubkeys, extra);
+        assert!(report.mismatched_accounts.is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_account() {
+        let reference = reference_state(100, &PROGRAM_DATA, &[(pubkey(1), whirlpool(100)), (pubkey(2), vec![1, 2])]);
+        let report = verify(&reference, 100, &[(pubkey(1), whirlpool(200)), (pubkey(2), vec![1, 3])]);
+
+        assert!(!report.is_ok());
+        assert_eq!(report.matched_account_count, 0);
+        let mut expected = vec![
+            AccountMismatch {
+                pubkey: pubkey(1),
+                account_type: "Whirlpool".to_string(),
+                field_diffs: vec![FieldDiff {
+                    field: "liquidity".to_string(),
+                    expected: "100".to_string(),
+                    actual: "200".to_string(),
+                }],
+            },
+            // undecodable accounts are compared as raw data
+            AccountMismatch {
+                pubkey: pubkey(2),
+                account_type: "Unknown".to_string(),
+                field_diffs: vec![FieldDiff {
+                    field: "data".to_string(),
+                    expected: BASE64_STANDARD.encode([1, 2]),
+                    actual: BASE64_STANDARD.encode([1, 3]),
+                }],
+            },
+        ];
+        expected.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+        assert_eq!(report.mismatched_accounts, expected);
+    }
+
+    #[test]
+    fn test_slot_mismatch() {
+        let accounts = vec![(pubkey(1), vec![1])];
+        let report = verify(&reference_state(100, &PROGRAM_DATA, &accounts), 99, &accounts);
+
+        assert!(!report.is_ok());
+        assert_eq!(report.expected_slot, Slot::new(100, 90, 1700000000));
+        assert_eq!(report.actual_slot, Slot::new(99, 89, 1700000000));
+        assert_eq!(report.matched_account_count, 1);
+    }
+
+    #[test]
+    fn test_program_data_mismatch() {
+        let accounts = vec![(pubkey(1), vec![1])];
+        let report = verify(&reference_state(100, &[1, 2, 3], &accounts), 100, &accounts);
+
+        assert!(!report.is_ok());
+        assert!(!report.program_data_matched);
+        assert_eq!(report.matched_account_count, 1);
+    }
+}
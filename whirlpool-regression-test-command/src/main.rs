@@ -1,9 +1,10 @@
+use std::collections::BTreeSet;
 use std::vec;
 use clap::Parser;
 
 use replay_engine::{decoded_instructions, replay_engine::ReplayEngine, types::{ProgramData, WritableAccountSnapshot}};
 use whirlpool_replayer::{
-    io, schema::{WhirlpoolTransaction}, serde::{self, AccountDataStoreConfig}, Slot,
+    diff, io, schema::{WhirlpoolTransaction}, serde::{self, AccountDataStoreConfig}, ReplayError, Slot,
 };
 
 #[derive(Parser, Debug)]
@@ -70,18 +71,29 @@ fn main() {
             assert_eq!(name_left, name_right);
             assert_eq!(payload_left, payload_right);
 
-            // compare snapshots
-            let snapshot_left = snapshot_left.post_snapshot.clone();
-            let snapshot_right = snapshot_right.post_snapshot.clone();
-            assert_eq!(snapshot_left.len(), snapshot_right.len());
-            for (key_left, account_left) in snapshot_left.iter() {
-                let account_right = snapshot_right.get(key_left).unwrap();
-                if account_left != account_right {
-                    println!("Account mismatch: slot={}, signature={}, name={}, payload={}", slot_left, signature_left, name_left, payload_left);
-                    println!("Left: {} => {:?}", key_left, account_left);
-                    println!("Right: {} => {:?}", key_left, account_right);
-                    panic!("Fatal: Account mismatch");
-                }
+            // compare snapshots (left as old, right as new)
+            let snapshot_left = &snapshot_left.post_snapshot;
+            let snapshot_right = &snapshot_right.post_snapshot;
+            let tick_spacing = |whirlpool: &String| {
+                snapshot_right.get(whirlpool)
+                    .or_else(|| snapshot_left.get(whirlpool))
+                    .and_then(|data| diff::whirlpool_tick_spacing(data))
+            };
+            let pubkeys: BTreeSet<&String> = snapshot_left.keys().chain(snapshot_right.keys()).collect();
+            let account_diffs: Vec<diff::AccountDiff> = pubkeys
+                .into_iter()
+                .filter_map(|pubkey| diff::diff_account(
+                    pubkey,
+                    snapshot_left.get(pubkey).map(|data| data.as_slice()),
+                    snapshot_right.get(pubkey).map(|data| data.as_slice()),
+                    &tick_spacing,
+                ))
+                .collect();
+            if !account_diffs.is_empty() {
+                println!("Account mismatch: slot={}, signature={}, name={}, payload={}", slot_left, signature_left, name_left, payload_left);
+                println!("Left -> Right:");
+                print!("{}", diff::format_account_diffs(&account_diffs));
+                panic!("Fatal: Account mismatch");
             }
 
             println!("ok: slot={}, signature={}, name={}", slot_left, signature_left, name_left);
@@ -93,7 +105,7 @@ fn main() {
 
 pub struct WhirlpoolReplayerStep {
     replay_engine: ReplayEngine,
-    transaction_iter: Box<dyn Iterator<Item = WhirlpoolTransaction> + Send>,
+    transaction_iter: Box<dyn Iterator<Item = Result<WhirlpoolTransaction, ReplayError>> + Send>,
 }
 
 impl WhirlpoolReplayerStep {
@@ -113,9 +125,9 @@ impl WhirlpoolReplayerStep {
             io::get_whirlpool_transaction_file_relative_path(&current);
         let transaction_file_path = format!("{}/{}", base_path, transaction_file_relative_path);
 
-        let state = io::load_from_local_whirlpool_state_file(&state_file_path, account_data_store_config);
+        let state = io::load_from_local_whirlpool_state_file(&state_file_path, account_data_store_config).unwrap();
         let transaction_iter =
-            io::load_from_local_whirlpool_transaction_file(&transaction_file_path);
+            io::load_from_local_whirlpool_transaction_file(&transaction_file_path).unwrap();
 
         let replay_engine = ReplayEngine::new(
             Slot::new(state.slot, state.block_height, state.block_time),
@@ -141,7 +153,7 @@ impl WhirlpoolReplayerStep {
             return None;
         }
 
-        let whirlpool_transaction = next_whirlpool_transaction.unwrap();
+        let whirlpool_transaction = next_whirlpool_transaction.unwrap().unwrap();
 
         let slot = Slot {
             slot: whirlpool_transaction.slot,
@@ -1,29 +1,51 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
 
-use clap::Parser;
+use clap::{Args as ClapArgs, Parser, Subcommand};
 use itertools::Itertools;
 
 use whirlpool_replayer::{
+    diff,
+    event,
     io,
+    ohlcv,
+    parallel,
+    reference_snapshot,
+    repro,
     schema,
     serde,
+    state_diff,
+    verify,
+    parse_date,
+    WhirlpoolFilter,
     WhirlpoolReplayer,
+    ReplayError,
     ReplayUntil,
+    Slot,
     SyncInstructionCallback,
-    SyncSlotCallback
+    SyncSlotCallback,
+    TokenTransferMismatchReport,
 };
 
 use anchor_lang::prelude::*;
 use whirlpool_base::state::Whirlpool;
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     #[clap(long, id = "directory")]
     cache_dir: Option<String>,
 
     #[clap(short, long, id = "filename")]
     save_as: Option<String>,
 
+    // save the state with accounts sorted by pubkey (the same state is always saved into the same bytes)
+    #[clap(long, requires = "filename")]
+    canonical: bool,
+
     #[clap(long, id = "slot")]
     stop_slot: Option<u64>,
 
@@ -33,21 +55,173 @@ struct Args {
     #[clap(long, id = "blockTime")]
     stop_block_time: Option<i64>,
 
+    #[clap(long, id = "stop yyyymmdd")]
+    stop_date: Option<String>,
+
+    #[clap(long, id = "to yyyymmdd")]
+    to: Option<String>,
+
+    // stream the transactions after the state of the previous day from the database (requires mysql feature)
+    // program upgrades are not in the database, so the program data of the state is used for all slots
+    #[clap(long, id = "database url", conflicts_with_all = ["to yyyymmdd", "threads", "checkpoint filename"])]
+    database: Option<String>,
+
+    // replay each day from yyyymmdd to --to independently on N threads
+    #[clap(long, id = "threads", requires = "to yyyymmdd", conflicts_with_all = ["filename", "checkpoint filename", "whirlpool pubkey"])]
+    parallel: Option<usize>,
+
     #[clap(short, long, id = "memory")]
     memory: bool,
 
+    // compare the replayed state with the published state file of the last replayed day
+    // (not with --whirlpool: the filtered replay doesn't have the accounts of the other whirlpools)
+    #[clap(long, conflicts_with = "whirlpool pubkey")]
+    verify: bool,
+
+    // check token transfer amounts of each instruction against the recorded amounts
+    #[clap(long)]
+    strict: bool,
+
+    // replay only the instructions that touch the whirlpool (can be repeated)
+    #[clap(long, id = "whirlpool pubkey")]
+    whirlpool: Vec<String>,
+
+    // save a checkpoint to the file on Ctrl-C (and every --checkpoint-interval slots)
+    #[clap(long, id = "checkpoint filename")]
+    checkpoint: Option<String>,
+
+    #[clap(long, id = "slots")]
+    checkpoint_interval: Option<u64>,
+
+    // resume the replay from the checkpoint file if it exists
+    #[clap(long, requires = "checkpoint filename")]
+    resume: bool,
+
+    // write the replayed instructions with pre/post account state and program events into the event file (.jsonl.gz or .jsonl.zst)
+    // (enables the execution recording)
+    #[clap(long, id = "event filename", conflicts_with = "threads")]
+    event: Option<String>,
+
+    // record program logs and compute units of each instruction (slower)
+    #[clap(long, conflicts_with = "threads")]
+    record_execution: bool,
+
+    // write a repro bundle into the directory when an instruction fails (replay it with whirlpool-repro)
+    #[clap(long, id = "repro directory", conflicts_with = "threads")]
+    repro_dir: Option<String>,
+
+    // build OHLCV candles from the replayed swaps and save them under the directory
+    #[clap(long, id = "ohlcv directory", conflicts_with = "threads")]
+    ohlcv: Option<String>,
+
+    #[clap(id = "path|url", required = true)]
+    storage: Option<String>,
+
+    #[clap(id = "yyyymmdd", required = true)]
+    yyyymmdd: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // replay a single instruction on the state and print the decoded pre/post diff
+    Instruction(InstructionArgs),
+    // compare the accounts of two states (state files, checkpoint files or snapshot CSV files)
+    Diff(DiffArgs),
+    // replay until the slot of each snapshot CSV file (data/reference_snapshot) and compare the state with it
+    VerifySnapshot(VerifySnapshotArgs),
+}
+
+#[derive(ClapArgs, Debug)]
+struct InstructionArgs {
+    // state file (a full state file or a file with a subset of its accounts in the same format)
+    #[clap(long, id = "state filename")]
+    state: String,
+
+    // program .so file (the program data in the state file is used if omitted)
+    #[clap(long, id = "program filename")]
+    program: Option<String>,
+
+    // slot, blockHeight and blockTime of the state file are used if omitted
+    #[clap(long)]
+    slot: Option<u64>,
+
+    #[clap(long)]
+    block_height: Option<u64>,
+
+    #[clap(long)]
+    block_time: Option<i64>,
+
+    // instruction name (e.g. swap)
+    #[clap(id = "name")]
+    name: String,
+
+    // instruction payload in the transaction file format (JSON)
+    #[clap(id = "json")]
+    payload: String,
+}
+
+#[derive(ClapArgs, Debug)]
+struct DiffArgs {
+    // print the summary and the differences as a JSON document
+    #[clap(long)]
+    json: bool,
+
+    // print only the summary (counts by account type)
+    #[clap(long)]
+    summary_only: bool,
+
+    #[clap(short, long)]
+    memory: bool,
+
+    // state file, checkpoint file or snapshot CSV file (.csv.gz, .csv.zst)
+    #[clap(id = "left")]
+    left: String,
+
+    #[clap(id = "right")]
+    right: String,
+}
+
+#[derive(ClapArgs, Debug)]
+struct VerifySnapshotArgs {
+    #[clap(long, id = "directory")]
+    cache_dir: Option<String>,
+
+    #[clap(short, long)]
+    memory: bool,
+
+    // the day to replay (the day containing the snapshot slot is searched with the state files if omitted)
+    #[clap(long, id = "yyyymmdd")]
+    date: Option<String>,
+
     #[clap(id = "path|url")]
     storage: String,
 
-    #[clap(id = "yyyymmdd")]
-    yyyymmdd: String,
+    // whirlpool-snapshot-<slot>.csv.gz (compact or full-columns)
+    #[clap(id = "snapshot", required = true)]
+    snapshots: Vec<String>,
 }
 
 fn main() {
     let args = Args::parse();
 
-    let base_path_or_url: String = args.storage;
-    let yyyymmdd: String = args.yyyymmdd;
+    match args.command {
+        Some(Command::Instruction(instruction_args)) => {
+            replay_single_instruction(&instruction_args);
+            return;
+        }
+        Some(Command::Diff(diff_args)) => {
+            diff_states(&diff_args);
+            return;
+        }
+        Some(Command::VerifySnapshot(verify_snapshot_args)) => {
+            verify_reference_snapshots(&verify_snapshot_args);
+            return;
+        }
+        None => {}
+    }
+
+    let base_path_or_url: String = args.storage.unwrap();
+    let yyyymmdd: String = args.yyyymmdd.unwrap();
 
     let account_data_store_config = if args.memory {
         // account data will be stored on memory
@@ -64,27 +238,97 @@ fn main() {
         ReplayUntil::BlockHeight(args.stop_block_height.unwrap())
     } else if args.stop_block_time.is_some() {
         ReplayUntil::BlockTime(args.stop_block_time.unwrap())
+    } else if args.stop_date.is_some() {
+        let stop_date = unwrap_or_exit(parse_date(&args.stop_date.unwrap()));
+        ReplayUntil::Date(stop_date)
     } else {
         ReplayUntil::End
     };
 
+    // the state file of the last day is the expected result of the replay
+    let last_yyyymmdd = args.to.clone().unwrap_or(yyyymmdd.clone());
+
+    if let Some(threads) = args.parallel {
+        let storage = Storage {
+            base_path_or_url: base_path_or_url.clone(),
+            cache_dir: args.cache_dir.clone(),
+            account_data_store_config: account_data_store_config.clone(),
+        };
+        replay_days_in_parallel(&storage, &yyyymmdd, &last_yyyymmdd, threads, &until_condition, args.strict, args.verify);
+        return;
+    }
+
+    let resume_checkpoint = args.checkpoint.clone()
+        .filter(|checkpoint| args.resume && std::path::Path::new(checkpoint).exists());
+
+    // replay only the instructions for the whirlpools
+    // (the *_with_filter builders expand the filter by two-hop swaps)
+    let mut filter = if args.whirlpool.is_empty() {
+        None
+    } else {
+        Some(WhirlpoolFilter::new(args.whirlpool.clone()))
+    };
+
     // build replayer
-    let mut replayer = if base_path_or_url.starts_with("https://") {
-        if args.cache_dir.is_some() {
-            let cache_dir = args.cache_dir.unwrap();
-            WhirlpoolReplayer::build_with_remote_file_storage_with_local_cache(
-                &base_path_or_url,
-                &yyyymmdd,
-                &account_data_store_config,
-                &cache_dir,
-                false,
-            )
+    let mut replayer = unwrap_or_exit(if let Some(checkpoint) = resume_checkpoint {
+        // resume the replay from yyyymmdd to last_yyyymmdd
+        println!("resume from checkpoint: {}", checkpoint);
+        if base_path_or_url.starts_with("https://") {
+            if args.cache_dir.is_some() {
+                let cache_dir = args.cache_dir.clone().unwrap();
+                WhirlpoolReplayer::build_with_remote_file_storage_with_local_cache_from_checkpoint(
+                    &base_path_or_url,
+                    &yyyymmdd,
+                    &last_yyyymmdd,
+                    &checkpoint,
+                    &account_data_store_config,
+                    &cache_dir,
+                    false,
+                )
+            } else {
+                WhirlpoolReplayer::build_with_remote_file_storage_from_checkpoint(&base_path_or_url, &yyyymmdd, &last_yyyymmdd, &checkpoint, &account_data_store_config)
+            }
         } else {
-            WhirlpoolReplayer::build_with_remote_file_storage(&base_path_or_url, &yyyymmdd, &account_data_store_config)
+            WhirlpoolReplayer::build_with_local_file_storage_from_checkpoint(&base_path_or_url, &yyyymmdd, &last_yyyymmdd, &checkpoint, &account_data_store_config)
         }
+    } else if let Some(to_yyyymmdd) = args.to {
+        // replay from yyyymmdd to to_yyyymmdd continuously
+        build_date_range_replayer(&base_path_or_url, &args.cache_dir, &yyyymmdd, &to_yyyymmdd, &account_data_store_config, filter.take())
+    } else if let Some(database_url) = args.database.as_ref() {
+        build_database_replayer(database_url, &base_path_or_url, &yyyymmdd, &account_data_store_config)
     } else {
-        WhirlpoolReplayer::build_with_local_file_storage(&base_path_or_url, &yyyymmdd, &account_data_store_config)
-    };
+        build_filtered_day_replayer(&base_path_or_url, &args.cache_dir, &yyyymmdd, &account_data_store_config, filter.take())
+    });
+
+    // the state of the checkpoint and the database replayer is loaded without the filter (expanded and pruned here)
+    if let Some(mut filter) = filter {
+        // the pools connected through two-hop swaps are needed, too
+        unwrap_or_exit(expand_filter_by_two_hop_swaps(&base_path_or_url, &args.cache_dir, &yyyymmdd, &last_yyyymmdd, &mut filter));
+        unwrap_or_exit(replayer.set_whirlpool_filter(filter));
+    }
+    if let Some(filter) = replayer.get_whirlpool_filter() {
+        println!("replay only instructions for whirlpools: {}", filter.get_whirlpools().iter().sorted().join(", "));
+    }
+
+    replayer.set_token_transfer_verification(args.strict);
+    // program events in the event file are decoded from the execution recording
+    replayer.set_execution_recording(args.record_execution || args.event.is_some());
+    if let Some(repro_dir) = args.repro_dir.as_ref() {
+        replayer.set_repro_bundle_dir(repro_dir);
+    }
+
+    if let Some(checkpoint) = args.checkpoint.as_ref() {
+        replayer.set_checkpoint_autosave(checkpoint, args.checkpoint_interval.unwrap_or(0));
+
+        // replay stops at the end of the current slot on Ctrl-C
+        let interrupted = Arc::new(AtomicBool::new(false));
+        replayer.set_interrupt_flag(Arc::clone(&interrupted));
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(tokio::signal::ctrl_c()).unwrap();
+            interrupted.store(true, Ordering::SeqCst);
+        });
+    }
 
     // define callbacks
     let slot_pre_callback: SyncSlotCallback = Rc::new(|slot, _accounts| {
@@ -97,9 +341,30 @@ fn main() {
     let instruction_counter = Rc::new(RefCell::new(HashMap::<String, u64>::new()));
     let instruction_counter_clone = Rc::clone(&instruction_counter);
 
+    // decimals are taken from the token file of the last day
+    let ohlcv_builder = args.ohlcv.as_ref().map(|_| {
+        let tokens = unwrap_or_exit(load_whirlpool_token(&base_path_or_url, &args.cache_dir, &last_yyyymmdd));
+        Rc::new(RefCell::new(ohlcv::OhlcvBuilder::new(&tokens)))
+    });
+    let ohlcv_callback = ohlcv_builder.as_ref().map(ohlcv::OhlcvBuilder::instruction_callback);
+
+    let event_writer = args.event.as_ref().map(|event_file| {
+        Rc::new(RefCell::new(unwrap_or_exit(event::WhirlpoolEventWriter::create(event_file))))
+    });
+    let event_callback = event_writer.as_ref().map(event::WhirlpoolEventWriter::instruction_callback);
+
     let instruction_callback: SyncInstructionCallback = Rc::new(
-        move |_slot, transaction, name, instruction, accounts, snapshot| {
-            println!("  replayed instruction: {}", name);
+        move |slot, transaction, name, instruction, accounts, snapshot, execution_record| {
+            match execution_record {
+                Some(execution_record) => {
+                    println!("  replayed instruction: {} ({} CU)", name, execution_record.compute_units_consumed);
+                    // events emitted by the program
+                    for event in execution_record.events.iter() {
+                        println!("    event: {:?}", event);
+                    }
+                }
+                None => println!("  replayed instruction: {}", name),
+            }
 
             // callback will receive various data to implement various data processing!
             // For example, print the details of swap instruction with pre/post writable account state info.
@@ -129,15 +394,53 @@ fn main() {
             let mut counter = instruction_counter_clone.borrow_mut();
             let count = counter.entry(name.clone()).or_insert(0u64);
             *count += 1;
+
+            if let Some(ohlcv_callback) = ohlcv_callback.as_ref() {
+                ohlcv_callback(slot, transaction, name, instruction, accounts, snapshot, execution_record);
+            }
+            if let Some(event_callback) = event_callback.as_ref() {
+                event_callback(slot, transaction, name, instruction, accounts, snapshot, execution_record);
+            }
         },
     );
 
-    replayer.replay(
+    let replayed = replayer.replay(
         until_condition,
         Some(instruction_callback),
         Some(slot_pre_callback),
         None // no slot_post_callback
     );
+    // program logs of the failed instruction
+    if let (Err(_), Some(execution_record)) = (&replayed, replayer.get_last_execution_record()) {
+        eprintln!("\n\nprogram logs of the last instruction:");
+        for log_message in execution_record.log_messages.iter() {
+            eprintln!("  {}", log_message);
+        }
+    }
+    if let (Err(_), Some(repro_bundle)) = (&replayed, replayer.get_last_repro_bundle()) {
+        eprintln!("\n\nrepro bundle: {}", repro_bundle);
+    }
+    unwrap_or_exit(replayed);
+
+    // token transfer mismatches found in strict mode (the replay goes on)
+    let token_transfer_mismatches = replayer.take_token_transfer_mismatches();
+    for mismatch in token_transfer_mismatches.iter() {
+        eprintln!("token transfer mismatch: {}", mismatch);
+    }
+
+    // finish event file (the callback holding the other reference has been dropped)
+    if let Some(event_writer) = event_writer {
+        let event_writer = Rc::try_unwrap(event_writer).ok().expect("event writer is still referenced");
+        unwrap_or_exit(event_writer.into_inner().finish());
+    }
+
+    // save checkpoint and exit if interrupted
+    if replayer.is_interrupted() {
+        let checkpoint = args.checkpoint.unwrap();
+        unwrap_or_exit(replayer.save_checkpoint(&checkpoint));
+        println!("\n\ninterrupted at slot {}, checkpoint saved: {}", replayer.get_slot().slot, checkpoint);
+        std::process::exit(130);
+    }
 
     // show instruction count
     println!("\n\nReplayed instructions\n");
@@ -152,11 +455,500 @@ fn main() {
         let latest_slot = replayer.get_slot();
         let latest_program_data = replayer.get_program_data();
         let latest_accounts = replayer.get_accounts();
-        io::save_to_whirlpool_state_file(
+        let save_to_whirlpool_state_file = if args.canonical {
+            io::save_to_whirlpool_state_file_canonical
+        } else {
+            io::save_to_whirlpool_state_file
+        };
+        unwrap_or_exit(save_to_whirlpool_state_file(
             &state_file.to_string(),
             latest_slot,
             latest_program_data,
             latest_accounts,
-        );
+        ));
+    }
+
+    // save OHLCV
+    if let Some(ohlcv_dir) = args.ohlcv.as_ref() {
+        unwrap_or_exit(ohlcv_builder.unwrap().borrow().save(ohlcv_dir));
+        println!("\n\nOHLCV saved: {}", ohlcv_dir);
+    }
+
+    // verify state
+    let mut failed = false;
+    if args.verify {
+        let report = unwrap_or_exit(verify_replayed_state(&base_path_or_url, &args.cache_dir, &last_yyyymmdd, &replayer));
+
+        println!("\n\n{}", report);
+        failed |= !report.is_ok();
+    }
+
+    if !token_transfer_mismatches.is_empty() {
+        eprintln!("\n\n{} instructions with token transfer mismatch", token_transfer_mismatches.len());
+        failed = true;
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn build_day_replayer(
+    base_path_or_url: &String,
+    cache_dir: &Option<String>,
+    yyyymmdd: &String,
+    account_data_store_config: &serde::AccountDataStoreConfig,
+) -> Result<WhirlpoolReplayer, ReplayError> {
+    if base_path_or_url.starts_with("https://") {
+        if let Some(cache_dir) = cache_dir {
+            WhirlpoolReplayer::build_with_remote_file_storage_with_local_cache(
+                base_path_or_url,
+                yyyymmdd,
+                account_data_store_config,
+                cache_dir,
+                false,
+            )
+        } else {
+            WhirlpoolReplayer::build_with_remote_file_storage(base_path_or_url, yyyymmdd, account_data_store_config)
+        }
+    } else {
+        WhirlpoolReplayer::build_with_local_file_storage(base_path_or_url, yyyymmdd, account_data_store_config)
+    }
+}
+
+// only the accounts needed by the filter are loaded
+fn build_filtered_day_replayer(
+    base_path_or_url: &String,
+    cache_dir: &Option<String>,
+    yyyymmdd: &String,
+    account_data_store_config: &serde::AccountDataStoreConfig,
+    filter: Option<WhirlpoolFilter>,
+) -> Result<WhirlpoolReplayer, ReplayError> {
+    let filter = match filter {
+        Some(filter) => filter,
+        None => return build_day_replayer(base_path_or_url, cache_dir, yyyymmdd, account_data_store_config),
+    };
+
+    if base_path_or_url.starts_with("https://") {
+        if let Some(cache_dir) = cache_dir {
+            WhirlpoolReplayer::build_with_remote_file_storage_with_local_cache_with_filter(
+                base_path_or_url,
+                yyyymmdd,
+                account_data_store_config,
+                cache_dir,
+                false,
+                filter,
+            )
+        } else {
+            WhirlpoolReplayer::build_with_remote_file_storage_with_filter(base_path_or_url, yyyymmdd, account_data_store_config, filter)
+        }
+    } else {
+        WhirlpoolReplayer::build_with_local_file_storage_with_filter(base_path_or_url, yyyymmdd, account_data_store_config, filter)
+    }
+}
+
+fn build_date_range_replayer(
+    base_path_or_url: &String,
+    cache_dir: &Option<String>,
+    from_yyyymmdd: &String,
+    to_yyyymmdd: &String,
+    account_data_store_config: &serde::AccountDataStoreConfig,
+    filter: Option<WhirlpoolFilter>,
+) -> Result<WhirlpoolReplayer, ReplayError> {
+    if base_path_or_url.starts_with("https://") {
+        match (cache_dir, filter) {
+            (Some(cache_dir), Some(filter)) => WhirlpoolReplayer::build_with_remote_file_storage_with_local_cache_with_date_range_with_filter(
+                base_path_or_url,
+                from_yyyymmdd,
+                to_yyyymmdd,
+                account_data_store_config,
+                cache_dir,
+                false,
+                filter,
+            ),
+            (Some(cache_dir), None) => WhirlpoolReplayer::build_with_remote_file_storage_with_local_cache_with_date_range(
+                base_path_or_url,
+                from_yyyymmdd,
+                to_yyyymmdd,
+                account_data_store_config,
+                cache_dir,
+                false,
+            ),
+            (None, Some(filter)) => WhirlpoolReplayer::build_with_remote_file_storage_with_date_range_with_filter(base_path_or_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config, filter),
+            (None, None) => WhirlpoolReplayer::build_with_remote_file_storage_with_date_range(base_path_or_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config),
+        }
+    } else {
+        match filter {
+            Some(filter) => WhirlpoolReplayer::build_with_local_file_storage_with_date_range_with_filter(base_path_or_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config, filter),
+            None => WhirlpoolReplayer::build_with_local_file_storage_with_date_range(base_path_or_url, from_yyyymmdd, to_yyyymmdd, account_data_store_config),
+        }
+    }
+}
+
+// the state file of the previous day is taken from the local storage
+#[cfg(feature = "mysql")]
+fn build_database_replayer(
+    database_url: &String,
+    base_path: &String,
+    yyyymmdd: &String,
+    account_data_store_config: &serde::AccountDataStoreConfig,
+) -> Result<WhirlpoolReplayer, ReplayError> {
+    let date = parse_date(yyyymmdd)?;
+    let previous = date.pred_opt().ok_or_else(|| ReplayError::InvalidDate(format!("no previous day: {}", date)))?;
+    let state_file_path = io::local_file_path(base_path, &io::get_whirlpool_state_file_relative_paths(&previous));
+    WhirlpoolReplayer::build_with_mysql_database(database_url, &state_file_path, account_data_store_config)
+}
+
+#[cfg(not(feature = "mysql"))]
+fn build_database_replayer(
+    _database_url: &String,
+    _base_path: &String,
+    _yyyymmdd: &String,
+    _account_data_store_config: &serde::AccountDataStoreConfig,
+) -> Result<WhirlpoolReplayer, ReplayError> {
+    Err(ReplayError::Database("whirlpool-replay is built without mysql feature (cargo build --features mysql)".to_string()))
+}
+
+fn load_whirlpool_token(
+    base_path_or_url: &String,
+    cache_dir: &Option<String>,
+    yyyymmdd: &String,
+) -> Result<schema::WhirlpoolToken, ReplayError> {
+    let date = parse_date(yyyymmdd)?;
+    let token_file_relative_paths = io::get_whirlpool_token_file_relative_paths(&date);
+
+    if base_path_or_url.starts_with("https://") {
+        if let Some(cache_dir) = cache_dir {
+            let token_file_path = io::cached_file_path(base_path_or_url, cache_dir, &token_file_relative_paths, false)?;
+            io::load_from_local_whirlpool_token_file(&token_file_path)
+        } else {
+            let token_file_url = io::remote_file_url(base_path_or_url, &token_file_relative_paths)?;
+            io::load_from_remote_whirlpool_token_file(&token_file_url)
+        }
+    } else {
+        let token_file_path = io::local_file_path(base_path_or_url, &token_file_relative_paths);
+        io::load_from_local_whirlpool_token_file(&token_file_path)
+    }
+}
+
+// compare the replayed state with the published state file of the day
+fn verify_replayed_state(
+    base_path_or_url: &String,
+    cache_dir: &Option<String>,
+    yyyymmdd: &String,
+    replayer: &WhirlpoolReplayer,
+) -> Result<verify::StateVerificationReport, ReplayError> {
+    let date = parse_date(yyyymmdd)?;
+    let state_file_relative_paths = io::get_whirlpool_state_file_relative_paths(&date);
+
+    if base_path_or_url.starts_with("https://") {
+        if let Some(cache_dir) = cache_dir {
+            let state_file_path = io::cached_file_path(base_path_or_url, cache_dir, &state_file_relative_paths, false)?;
+            verify::verify_with_local_whirlpool_state_file(
+                &state_file_path,
+                replayer.get_slot(),
+                replayer.get_program_data(),
+                replayer.get_accounts(),
+            )
+        } else {
+            let state_file_url = io::remote_file_url(base_path_or_url, &state_file_relative_paths)?;
+            verify::verify_with_remote_whirlpool_state_file(
+                &state_file_url,
+                replayer.get_slot(),
+                replayer.get_program_data(),
+                replayer.get_accounts(),
+            )
+        }
+    } else {
+        let state_file_path = io::local_file_path(base_path_or_url, &state_file_relative_paths);
+        verify::verify_with_local_whirlpool_state_file(
+            &state_file_path,
+            replayer.get_slot(),
+            replayer.get_program_data(),
+            replayer.get_accounts(),
+        )
+    }
+}
+
+// scan the transactions to be replayed (remote files are read twice if no cache is used)
+fn expand_filter_by_two_hop_swaps(
+    base_path_or_url: &String,
+    cache_dir: &Option<String>,
+    from_yyyymmdd: &String,
+    to_yyyymmdd: &String,
+    filter: &mut WhirlpoolFilter,
+) -> Result<(), ReplayError> {
+    let from = parse_date(from_yyyymmdd)?;
+    let to = parse_date(to_yyyymmdd)?;
+
+    // all days are scanned at once because pools can be connected through two-hop swaps on different days
+    let transaction_iter = from.iter_days().take_while(|date| *date <= to).flat_map(|date| {
+        match load_transaction_file(base_path_or_url, cache_dir, &date) {
+            Ok(iter) => iter,
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    });
+    filter.expand_by_two_hop_swaps(transaction_iter)
+}
+
+fn load_transaction_file(
+    base_path_or_url: &String,
+    cache_dir: &Option<String>,
+    date: &chrono::NaiveDate,
+) -> Result<Box<dyn Iterator<Item = Result<schema::WhirlpoolTransaction, ReplayError>>>, ReplayError> {
+    let transaction_file_relative_paths = io::get_whirlpool_transaction_file_relative_paths(date);
+    if base_path_or_url.starts_with("https://") {
+        if let Some(cache_dir) = cache_dir {
+            let transaction_file_path = io::cached_file_path(base_path_or_url, cache_dir, &transaction_file_relative_paths, false)?;
+            Ok(Box::new(io::load_from_local_whirlpool_transaction_file(&transaction_file_path)?))
+        } else {
+            let transaction_file_url = io::remote_file_url(base_path_or_url, &transaction_file_relative_paths)?;
+            Ok(Box::new(io::load_from_remote_whirlpool_transaction_file(&transaction_file_url)?))
+        }
+    } else {
+        let transaction_file_path = io::local_file_path(base_path_or_url, &transaction_file_relative_paths);
+        Ok(Box::new(io::load_from_local_whirlpool_transaction_file(&transaction_file_path)?))
+    }
+}
+
+struct Storage {
+    base_path_or_url: String,
+    cache_dir: Option<String>,
+    account_data_store_config: serde::AccountDataStoreConfig,
+}
+
+struct DayResult {
+    slot: u64,
+    instruction_counter: HashMap<String, u64>,
+    report: Option<verify::StateVerificationReport>,
+    token_transfer_mismatches: Vec<TokenTransferMismatchReport>,
+}
+
+// each day starts from the state of the previous day, so days can be replayed independently
+fn replay_days_in_parallel(
+    storage: &Storage,
+    from_yyyymmdd: &String,
+    to_yyyymmdd: &String,
+    threads: usize,
+    until_condition: &ReplayUntil,
+    strict: bool,
+    verify: bool,
+) {
+    let mut failed = false;
+
+    unwrap_or_exit(parallel::replay_days_in_parallel(
+        from_yyyymmdd,
+        to_yyyymmdd,
+        threads,
+        |yyyymmdd| {
+            let mut replayer = build_day_replayer(&storage.base_path_or_url, &storage.cache_dir, yyyymmdd, &storage.account_data_store_config)?;
+            replayer.set_token_transfer_verification(strict);
+
+            // output is collected per day (printing here would interleave days)
+            let instruction_counter = Rc::new(RefCell::new(HashMap::<String, u64>::new()));
+            let instruction_counter_clone = Rc::clone(&instruction_counter);
+            let instruction_callback: SyncInstructionCallback = Rc::new(
+                move |_slot, _transaction, name, _instruction, _accounts, _snapshot, _execution_record| {
+                    let mut counter = instruction_counter_clone.borrow_mut();
+                    let count = counter.entry(name.clone()).or_insert(0u64);
+                    *count += 1;
+                },
+            );
+
+            replayer.replay(until_condition.clone(), Some(instruction_callback), None, None)?;
+
+            let report = if verify {
+                Some(verify_replayed_state(&storage.base_path_or_url, &storage.cache_dir, yyyymmdd, &replayer)?)
+            } else {
+                None
+            };
+
+            let instruction_counter = instruction_counter.borrow().clone();
+            Ok(DayResult {
+                slot: replayer.get_slot().slot,
+                instruction_counter,
+                report,
+                token_transfer_mismatches: replayer.take_token_transfer_mismatches(),
+            })
+        },
+        |yyyymmdd, result| {
+            match result {
+                Ok(day) => {
+                    println!("\n\n{}: replayed until slot {}\n", yyyymmdd, day.slot);
+                    for (ix, count) in day.instruction_counter.iter().sorted() {
+                        println!("  {:8} : {}", count, ix);
+                    }
+                    if let Some(report) = day.report {
+                        println!("\n{}", report);
+                        failed |= !report.is_ok();
+                    }
+                    for mismatch in day.token_transfer_mismatches.iter() {
+                        eprintln!("token transfer mismatch: {}", mismatch);
+                    }
+                    failed |= !day.token_transfer_mismatches.is_empty();
+                }
+                Err(err) => {
+                    eprintln!("\n\n{}: replay failed: {}", yyyymmdd, err);
+                    failed = true;
+                }
+            }
+        },
+    ));
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn replay_single_instruction(args: &InstructionArgs) {
+    let state = unwrap_or_exit(io::load_from_local_whirlpool_state_file(&args.state, &serde::AccountDataStoreConfig::OnMemory));
+    let program_data = match args.program.as_ref() {
+        Some(program) => unwrap_or_exit(std::fs::read(program).map_err(ReplayError::from)),
+        None => state.program_data,
+    };
+    let slot = Slot::new(
+        args.slot.unwrap_or(state.slot),
+        args.block_height.unwrap_or(state.block_height),
+        args.block_time.unwrap_or(state.block_time),
+    );
+
+    println!("slot: {} (blockHeight={}, blockTime={})", slot.slot, slot.block_height, slot.block_time);
+    println!("instruction: {}", args.name);
+
+    let outcome = unwrap_or_exit(repro::replay_single_instruction(slot, program_data, state.accounts, &args.name, &args.payload));
+
+    if let Some(execution_record) = outcome.execution_record.as_ref() {
+        println!("\nprogram logs:");
+        for log_message in execution_record.log_messages.iter() {
+            println!("  {}", log_message);
+        }
+        println!("compute units: {}", execution_record.compute_units_consumed);
+        for event in execution_record.events.iter() {
+            println!("event: {:?}", event);
+        }
+    }
+
+    match outcome.result {
+        Ok(result) => {
+            println!("\naccount diff (pre -> post):");
+            print!("{}", diff::format_account_diffs(&diff::diff_snapshot(&result.snapshot)));
+        }
+        Err(err) => {
+            eprintln!("\n\nreplay failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// exit with 1 if the states are different (like diff command)
+fn diff_states(args: &DiffArgs) {
+    let account_data_store_config = if args.memory {
+        serde::AccountDataStoreConfig::OnMemory
+    } else {
+        serde::AccountDataStoreConfig::OnDisk(None)
+    };
+
+    let left = unwrap_or_exit(state_diff::StateSource::from_path(&args.left).load(&account_data_store_config));
+    let right = unwrap_or_exit(state_diff::StateSource::from_path(&args.right).load(&account_data_store_config));
+
+    let summary = if args.json {
+        let mut report = unwrap_or_exit(state_diff::build_state_diff_report(&left, &right));
+        if args.summary_only {
+            report.accounts.clear();
+        }
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        report.summary
+    } else {
+        println!("--- {}", args.left);
+        println!("+++ {}", args.right);
+        let summary = unwrap_or_exit(state_diff::diff_account_stores(&left, &right, |account_diff| {
+            if !args.summary_only {
+                print!("{}", account_diff);
+            }
+            Ok(())
+        }));
+        println!("\n{}", summary);
+        summary
+    };
+
+    if !summary.is_identical() {
+        std::process::exit(1);
+    }
+}
+
+// exit with 1 if any snapshot doesn't match
+fn verify_reference_snapshots(args: &VerifySnapshotArgs) {
+    let account_data_store_config = if args.memory {
+        serde::AccountDataStoreConfig::OnMemory
+    } else {
+        serde::AccountDataStoreConfig::OnDisk(None)
+    };
+
+    let mut failed = false;
+    let mut report_lines = vec![];
+    for snapshot in args.snapshots.iter() {
+        let report = unwrap_or_exit(verify_reference_snapshot(args, snapshot, &account_data_store_config));
+        println!("\n\n{}", report);
+        failed |= !report.is_ok();
+        report_lines.push(reference_snapshot::format_report_line(&report));
+    }
+
+    println!("\n\nReference snapshots\n");
+    for (snapshot, report_line) in args.snapshots.iter().zip(report_lines.iter()) {
+        println!("  {} : {}", report_line, snapshot);
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn verify_reference_snapshot(
+    args: &VerifySnapshotArgs,
+    snapshot: &String,
+    account_data_store_config: &serde::AccountDataStoreConfig,
+) -> Result<reference_snapshot::ReferenceSnapshotReport, ReplayError> {
+    let snapshot_slot = reference_snapshot::parse_snapshot_slot(snapshot).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("no slot in the snapshot file name: {}", snapshot))
+    })?;
+
+    let date = match args.date.as_ref() {
+        Some(yyyymmdd) => parse_date(yyyymmdd)?,
+        None => reference_snapshot::find_day_containing_slot(snapshot_slot, |date| load_state_slot(&args.storage, date))?,
+    };
+    let yyyymmdd = date.format("%Y%m%d").to_string();
+    println!("replay {} until slot {} ({})", yyyymmdd, snapshot_slot, snapshot);
+
+    let mut replayer = build_day_replayer(&args.storage, &args.cache_dir, &yyyymmdd, account_data_store_config)?;
+    replayer.replay(ReplayUntil::Slot(snapshot_slot), None, None, None)?;
+
+    reference_snapshot::verify_with_reference_snapshot_file(
+        snapshot,
+        snapshot_slot,
+        &date,
+        replayer.get_slot(),
+        replayer.get_accounts(),
+        account_data_store_config,
+    )
+}
+
+// only the beginning of the state file is read (no cache is used)
+fn load_state_slot(base_path_or_url: &String, date: &chrono::NaiveDate) -> Result<Slot, ReplayError> {
+    let state_file_relative_paths = io::get_whirlpool_state_file_relative_paths(date);
+    if base_path_or_url.starts_with("https://") {
+        let state_file_url = io::remote_file_url(base_path_or_url, &state_file_relative_paths)?;
+        io::load_slot_from_remote_whirlpool_state_file(&state_file_url)
+    } else {
+        let state_file_path = io::local_file_path(base_path_or_url, &state_file_relative_paths);
+        io::load_slot_from_local_whirlpool_state_file(&state_file_path)
+    }
+}
+
+fn unwrap_or_exit<T>(result: Result<T, ReplayError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("\n\nreplay failed: {}", err);
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,80 @@
+use clap::{Parser, Subcommand};
+
+use whirlpool_replayer::{diff, io, repro, ExecutionRecord, ReplayError};
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // re-execute the failed instruction in the repro bundle with full logs
+    Bundle {
+        #[clap(id = "bundle filename")]
+        bundle: String,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+
+    match args.command {
+        Command::Bundle { bundle } => unwrap_or_exit(replay_bundle(&bundle)),
+    }
+}
+
+fn replay_bundle(file_path: &String) -> Result<(), ReplayError> {
+    let bundle = io::load_from_local_whirlpool_repro_bundle_file(file_path)?;
+
+    println!("slot: {} (blockHeight={}, blockTime={})", bundle.slot, bundle.block_height, bundle.block_time);
+    println!("signature: {} (instruction index={})", bundle.signature, bundle.instruction_index);
+    println!("instruction: {}", bundle.instruction.name);
+    println!("  payload: {}", bundle.instruction.payload);
+    println!("program data hash: {}", bundle.program_data_hash);
+    println!("accounts: {} (hash={})", bundle.accounts.len(), bundle.accounts_hash);
+    println!("original error: {}", bundle.error);
+
+    let outcome = repro::replay_repro_bundle(&bundle)?;
+
+    if let Some(execution_record) = outcome.execution_record.as_ref() {
+        print_execution_record(execution_record);
+    }
+
+    match outcome.result {
+        Ok(result) => {
+            println!("\nresult: success (not reproduced)");
+            print!("{}", diff::format_account_diffs(&diff::diff_snapshot(&result.snapshot)));
+        }
+        Err(err) => {
+            println!("\nresult: {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_execution_record(execution_record: &ExecutionRecord) {
+    println!("\nprogram logs:");
+    for log_message in execution_record.log_messages.iter() {
+        println!("  {}", log_message);
+    }
+    println!("compute units: {}", execution_record.compute_units_consumed);
+    if !execution_record.inner_instructions.is_empty() {
+        println!("inner instructions:");
+        for inner in execution_record.inner_instructions.iter() {
+            println!("  [{}] {} ({} accounts, {} bytes)", inner.stack_height, inner.program_id, inner.accounts.len(), inner.data.len());
+        }
+    }
+}
+
+fn unwrap_or_exit<T>(result: Result<T, ReplayError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("\n\nrepro failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
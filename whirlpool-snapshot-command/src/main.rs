@@ -0,0 +1,107 @@
+use clap::{Parser, Subcommand};
+
+use whirlpool_replayer::{convert, serde::AccountDataStoreConfig, ReplayError, Slot};
+
+#[derive(Parser, Debug)]
+struct Args {
+    // load state files on memory (on-disk store in the system temporary directory by default)
+    #[clap(short, long, global = true)]
+    memory: bool,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // snapshot CSV file (compact or full-columns) to state file
+    Csv2state {
+        #[clap(long)]
+        slot: u64,
+
+        #[clap(long)]
+        block_height: u64,
+
+        #[clap(long)]
+        block_time: i64,
+
+        #[clap(id = "snapshot csv")]
+        csv: String,
+
+        #[clap(id = "program so")]
+        program: String,
+
+        #[clap(id = "output state")]
+        output: String,
+    },
+    // state file to snapshot CSV file (sorted by pubkey)
+    State2csv {
+        #[clap(id = "state")]
+        state: String,
+
+        #[clap(id = "output csv")]
+        output: String,
+    },
+    // replace the program data of the state file (the state file is overwritten if no output is given)
+    RewriteProgramData {
+        #[clap(id = "state")]
+        state: String,
+
+        #[clap(id = "program so")]
+        program: String,
+
+        #[clap(id = "output state")]
+        output: Option<String>,
+    },
+    // full-columns snapshot CSV file to compact snapshot CSV file (pubkey,data)
+    Compact {
+        #[clap(id = "full-columns csv")]
+        csv: String,
+
+        #[clap(id = "output csv")]
+        output: String,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let account_data_store_config = if args.memory {
+        AccountDataStoreConfig::OnMemory
+    } else {
+        AccountDataStoreConfig::OnDisk(None)
+    };
+
+    match args.command {
+        Command::Csv2state { slot, block_height, block_time, csv, program, output } => {
+            let program_data = unwrap_or_exit(std::fs::read(&program).map_err(ReplayError::from));
+            let slot = Slot::new(slot, block_height, block_time);
+            let accounts = unwrap_or_exit(convert::convert_snapshot_csv_to_state_file(&csv, &slot, &program_data, &output));
+            println!("{}: {} accounts (slot={})", output, accounts, slot.slot);
+        }
+        Command::State2csv { state, output } => {
+            let accounts = unwrap_or_exit(convert::convert_state_file_to_snapshot_csv(&state, &output, &account_data_store_config));
+            println!("{}: {} accounts", output, accounts);
+        }
+        Command::RewriteProgramData { state, program, output } => {
+            let program_data = unwrap_or_exit(std::fs::read(&program).map_err(ReplayError::from));
+            let output = output.unwrap_or(state.clone());
+            unwrap_or_exit(convert::replace_program_data_in_state_file(&state, &program_data, &output, &account_data_store_config));
+            println!("{}: program data replaced with {}", output, program);
+        }
+        Command::Compact { csv, output } => {
+            let accounts = unwrap_or_exit(convert::convert_full_column_snapshot_csv_to_compact(&csv, &output));
+            println!("{}: {} accounts", output, accounts);
+        }
+    }
+}
+
+fn unwrap_or_exit<T>(result: Result<T, ReplayError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("conversion failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}